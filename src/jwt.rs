@@ -1,266 +1,6366 @@
-use std::{marker::PhantomData, time::Duration};
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::BTreeMap,
+    fmt,
+    hash::{Hash, Hasher},
+    io::BufRead,
+    marker::PhantomData,
+    sync::Arc,
+    time::Duration,
+};
 
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{
+    errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
+};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
 use thiserror::Error as ErrorTrait;
-use time::OffsetDateTime;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use zeroize::{Zeroize, Zeroizing};
+
+mod audience;
+#[cfg(feature = "axum")]
+mod axum;
+mod builder;
+mod clock;
+mod compression;
+#[cfg(feature = "cookie")]
+pub mod cookie;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "jwe")]
+pub mod jwe;
+#[cfg(feature = "jwks")]
+pub mod jwks;
+mod metrics;
+mod payload;
+mod raw;
+#[cfg(feature = "redis")]
+mod redis;
+pub mod serde_seconds;
+mod store;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+#[cfg(feature = "x5c-verify")]
+pub mod x5c;
+
+#[cfg(feature = "redis")]
+pub use self::redis::RedisTokenStore;
+pub use audience::{AudienceMatch, AudienceSet, RuntimeAudiences};
+pub use builder::ClaimsBuilder;
+#[cfg(feature = "test-clock")]
+pub use clock::{clear_mock_now, set_mock_now};
+pub use metrics::{set_metrics, Metrics};
+pub use raw::RawSubject;
+pub use store::{MemoryTokenStore, TokenStore};
 
 #[derive(Debug, ErrorTrait)]
 pub enum Error {
     #[error(transparent)]
-    Jsonwebtoken(#[from] jsonwebtoken::errors::Error),
+    Jsonwebtoken(jsonwebtoken::errors::Error),
+    #[error("token expired")]
+    Expired,
+    #[error("token not valid for another {}s", valid_in.as_secs())]
+    NotYetValid { valid_in: Duration },
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("invalid compressed payload: {0}")]
+    Compression(std::io::Error),
+    #[error("failed to read token: {0}")]
+    Read(std::io::Error),
+    #[error("malformed {segment} segment: {reason}")]
+    Malformed { segment: Segment, reason: String },
+    #[error("token doesn't start with `{0}`")]
+    MissingPrefix(String),
+    #[error("missing query parameter `{0}`")]
+    MissingQueryParam(String),
+    #[error("token of {len} bytes exceeds the maximum of {max} bytes")]
+    TokenTooLong { len: usize, max: usize },
+    #[error("token issued {age}s ago exceeds the maximum age of {max_age}s")]
+    TooOld { age: u64, max_age: u64 },
+    #[error("token signed with secret version {version}, below the minimum of {min}")]
+    OutdatedSecret { version: u32, min: u32 },
+    #[error("inconsistent timestamps: {0}")]
+    InconsistentTimestamps(&'static str),
+    #[error("tokens would expire as soon as they're issued")]
+    ZeroDuration,
+    #[error("Subject field `{0}` collides with a registered claim")]
+    ReservedClaimCollision(&'static str),
+    #[error("`{claim}` of {value} isn't a valid NumericDate")]
+    InvalidNumericDate { claim: &'static str, value: i64 },
+    #[error("token with nonce `{0}` was already used")]
+    Replayed(String),
+    #[error("token with jti `{0}` was revoked")]
+    Revoked(String),
+    #[error("invalid delegation chain: {0}")]
+    InvalidChain(&'static str),
+    #[error("token bound to another client")]
+    FingerprintMismatch,
+    #[error("unsecured token with algorithm `none`")]
+    AlgorithmNone,
+    #[error("secret of {len} bytes is shorter than the minimum of {min} bytes")]
+    WeakSecret { len: usize, min: usize },
+    #[error("subject rejected: {0}")]
+    SubjectRejected(String),
+    #[error("expected a token of type `{expected}`, found {found:?}")]
+    UnexpectedTokenType {
+        expected: &'static str,
+        found: Option<String>,
+    },
+    #[cfg(feature = "cookie")]
+    #[error("missing cookie `{0}`")]
+    MissingCookie(String),
+    #[cfg(feature = "cookie")]
+    #[error("cookie of {len} bytes exceeds the maximum of {max} bytes")]
+    TokenTooLargeForCookie { len: usize, max: usize },
+    #[cfg(feature = "http")]
+    #[error("invalid authorization header: {0}")]
+    InvalidHeader(String),
+    #[cfg(feature = "http")]
+    #[error("expected content type `application/jwt`, found `{0}`")]
+    UnexpectedContentType(String),
+    #[cfg(feature = "jwe")]
+    #[error("invalid encrypted token: {0}")]
+    Jwe(&'static str),
+    #[cfg(feature = "x5c-verify")]
+    #[error("invalid `x5c` certificate chain: {0}")]
+    Certificate(&'static str),
+    #[cfg(feature = "jwks")]
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[cfg(feature = "jwks")]
+    #[error("token header has no `kid`")]
+    MissingKeyId,
+    #[cfg(feature = "jwks")]
+    #[error("no key with id `{0}` in the key set")]
+    UnknownKeyId(String),
+    #[cfg(feature = "yaml")]
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
 }
 pub(crate) type Result<T> = std::result::Result<T, Error>;
 
-pub type ClaimsEncoded<T> = Claims<T, Encoded>;
-pub type ClaimsDecoded<T> = Claims<T, Decoded<T>>;
+impl Error {
+    /// A short, stable code identifying the kind of error
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Jsonwebtoken(err) => match err.kind() {
+                ErrorKind::ExpiredSignature => "expired",
+                ErrorKind::ImmatureSignature => "not_yet_valid",
+                ErrorKind::InvalidSignature => "invalid_signature",
+                ErrorKind::InvalidAlgorithm
+                | ErrorKind::InvalidAlgorithmName
+                | ErrorKind::MissingAlgorithm => "invalid_algorithm",
+                ErrorKind::InvalidIssuer => "invalid_issuer",
+                ErrorKind::InvalidAudience => "invalid_audience",
+                ErrorKind::InvalidSubject => "invalid_subject",
+                ErrorKind::MissingRequiredClaim(_) => "missing_claim",
+                ErrorKind::InvalidToken
+                | ErrorKind::Base64(_)
+                | ErrorKind::Json(_)
+                | ErrorKind::Utf8(_) => "malformed",
+                ErrorKind::InvalidEcdsaKey
+                | ErrorKind::InvalidRsaKey(_)
+                | ErrorKind::RsaFailedSigning
+                | ErrorKind::InvalidKeyFormat
+                | ErrorKind::Crypto(_) => "internal",
+                _ => "invalid_token",
+            },
+            Error::Json(_)
+            | Error::Compression(_)
+            | Error::Malformed { .. }
+            | Error::MissingPrefix(_)
+            | Error::TokenTooLong { .. } => "malformed",
+            Error::Read(_) => "internal",
+            Error::Expired | Error::TooOld { .. } => "expired",
+            Error::NotYetValid { .. } => "not_yet_valid",
+            Error::OutdatedSecret { .. } => "invalid_token",
+            Error::AlgorithmNone => "invalid_algorithm",
+            Error::WeakSecret { .. } | Error::ZeroDuration | Error::ReservedClaimCollision(_) => {
+                "internal"
+            }
+            Error::Replayed(_)
+            | Error::Revoked(_)
+            | Error::FingerprintMismatch
+            | Error::InvalidChain(_)
+            | Error::InconsistentTimestamps(_)
+            | Error::InvalidNumericDate { .. } => "invalid_token",
+            Error::SubjectRejected(_) => "invalid_subject",
+            Error::UnexpectedTokenType { .. } => "invalid_token",
+            Error::MissingQueryParam(_) => "missing_token",
+            #[cfg(feature = "cookie")]
+            Error::MissingCookie(_) => "missing_token",
+            #[cfg(feature = "cookie")]
+            Error::TokenTooLargeForCookie { .. } => "internal",
+            #[cfg(feature = "http")]
+            Error::InvalidHeader(_) | Error::UnexpectedContentType(_) => "malformed",
+            #[cfg(feature = "jwe")]
+            Error::Jwe(_) => "invalid_token",
+            #[cfg(feature = "x5c-verify")]
+            Error::Certificate(_) => "invalid_token",
+            #[cfg(feature = "jwks")]
+            Error::Http(_) => "internal",
+            #[cfg(feature = "jwks")]
+            Error::MissingKeyId | Error::UnknownKeyId(_) => "invalid_token",
+            #[cfg(feature = "yaml")]
+            Error::Yaml(_) => "internal",
+        }
+    }
+
+    /// How long until a token that isn't valid yet becomes valid, e.g. for a
+    /// `Retry-After` header. `None` for any other error.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::NotYetValid { valid_in } => Some(*valid_in),
+            _ => None,
+        }
+    }
+
+    /// The step of decoding a token that failed, e.g. for a precise 4xx
+    /// response. `None` for errors past the validation of the token, like a
+    /// revoked one, and those of issuing tokens.
+    pub fn step(&self) -> Option<ValidationStep> {
+        let claim_step = |claim: &str| match claim {
+            "exp" => Some(ValidationStep::Expiry),
+            "nbf" => Some(ValidationStep::NotBefore),
+            "iss" => Some(ValidationStep::Issuer),
+            "aud" => Some(ValidationStep::Audience),
+            _ => None,
+        };
+
+        match self {
+            Error::Jsonwebtoken(err) => match err.kind() {
+                ErrorKind::InvalidToken | ErrorKind::Base64(_) | ErrorKind::Utf8(_) => {
+                    Some(ValidationStep::Format)
+                }
+                ErrorKind::InvalidSignature
+                | ErrorKind::InvalidAlgorithm
+                | ErrorKind::InvalidAlgorithmName
+                | ErrorKind::MissingAlgorithm => Some(ValidationStep::Signature),
+                ErrorKind::ExpiredSignature => Some(ValidationStep::Expiry),
+                ErrorKind::ImmatureSignature => Some(ValidationStep::NotBefore),
+                ErrorKind::InvalidIssuer => Some(ValidationStep::Issuer),
+                ErrorKind::InvalidAudience => Some(ValidationStep::Audience),
+                ErrorKind::MissingRequiredClaim(claim) => claim_step(claim),
+                ErrorKind::Json(_) => Some(ValidationStep::Deserialize),
+                _ => None,
+            },
+            Error::Malformed { .. }
+            | Error::MissingPrefix(_)
+            | Error::TokenTooLong { .. }
+            | Error::Compression(_)
+            | Error::UnexpectedTokenType { .. } => Some(ValidationStep::Format),
+            Error::AlgorithmNone => Some(ValidationStep::Signature),
+            Error::Expired => Some(ValidationStep::Expiry),
+            Error::NotYetValid { .. } => Some(ValidationStep::NotBefore),
+            Error::InvalidNumericDate { claim, .. } => claim_step(claim),
+            Error::Json(_) => Some(ValidationStep::Deserialize),
+            _ => None,
+        }
+    }
+
+    /// The body of an error response in `format` and its MIME type, only
+    /// holding the [`Error::code`]
+    pub fn error_body(&self, format: ErrorFormat) -> (String, &'static str) {
+        match format {
+            ErrorFormat::Json => (
+                serde_json::json!({ "error": self.code() }).to_string(),
+                "application/json",
+            ),
+            ErrorFormat::PlainText => (self.code().to_string(), "text/plain; charset=utf-8"),
+        }
+    }
+}
+
+/// The kind of key an [`Algorithm`] signs with, see
+/// [`ClaimsSubTrait::algorithm_family`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlgorithmFamily {
+    /// `HS256`, `HS384` and `HS512`
+    Hmac,
+    /// `RS256`, `RS384`, `RS512`, `PS256`, `PS384` and `PS512`
+    Rsa,
+    /// `ES256` and `ES384`
+    Ec,
+    /// `EdDSA`
+    Ed,
+}
+
+impl AlgorithmFamily {
+    pub fn of(alg: Algorithm) -> Self {
+        match alg {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => Self::Hmac,
+            Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::PS256
+            | Algorithm::PS384
+            | Algorithm::PS512 => Self::Rsa,
+            Algorithm::ES256 | Algorithm::ES384 => Self::Ec,
+            Algorithm::EdDSA => Self::Ed,
+        }
+    }
+
+    /// Whether `alg` is of this family
+    pub fn contains(self, alg: Algorithm) -> bool {
+        Self::of(alg) == self
+    }
+}
+
+/// A coarse estimate of the work to verify a token, see
+/// [`Claims::decode_cost_hint`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DecodeCost {
+    /// A MAC, e.g. `HS256`, or a token rejected before verifying anything
+    Cheap,
+    /// A public key signature, e.g. `RS256` or `ES256`
+    Expensive,
+}
+
+/// The format of an error response, see [`Error::error_body`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorFormat {
+    #[default]
+    Json,
+    PlainText,
+}
+
+impl ErrorFormat {
+    /// The first of JSON or plain text the `Accept` header value `accept`
+    /// lists, JSON if neither.
+    pub fn from_accept(accept: &str) -> Self {
+        accept
+            .split(',')
+            .map(|range| range.split(';').next().unwrap_or_default().trim())
+            .find_map(|media_type| match media_type {
+                "application/json" => Some(ErrorFormat::Json),
+                "text/plain" => Some(ErrorFormat::PlainText),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Normalize an error of jsonwebtoken into the semantic [`Error`] this crate
+/// reports, as done for all errors raised while decoding.
+pub fn map_jwt_error(err: jsonwebtoken::errors::Error) -> Error {
+    match err.kind() {
+        ErrorKind::ExpiredSignature => Error::Expired,
+        _ => Error::Jsonwebtoken(err),
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for Error {
+    fn from(err: jsonwebtoken::errors::Error) -> Self {
+        map_jwt_error(err)
+    }
+}
+
+/// Malformed tokens are `InvalidData`, rejected ones `PermissionDenied`
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        let kind = match err.code() {
+            "malformed" => std::io::ErrorKind::InvalidData,
+            "internal" => std::io::ErrorKind::Other,
+            _ => std::io::ErrorKind::PermissionDenied,
+        };
+
+        match err {
+            Error::Read(err) => err,
+            err => std::io::Error::new(kind, err),
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        map_jwt_error(kind.into())
+    }
+}
+
+/// A segment of a compact token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    Header,
+    Payload,
+    Signature,
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Segment::Header => "header",
+            Segment::Payload => "payload",
+            Segment::Signature => "signature",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The step of decoding a token that failed, see [`Error::step`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationStep {
+    /// The token isn't a well-formed JWT
+    Format,
+    /// The signature or its algorithm
+    Signature,
+    /// The `exp` claim
+    Expiry,
+    /// The `nbf` claim, or the `iat` claim with [`ClaimsSubTrait::NBF_LEEWAY`]
+    NotBefore,
+    /// The `iss` claim
+    Issuer,
+    /// The `aud` claim
+    Audience,
+    /// The payload doesn't deserialize to the claims of the Subject
+    Deserialize,
+}
+
+/// The length in bytes of each segment of a token, see [`Claims::size_breakdown`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeBreakdown {
+    pub header: usize,
+    pub payload: usize,
+    pub signature: usize,
+}
+
+/// The validation that was applied to a decoded token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// The leeway in seconds granted on `exp`
+    pub leeway_used: u64,
+    /// The algorithms the token may have been signed with
+    pub algorithms: Vec<Algorithm>,
+    pub checked_exp: bool,
+    pub checked_nbf: bool,
+}
+
+/// The audiences tokens for `T` are accepted for, the runtime ones if any
+fn accepted_audiences<T: ClaimsSubTrait>() -> AudienceSet {
+    let audiences = T::audiences_runtime();
+    if audiences.is_empty() {
+        return T::audiences();
+    }
+
+    audiences.into_iter().collect()
+}
+
+/// Split a compact token into its header, payload and signature segments,
+/// checking each is valid base64url.
+fn segments(token: &str) -> Result<[&str; 3]> {
+    let malformed = |segment, reason: &str| Error::Malformed {
+        segment,
+        reason: reason.to_string(),
+    };
+
+    let mut parts = token.splitn(4, '.');
+    let mut segments = [""; 3];
+
+    for (segment, part) in [Segment::Header, Segment::Payload, Segment::Signature]
+        .into_iter()
+        .zip(segments.iter_mut())
+    {
+        *part = parts
+            .next()
+            .ok_or_else(|| malformed(segment, "missing segment"))?;
+
+        // An unsecured token has an empty signature, which is rejected during verification
+        if part.is_empty() && segment != Segment::Signature {
+            return Err(malformed(segment, "empty segment"));
+        }
+        if part.bytes().any(|byte| byte.is_ascii_whitespace()) {
+            return Err(malformed(segment, "contains whitespace"));
+        }
+        if let Err(err) = base64::decode_config(*part, base64::URL_SAFE_NO_PAD) {
+            return Err(malformed(segment, &err.to_string()));
+        }
+    }
+
+    if parts.next().is_some() {
+        return Err(malformed(Segment::Signature, "unexpected trailing segment"));
+    }
+
+    Ok(segments)
+}
+
+/// `header` as JSON with the `extra` parameters it doesn't set itself
+fn header_json(header: &Header, extra: &BTreeMap<String, Value>) -> Result<Map<String, Value>> {
+    let mut header_json = match serde_json::to_value(header)? {
+        Value::Object(header_json) => header_json,
+        _ => unreachable!("a header serializes to a map"),
+    };
+    for (name, value) in extra {
+        header_json
+            .entry(name.as_str())
+            .or_insert_with(|| value.clone());
+    }
+
+    Ok(header_json)
+}
+
+/// `token` without surrounding ASCII whitespace, which never is part of a token,
+/// nor a pair of surrounding double quotes with [`ClaimsSubTrait::TOLERANT_QUOTES`]
+fn trim_token<T: ClaimsSubTrait>(token: &str) -> &str {
+    let token = token.trim_matches(|c: char| c.is_ascii_whitespace());
+    if !T::TOLERANT_QUOTES {
+        return token;
+    }
+
+    token
+        .strip_prefix('"')
+        .and_then(|token| token.strip_suffix('"'))
+        .unwrap_or(token)
+}
+
+/// The last second of the year 9999, the latest timestamp `OffsetDateTime`
+/// represents
+const MAX_NUMERIC_DATE: i64 = 253_402_300_799;
+
+/// Reject timestamp claims before the unix epoch or beyond [`MAX_NUMERIC_DATE`],
+/// NumericDates per RFC 7519 are seconds since the epoch.
+fn check_numeric_dates<T: ClaimsSubTrait>(claims: &Decoded<T>) -> Result<()> {
+    let timestamps = [
+        ("exp", Some(claims.exp)),
+        ("iat", claims.iat),
+        ("nbf", claims.nbf),
+    ];
+
+    for (claim, value) in timestamps {
+        match value {
+            Some(value) if !(0..=MAX_NUMERIC_DATE).contains(&value) => {
+                return Err(Error::InvalidNumericDate { claim, value });
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The integer `exp` of the payload of `token`, which jsonwebtoken takes for a
+/// missing one when it's negative.
+fn numeric_exp(token: &str) -> Option<i64> {
+    let [_, payload, _] = segments(token).ok()?;
+    let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?;
+
+    serde_json::from_slice::<Value>(&payload)
+        .ok()?
+        .get("exp")?
+        .as_i64()
+}
+
+/// `token` with the claims renamed by [`ClaimsSubTrait::rename_registered_claims`]
+/// under their own names again, for jsonwebtoken to validate them. The
+/// signature is verified here, it doesn't cover the restored payload.
+fn restore_registered_names<T: ClaimsSubTrait>(
+    token: &str,
+    key: &DecodingKey,
+    validation: &Validation,
+) -> Result<String> {
+    let mut signature = validation.clone();
+    signature.required_spec_claims.clear();
+    signature.validate_exp = false;
+    signature.validate_nbf = false;
+    signature.iss = None;
+    signature.aud = None;
+    signature.sub = None;
+
+    let mut payload = jsonwebtoken::decode::<Map<String, Value>>(token, key, &signature)?.claims;
+    payload::restore_registered_names::<T>(&mut payload);
+
+    let [header, _, signature] = segments(token)?;
+    let payload = base64::encode_config(serde_json::to_vec(&payload)?, base64::URL_SAFE_NO_PAD);
+    Ok(format!("{}.{}.{}", header, payload, signature))
+}
+
+/// The length a compressed payload of a token for `T` may inflate to
+fn max_inflated_len<T: ClaimsSubTrait>() -> usize {
+    T::MAX_TOKEN_LEN.saturating_mul(compression::MAX_INFLATE_RATIO)
+}
+
+/// Calls [`ClaimsSubTrait::on_expired`] when `err` is an expiry
+fn notify_expired<T: ClaimsSubTrait>(err: Error) -> Error {
+    if matches!(err, Error::Expired) {
+        T::on_expired();
+    }
+    err
+}
+
+/// Whether the header of `token` names the `none` algorithm, which
+/// jsonwebtoken can't represent at all.
+fn is_unsecured(token: &str) -> bool {
+    let header = token.split('.').next().unwrap_or_default();
+
+    let alg = base64::decode_config(header, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|header| serde_json::from_slice::<serde_json::Value>(&header).ok())
+        .and_then(|header| header.get("alg")?.as_str().map(str::to_ascii_lowercase));
+
+    matches!(alg.as_deref(), Some("none"))
+}
+
+pub type ClaimsEncoded<T> = Claims<T, Encoded>;
+pub type ClaimsDecoded<T> = Claims<T, Decoded<T>>;
+
+pub trait ClaimsSubTrait: Serialize {
+    /// The time before expiry from now in seconds, unused when [`ClaimsSubTrait::duration`]
+    /// is overridden. No tokens are issued while it's zero, see [`Error::ZeroDuration`].
+    const DURATION: u64 = 0;
+
+    /// The time before expiry from now
+    fn duration() -> Duration {
+        Duration::from_secs(Self::DURATION)
+    }
+
+    /// See [`ClaimsSubTrait::ROTATION_GRACE`]
+    fn secret_rotation_window() -> Duration {
+        Duration::from_secs(Self::ROTATION_GRACE)
+    }
+
+    /// The algorithm used for signing the token
+    const ALGORITHM: Algorithm = Algorithm::HS256;
+
+    /// DEFLATE the payload before signing, marked with a `"zip": "DEF"` header
+    const COMPRESS: bool = false;
+
+    /// Embed the `iat` claim in issued tokens, leave it out to not reveal when
+    /// a token was issued. [`Claims::age`] is unknown without it, and
+    /// [`ClaimsSubTrait::MAX_AGE`] can't be checked.
+    const INCLUDE_IAT: bool = true;
+
+    /// The unix timestamp of the epoch `exp` and `iat` count from in tokens,
+    /// for systems that don't use the unix epoch
+    const EPOCH_OFFSET: i64 = 0;
+
+    /// Also accept `exp`, `iat` and `nbf` as RFC 3339 datetimes instead of
+    /// numeric timestamps, for (non-conformant) issuers that emit them.
+    const LENIENT_TIME_FORMAT: bool = false;
+
+    /// Leeway in seconds for `nbf` and `iat` lying in the future, replacing the
+    /// leeway of [`ClaimsSubTrait::validation`]. `exp` is then checked without
+    /// any leeway.
+    const NBF_LEEWAY: Option<u64> = None;
+
+    /// The `typ` header of issued tokens, required to match when decoding.
+    const EXPECTED_TYP: Option<&'static str> = None;
+
+    /// The `cty` header of issued tokens, e.g. `JWT` for a token nesting
+    /// another one. Read back from the header of [`Claims::decode_full`].
+    const CONTENT_TYPE: Option<&'static str> = None;
+
+    /// The maximum time in seconds since a token was issued, however long it's
+    /// valid for
+    const MAX_AGE: Option<u64> = None;
+
+    /// The version of the secret tokens are signed with, embedded as the `sv`
+    /// claim to tell which secret signed a token.
+    const SECRET_VERSION: u32 = 0;
+
+    /// Tokens signed with a secret version below this are rejected
+    const MIN_SECRET_VERSION: u32 = 0;
+
+    /// Seconds tokens signed with the previous secret version are accepted
+    /// for since they were issued, once the secret was rotated to
+    /// `SECRET_VERSION`. Tokens of older versions, or without an `iat`, are
+    /// rejected. Any version from `MIN_SECRET_VERSION` is accepted when zero.
+    const ROTATION_GRACE: u64 = 0;
+
+    /// Accept expired tokens when [`ClaimsSubTrait::validation`] turns off
+    /// `validate_exp`, which silently has no effect otherwise.
+    const ALLOW_EXPIRED: bool = false;
+
+    /// Whether a token has to name any or all of the
+    /// [`ClaimsSubTrait::audiences`] to be accepted
+    const AUDIENCE_MATCH: AudienceMatch = AudienceMatch::Any;
+
+    /// Accept tokens wrapped in a pair of double quotes, e.g. pasted from a
+    /// shell command quoting them. Off by default, the quotes are malformed input.
+    const TOLERANT_QUOTES: bool = false;
+
+    /// The maximum length of a token in bytes
+    const MAX_TOKEN_LEN: usize = 8 * 1024;
+
+    /// The value of the registered `sub` claim, independent of the Subject's own fields.
+    fn subject_id(&self) -> Option<String> {
+        None
+    }
+
+    /// The `jti` of the token a delegated token was issued from, referenced by
+    /// the Subject, see [`Claims::validate_chain`].
+    fn parent_jti(&self) -> Option<&str> {
+        None
+    }
+
+    /// The `nonce` claim of issued tokens, e.g. a random id for one-time
+    /// tokens checked with [`Claims::decode_once`].
+    fn generate_nonce() -> Option<String> {
+        None
+    }
+
+    /// The fingerprint of the client a token is issued to, e.g. a hash of its
+    /// IP address, embedded in the `cnf` claim and checked by [`Claims::decode_bound`].
+    fn fingerprint() -> Option<String> {
+        None
+    }
+
+    /// The `iss` claim of issued tokens, also required when decoding.
+    ///
+    /// Takes precedence over an issuer set in [`ClaimsSubTrait::validation`].
+    fn issuer() -> Option<String> {
+        None
+    }
+
+    /// The audiences a token is accepted for, it must name at least one of them
+    /// in its `aud` claim. Any audience is accepted when empty.
+    ///
+    /// Takes precedence over the audiences set in [`ClaimsSubTrait::validation`].
+    fn audiences() -> AudienceSet {
+        AudienceSet::new()
+    }
+
+    /// Audiences resolved at runtime, e.g. from [`RuntimeAudiences`] set at
+    /// startup. Issued tokens name all of them in their `aud` claim.
+    ///
+    /// Takes precedence over [`ClaimsSubTrait::audiences`] unless empty.
+    fn audiences_runtime() -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Header parameters of issued tokens that jsonwebtoken's [`Header`] has
+    /// no field for, e.g. `"x-env": "prod"`. They can't replace the
+    /// parameters set by this crate, like `alg`.
+    fn additional_headers() -> BTreeMap<String, Value> {
+        BTreeMap::new()
+    }
+
+    /// Registered claims kept under another name in the payload, as
+    /// `(claim, name)` pairs, e.g. `&[("exp", "expiry")]` for a partner
+    /// expecting that schema. They're validated as usual.
+    fn rename_registered_claims() -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+
+    /// The DER encoded certificate chain set as the `x5c` header of issued
+    /// tokens, leaf first. No `x5c` header is set when empty.
+    fn certificate_chain() -> Vec<Vec<u8>> {
+        Vec::new()
+    }
+
+    /// The algorithms a token may be signed with to be accepted, e.g. while
+    /// migrating from one algorithm to another.
+    fn accepted_algorithms() -> Vec<Algorithm> {
+        vec![Self::ALGORITHM]
+    }
+
+    /// The only family of algorithms a token may be signed with, whatever
+    /// the accepted algorithms of the validation. This rules out e.g. an
+    /// `HS256` token signed with the public key of an `RS256` Subject.
+    fn algorithm_family() -> AlgorithmFamily {
+        AlgorithmFamily::of(Self::ALGORITHM)
+    }
+
+    /// The validation applied when decoding a token for this Subject.
+    ///
+    /// Expiry is checked even if `validate_exp` is turned off here, unless
+    /// [`ClaimsSubTrait::ALLOW_EXPIRED`] is set as well.
+    fn validation() -> Validation {
+        let mut validation = Validation::new(Self::ALGORITHM);
+        validation.algorithms = Self::accepted_algorithms();
+        validation
+    }
+
+    /// Cheap checks run on a token before it's verified.
+    fn validate_token_format(token: &str) -> Result<()> {
+        if token.len() > Self::MAX_TOKEN_LEN {
+            return Err(Error::TokenTooLong {
+                len: token.len(),
+                max: Self::MAX_TOKEN_LEN,
+            });
+        }
+
+        segments(token).map(|_| ())
+    }
+
+    /// Checks on the Subject itself, run on decode and by [`Claims::try_sub`].
+    fn validate_sub(&self) -> std::result::Result<(), String> {
+        Ok(())
+    }
+
+    /// Checks on the Subject before a token is signed for it, failing with
+    /// [`Error::SubjectRejected`], e.g. so no token is issued for an empty id.
+    fn pre_sign_validate(&self) -> std::result::Result<(), String> {
+        Ok(())
+    }
+
+    /// Normalize the Subject before it's signed, e.g. lowercase an email, so the
+    /// payload of a token is always canonical.
+    fn canonicalize(self) -> Self
+    where
+        Self: Sized,
+    {
+        self
+    }
+
+    /// Wrap an issued token in an envelope, e.g. prefix it with a version tag.
+    ///
+    /// Applied whenever the token is handed out as a string, undone by
+    /// [`ClaimsSubTrait::pre_decode`].
+    fn post_encode(token: String) -> String {
+        token
+    }
+
+    /// Take a token out of the envelope of [`ClaimsSubTrait::post_encode`]
+    /// before anything else, borrowing it so borrowed tokens aren't copied.
+    fn pre_decode(token: &str) -> Cow<'_, str> {
+        Cow::Borrowed(token)
+    }
+
+    /// Called after every successful decode, e.g. to emit an audit record.
+    fn on_decode(_decoded: &Decoded<Self>)
+    where
+        Self: Sized,
+    {
+    }
+
+    /// Called when a decode fails because the token expired, and on no other
+    /// failure, e.g. to count the sessions that timed out.
+    fn on_expired() {}
+}
+
+/// Remembers the nonces of tokens that were used, see [`Claims::decode_once`]
+pub trait ReplayStore {
+    /// Record `nonce` as used, `false` if it already was
+    fn check_and_consume(&self, nonce: &str) -> bool;
+}
+
+/// A Subject tokens can be issued for.
+pub trait EncodingClaims: ClaimsSubTrait {
+    /// The key tokens are signed with
+    fn encoding_key() -> EncodingKey;
+
+    /// Checked before a token is signed, e.g. that the key isn't too weak
+    fn validate_encoding_key() -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A Subject tokens can be verified for.
+///
+/// A service that only verifies tokens needs nothing more than the key to
+/// verify them with, it can't issue tokens:
+///
+/// ```compile_fail
+/// # use jsonwebtoken::{Algorithm, DecodingKey};
+/// # use serde::{Deserialize, Serialize};
+/// # use typed_string_encoding::jwt::{Claims, ClaimsSubTrait, DecodingClaims};
+/// #[derive(Serialize, Deserialize)]
+/// struct VerifyOnly {
+///     id: String,
+/// }
+///
+/// impl ClaimsSubTrait for VerifyOnly {
+///     const ALGORITHM: Algorithm = Algorithm::RS256;
+/// }
+///
+/// impl DecodingClaims for VerifyOnly {
+///     fn decoding_key() -> DecodingKey {
+///         DecodingKey::from_secret(b"public_key")
+///     }
+/// }
+///
+/// Claims::new(VerifyOnly { id: "123".to_string() });
+/// ```
+pub trait DecodingClaims: ClaimsSubTrait {
+    /// The key the signature of tokens is verified with
+    fn decoding_key() -> DecodingKey;
+
+    /// The keys of earlier secret versions, by their
+    /// [`ClaimsSubTrait::SECRET_VERSION`], tokens are still verified with
+    /// while the secret is rotated. Tokens of any other version are verified
+    /// with `decoding_key`.
+    fn previous_decoding_keys() -> Vec<(u32, DecodingKey)> {
+        Vec::new()
+    }
+
+    /// Checked before a token is verified, e.g. that the keys aren't too weak
+    fn validate_decoding_key() -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A Subject signed and verified with the same secret, e.g. with `HS256`.
+pub trait SymmetricClaims: ClaimsSubTrait {
+    /// The minimum length in bytes of the secrets, a shorter one fails every
+    /// encode and decode with [`Error::WeakSecret`]. Defaults to the output
+    /// size of `HS256`.
+    const MIN_SECRET_BYTES: usize = 32;
+
+    /// The secret key used for encoding and decoding the Subject.
+    fn secret<'a>() -> &'a [u8];
+
+    /// The secret the keys are built from, zeroed as soon as they are.
+    ///
+    /// Override it to load the secret without it lingering in memory.
+    fn secret_guarded() -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(Self::secret().to_vec())
+    }
+
+    /// The secrets of earlier secret versions, see
+    /// [`DecodingClaims::previous_decoding_keys`]. Tokens are always signed
+    /// with `secret`, the newest one.
+    fn decoding_secrets() -> Vec<(u32, Zeroizing<Vec<u8>>)> {
+        Vec::new()
+    }
+}
+
+impl<T: SymmetricClaims> EncodingClaims for T {
+    fn encoding_key() -> EncodingKey {
+        guarded_key(T::secret_guarded(), EncodingKey::from_secret)
+    }
+
+    fn validate_encoding_key() -> Result<()> {
+        check_secret::<T>(T::secret().len())
+    }
+}
+
+impl<T: SymmetricClaims> DecodingClaims for T {
+    fn decoding_key() -> DecodingKey {
+        guarded_key(T::secret_guarded(), DecodingKey::from_secret)
+    }
+
+    fn previous_decoding_keys() -> Vec<(u32, DecodingKey)> {
+        T::decoding_secrets()
+            .into_iter()
+            .map(|(version, secret)| (version, guarded_key(secret, DecodingKey::from_secret)))
+            .collect()
+    }
+
+    fn validate_decoding_key() -> Result<()> {
+        check_secret::<T>(T::secret().len())?;
+        T::decoding_secrets()
+            .iter()
+            .try_for_each(|(_, secret)| check_secret::<T>(secret.len()))
+    }
+}
+
+/// Reject a secret of `len` bytes below [`SymmetricClaims::MIN_SECRET_BYTES`]
+fn check_secret<T: SymmetricClaims>(len: usize) -> Result<()> {
+    if len < T::MIN_SECRET_BYTES {
+        return Err(Error::WeakSecret {
+            len,
+            min: T::MIN_SECRET_BYTES,
+        });
+    }
+
+    Ok(())
+}
+
+/// Reject issuing tokens with a `ttl` of zero, which are expired on arrival
+/// unless [`ClaimsSubTrait::ALLOW_EXPIRED`] is set, e.g. from a forgotten
+/// [`ClaimsSubTrait::DURATION`].
+pub(crate) fn check_ttl<T: ClaimsSubTrait>(ttl: Duration) -> Result<()> {
+    if ttl.is_zero() && !T::ALLOW_EXPIRED {
+        return Err(Error::ZeroDuration);
+    }
+
+    Ok(())
+}
+
+/// `build` a key from `secret`, which is zeroed right after
+fn guarded_key<S, K>(secret: Zeroizing<S>, build: impl FnOnce(&[u8]) -> K) -> K
+where
+    S: Zeroize + AsRef<[u8]>,
+{
+    let key = build((*secret).as_ref());
+    drop(secret);
+    key
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Encoded(String);
+
+// Tokens are bearer credentials, don't leave them behind in memory
+#[cfg(feature = "zeroize")]
+impl Drop for Encoded {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for Encoded {}
+
+/// The payload of a token, (de)serialized with the Subject flattened into it
+#[derive(Debug)]
+pub struct Decoded<T: ClaimsSubTrait> {
+    pub(crate) sub: T,
+    subject_id: Option<String>,
+    iss: Option<String>,
+    nbf: Option<i64>,
+    exp: i64,
+    iat: Option<i64>,
+    sv: u32,
+    nonce: Option<String>,
+    aud: Vec<String>,
+    jti: Option<String>,
+    fingerprint: Option<String>,
+    scope: Option<String>,
+    // The token the claims were decoded from, when retained, see
+    // `VerifiedToken::into_claims`
+    token: Option<Encoded>,
+}
+
+// Claims are equal whether or not their token was retained
+impl<T: ClaimsSubTrait + PartialEq> PartialEq for Decoded<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.sub == other.sub
+            && self.subject_id == other.subject_id
+            && self.iss == other.iss
+            && self.nbf == other.nbf
+            && self.exp == other.exp
+            && self.iat == other.iat
+            && self.sv == other.sv
+            && self.nonce == other.nonce
+            && self.aud == other.aud
+            && self.jti == other.jti
+            && self.fingerprint == other.fingerprint
+            && self.scope == other.scope
+    }
+}
+
+impl<T: ClaimsSubTrait + Eq> Eq for Decoded<T> {}
+
+impl<T: ClaimsSubTrait + Default> Default for Decoded<T> {
+    fn default() -> Self {
+        Self {
+            sub: T::default(),
+            subject_id: None,
+            iss: None,
+            nbf: None,
+            exp: 0,
+            iat: Some(0),
+            sv: 0,
+            nonce: None,
+            aud: Vec::new(),
+            jti: None,
+            fingerprint: None,
+            scope: None,
+            token: None,
+        }
+    }
+}
+
+impl<T: ClaimsSubTrait> Decoded<T> {
+    /// The registered `sub` claim
+    pub fn subject_id(&self) -> Option<&str> {
+        self.subject_id.as_deref()
+    }
+
+    /// The registered `iss` claim
+    pub fn issuer(&self) -> Option<&str> {
+        self.iss.as_deref()
+    }
+
+    /// The `sv` claim, the version of the secret the token was signed with
+    pub fn secret_version(&self) -> u32 {
+        self.sv
+    }
+
+    /// The `nonce` claim, see [`Claims::decode_once`]
+    pub fn nonce(&self) -> Option<&str> {
+        self.nonce.as_deref()
+    }
+
+    /// The registered `aud` claim, empty without one
+    pub fn audience(&self) -> &[String] {
+        &self.aud
+    }
+
+    /// The registered `jti` claim
+    pub fn jti(&self) -> Option<&str> {
+        self.jti.as_deref()
+    }
+
+    /// The fingerprint in the `cnf` claim, see [`ClaimsSubTrait::fingerprint`]
+    pub fn fingerprint(&self) -> Option<&str> {
+        self.fingerprint.as_deref()
+    }
+}
+
+/// The Subject followed by the RFC 3339 issue and expiry times
+impl<T: ClaimsSubTrait + fmt::Display> fmt::Display for Decoded<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = |timestamp: i64| {
+            OffsetDateTime::from_unix_timestamp(timestamp)
+                .ok()
+                .and_then(|time| time.format(&Rfc3339).ok())
+                .unwrap_or_else(|| timestamp.to_string())
+        };
+
+        match self.iat {
+            Some(iat) => write!(
+                f,
+                "{} (issued {}, expires {})",
+                self.sub,
+                time(iat),
+                time(self.exp)
+            ),
+            None => write!(f, "{} (expires {})", self.sub, time(self.exp)),
+        }
+    }
+}
+
+/// Claims ordered by `exp`, then `iat`, e.g. to evict the claims expiring
+/// first from a `BinaryHeap<Reverse<ByExpiry<T>>>`. The Subject and the other
+/// claims are ignored, so claims expiring at the same time compare equal,
+/// unlike the [`Decoded`] themselves.
+#[derive(Debug)]
+pub struct ByExpiry<T: ClaimsSubTrait>(pub Decoded<T>);
+
+impl<T: ClaimsSubTrait> Ord for ByExpiry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.0.exp, self.0.iat).cmp(&(other.0.exp, other.0.iat))
+    }
+}
+
+impl<T: ClaimsSubTrait> PartialOrd for ByExpiry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: ClaimsSubTrait> PartialEq for ByExpiry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: ClaimsSubTrait> Eq for ByExpiry<T> {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Claims<T: ClaimsSubTrait, State = Decoded<T>> {
+    claims: State,
+    // Not `PhantomData<T>`, an encoded token is `Send + Sync` whatever `T` is
+    #[serde(skip)]
+    _type: PhantomData<fn() -> T>,
+}
+
+// Everything can be shared across threads, checked for any `T`
+#[allow(dead_code)]
+const _: () = {
+    fn assert_send_sync<S: Send + Sync>() {}
+
+    fn assert_all<T: ClaimsSubTrait, U: ClaimsSubTrait + Send + Sync>() {
+        assert_send_sync::<ClaimsEncoded<T>>();
+        assert_send_sync::<ClaimsDecoded<U>>();
+        assert_send_sync::<VerifiedToken<U>>();
+        assert_send_sync::<Error>();
+    }
+};
+
+impl<T: EncodingClaims> Claims<T> {
+    pub fn new(claims: T) -> Result<Claims<T, Encoded>> {
+        Self::new_at(claims, clock::now())
+    }
+
+    /// Like [`Claims::new`], with the current unix time given as `iat` so no
+    /// time source is needed.
+    pub fn new_at(claims: T, iat: i64) -> Result<Claims<T, Encoded>> {
+        Self::build(claims, iat, T::duration())
+    }
+
+    /// Like [`Claims::new`], signed with `header`, see [`Claims::encode_with_header`].
+    pub fn new_with_header(claims: T, header: Header) -> Result<Claims<T, Encoded>> {
+        check_ttl::<T>(T::duration())?;
+
+        let iat = clock::now();
+        Self::at(claims, iat, T::duration()).encode_with_header(header)
+    }
+
+    /// Like [`Claims::new`], expiring after `ttl` instead of [`ClaimsSubTrait::duration`].
+    pub fn new_with_ttl(claims: T, ttl: Duration) -> Result<Claims<T, Encoded>> {
+        Self::build(claims, clock::now(), ttl)
+    }
+
+    /// A builder to set the optional registered claims of a token for `claims`
+    pub fn builder(claims: T) -> ClaimsBuilder<T> {
+        ClaimsBuilder::new(claims)
+    }
+
+    /// Mint a token for each of `subjects`, reusing the `EncodingKey`.
+    pub fn mint_all<I>(subjects: I) -> impl Iterator<Item = Result<String>>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let key = T::encoding_key();
+
+        subjects.into_iter().map(move |claims| {
+            T::validate_encoding_key()?;
+            check_ttl::<T>(T::duration())?;
+            let iat = clock::now();
+            Self::at(claims, iat, T::duration())
+                .encode_with_key(&key)
+                .map(ClaimsEncoded::token)
+        })
+    }
+
+    fn build(claims: T, iat: i64, ttl: Duration) -> Result<Claims<T, Encoded>> {
+        check_ttl::<T>(ttl)?;
+
+        Self::at(claims, iat, ttl).encode()
+    }
+}
+
+impl<T: ClaimsSubTrait> Claims<T, Encoded> {
+    /// The token, which is no longer zeroized once moved out
+    pub fn token(mut self) -> String {
+        T::post_encode(std::mem::take(&mut self.claims.0))
+    }
+
+    /// The token prefixed with `prefix`, e.g. a type tag to store several
+    /// kinds of tokens side by side. Parsed with [`Claims::strip_prefix`].
+    pub fn with_prefix(self, prefix: &str) -> String {
+        format!("{}{}", prefix, self.token())
+    }
+
+    /// The token of `input`, which must start with `prefix`
+    pub fn strip_prefix(input: &str, prefix: &str) -> Result<Self> {
+        let token = input
+            .strip_prefix(prefix)
+            .ok_or_else(|| Error::MissingPrefix(prefix.to_string()))?;
+
+        Ok(Claims::from(token.to_string()))
+    }
+
+    /// The token as the `name=token` parameter of a query string, e.g. for a
+    /// magic link. Parsed with [`Claims::from_query`].
+    pub fn to_query_param(self, name: &str) -> String {
+        form_urlencoded::Serializer::new(String::new())
+            .append_pair(name, &self.token())
+            .finish()
+    }
+
+    /// The token of the first `name` parameter of `query`, with or without its
+    /// leading `?`. A `+` is decoded as a space, like any form, so it has to
+    /// be percent-encoded in the token as by [`Claims::to_query_param`].
+    pub fn from_query(query: &str, name: &str) -> Result<Self> {
+        let query = query.strip_prefix('?').unwrap_or(query);
+
+        form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == name)
+            .map(|(_, token)| Claims::from(token.into_owned()))
+            .ok_or_else(|| Error::MissingQueryParam(name.to_string()))
+    }
+
+    /// Length of the token in bytes
+    pub fn len(&self) -> usize {
+        self.claims.0.len()
+    }
+
+    /// Whether the token is empty, e.g. read from an unset header or cookie
+    pub fn is_empty(&self) -> bool {
+        self.claims.0.is_empty()
+    }
+
+    /// The signed part of the token: `base64url(header) + "." + base64url(payload)`
+    pub fn signing_input(&self) -> Result<String> {
+        let [header, payload, _] = segments(&self.claims.0)?;
+
+        Ok(format!("{}.{}", header, payload))
+    }
+
+    /// Whether the `exp` claim lies in the past, **without** verifying the token.
+    ///
+    /// Advisory only, as anyone can forge the payload, e.g. to skip fetching a
+    /// key for a token that's bound to be rejected anyway.
+    pub fn is_expired_unverified(&self) -> Result<bool> {
+        Ok(self.unverified_exp()? < clock::now())
+    }
+
+    /// The `exp` claim as a unix timestamp, **without** verifying the token
+    fn unverified_exp(&self) -> Result<i64> {
+        let exp = self
+            .unverified_payload()?
+            .get("exp")
+            .and_then(serde_json::Value::as_i64)
+            .ok_or_else(|| ErrorKind::MissingRequiredClaim("exp".to_string()))?;
+
+        Ok(exp.saturating_add(T::EPOCH_OFFSET))
+    }
+
+    /// The payload as JSON, inflated if compressed, **without** verifying it
+    fn unverified_payload(&self) -> Result<serde_json::Value> {
+        Self::unverified_payload_of(&self.claims.0)
+    }
+
+    /// `unverified_payload` of `token`
+    fn unverified_payload_of(token: &str) -> Result<serde_json::Value> {
+        let token = if T::COMPRESS {
+            let key = DecodingKey::from_secret(&[]);
+            Cow::Owned(compression::inflate(
+                token,
+                &key,
+                &Validation::default(),
+                false,
+                max_inflated_len::<T>(),
+            )?)
+        } else {
+            Cow::Borrowed(token)
+        };
+
+        let [_, payload, _] = segments(&token)?;
+        let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD)
+            .map_err(|err| Error::Jsonwebtoken(err.into()))?;
+
+        let mut payload = serde_json::from_slice(&payload)?;
+        if let Value::Object(payload) = &mut payload {
+            payload::restore_registered_names::<T>(payload);
+        }
+        Ok(payload)
+    }
+
+    /// The key to verify `token` with, that of the secret version of its `sv`
+    /// claim when it's one of [`DecodingClaims::previous_decoding_keys`].
+    pub(crate) fn decoding_key_for(token: &str) -> Result<DecodingKey>
+    where
+        T: DecodingClaims,
+    {
+        T::validate_decoding_key()?;
+
+        let previous = T::previous_decoding_keys();
+        if !previous.is_empty() {
+            // Only picks the key, the signature is still verified with it
+            let sv = Self::unverified_payload_of(token)
+                .ok()
+                .and_then(|payload| payload.get("sv")?.as_u64());
+            let key = previous
+                .into_iter()
+                .find(|(version, _)| Some(*version as u64) == sv && *version != T::SECRET_VERSION);
+            if let Some((_, key)) = key {
+                return Ok(key);
+            }
+        }
+
+        Ok(T::decoding_key())
+    }
+
+    /// The key to verify every token with when there are no
+    /// [`DecodingClaims::previous_decoding_keys`], so decoding many tokens
+    /// doesn't look it up for each, see [`Self::decoding_key_for`].
+    fn fixed_decoding_key() -> Option<DecodingKey>
+    where
+        T: DecodingClaims,
+    {
+        (T::validate_decoding_key().is_ok() && T::previous_decoding_keys().is_empty())
+            .then(T::decoding_key)
+    }
+
+    /// Whether both tokens are the same, compared in constant time unlike `==`
+    pub fn secure_eq(&self, other: &Self) -> bool {
+        use subtle::ConstantTimeEq;
+
+        self.claims
+            .0
+            .as_bytes()
+            .ct_eq(other.claims.0.as_bytes())
+            .into()
+    }
+
+    /// A hash identifying the token, to correlate logs without logging the
+    /// token itself: the first 16 bytes of its SHA-256 as hex.
+    pub fn token_hash(&self) -> String {
+        let digest = ring::digest::digest(&ring::digest::SHA256, self.claims.0.as_bytes());
+
+        digest.as_ref()[..16]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// How costly verifying the token is by the `alg` of its header, read
+    /// **without** verifying it, e.g. to schedule the work.
+    pub fn decode_cost_hint(&self) -> DecodeCost {
+        let alg = match jsonwebtoken::decode_header(&self.claims.0) {
+            Ok(header) => header.alg,
+            Err(_) => return DecodeCost::Cheap,
+        };
+
+        if AlgorithmFamily::Hmac.contains(alg) {
+            DecodeCost::Cheap
+        } else {
+            DecodeCost::Expensive
+        }
+    }
+
+    /// The `kid` header of the token, **without** verifying it, e.g. to pick
+    /// the key to verify it with.
+    pub fn peek_kid(&self) -> Result<Option<String>> {
+        Ok(jsonwebtoken::decode_header(&self.claims.0)?.kid)
+    }
+
+    /// The `alg` header of the token, **without** verifying it
+    pub fn header_alg(&self) -> Result<Algorithm> {
+        Ok(jsonwebtoken::decode_header(&self.claims.0)?.alg)
+    }
+
+    /// The length of each segment of the token, e.g. to tell whether the
+    /// payload is worth compressing. The dots between them aren't counted.
+    pub fn size_breakdown(&self) -> Result<SizeBreakdown> {
+        let [header, payload, signature] = segments(&self.claims.0)?;
+
+        Ok(SizeBreakdown {
+            header: header.len(),
+            payload: payload.len(),
+            signature: signature.len(),
+        })
+    }
+
+    /// The `header.payload` of the token and its signature, e.g. to hand out
+    /// the former and only keep the signature server-side. See [`Claims::rejoin`].
+    pub fn split_storage(self) -> Result<(String, String)> {
+        let [header, payload, signature] = segments(&self.claims.0)?;
+
+        Ok((format!("{}.{}", header, payload), signature.to_string()))
+    }
+
+    /// The token split by [`Claims::split_storage`], verified as usual when decoded
+    pub fn rejoin((header_payload, signature): (String, String)) -> Self {
+        Claims {
+            claims: Encoded(format!("{}.{}", header_payload, signature)),
+            _type: PhantomData,
+        }
+    }
+
+    /// The header and payload as JSON with the signature segment as is,
+    /// **without** verifying the token, e.g. to display it while debugging.
+    pub fn into_parts_json(self) -> Result<(serde_json::Value, serde_json::Value, String)> {
+        let [header, _, signature] = segments(&self.claims.0)?;
+        let header = base64::decode_config(header, base64::URL_SAFE_NO_PAD)
+            .map_err(|err| Error::Jsonwebtoken(err.into()))?;
+
+        Ok((
+            serde_json::from_slice(&header)?,
+            self.unverified_payload()?,
+            signature.to_string(),
+        ))
+    }
+}
+
+// Manual impls so `T` isn't required to be `Clone`, `PartialEq` or `Hash`
+impl<T: ClaimsSubTrait> Clone for Claims<T, Encoded> {
+    fn clone(&self) -> Self {
+        Self {
+            claims: Encoded(self.claims.0.clone()),
+            _type: PhantomData,
+        }
+    }
+}
+
+/// Compares the tokens as strings, **not** in constant time: use
+/// [`Claims::secure_eq`] to compare against a stored token.
+impl<T: ClaimsSubTrait> PartialEq for Claims<T, Encoded> {
+    fn eq(&self, other: &Self) -> bool {
+        self.claims.0 == other.claims.0
+    }
+}
+
+impl<T: ClaimsSubTrait> Eq for Claims<T, Encoded> {}
+
+impl<T: ClaimsSubTrait> Hash for Claims<T, Encoded> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.claims.0.hash(state);
+    }
+}
+
+/// The bytes of the token, e.g. to hash it, without the envelope of
+/// [`ClaimsSubTrait::post_encode`]
+impl<T: ClaimsSubTrait> AsRef<[u8]> for Claims<T, Encoded> {
+    fn as_ref(&self) -> &[u8] {
+        self.claims.0.as_bytes()
+    }
+}
+
+impl<T: ClaimsSubTrait> Claims<T, Decoded<T>> {
+    fn at(claims: T, iat: i64, ttl: Duration) -> Self {
+        let exp = iat.saturating_add(ttl.as_secs() as i64);
+
+        let claims = claims.canonicalize();
+        let subject_id = claims.subject_id();
+
+        Self {
+            claims: Decoded {
+                sub: claims,
+                subject_id,
+                iss: T::issuer(),
+                nbf: None,
+                exp,
+                // `exp` still counts from `iat` when it's left out
+                iat: T::INCLUDE_IAT.then_some(iat),
+                sv: T::SECRET_VERSION,
+                nonce: T::generate_nonce(),
+                aud: T::audiences_runtime(),
+                jti: None,
+                fingerprint: T::fingerprint(),
+                scope: None,
+                token: None,
+            },
+            _type: PhantomData,
+        }
+    }
+
+    fn encode(self) -> Result<Claims<T, Encoded>>
+    where
+        T: EncodingClaims,
+    {
+        self.encode_with_header(Self::default_header())
+    }
+
+    /// Sign the claims with `header`, e.g. to set a `kid`. Its `alg` is
+    /// replaced by [`ClaimsSubTrait::ALGORITHM`].
+    pub fn encode_with_header(self, mut header: Header) -> Result<Claims<T, Encoded>>
+    where
+        T: EncodingClaims,
+    {
+        T::validate_encoding_key()?;
+
+        header.alg = T::ALGORITHM;
+        self.encode_with(header, &T::encoding_key())
+    }
+
+    /// The header of issued tokens
+    fn default_header() -> Header {
+        let mut header = Header::new(T::ALGORITHM);
+        if let Some(typ) = T::EXPECTED_TYP {
+            header.typ = Some(typ.to_string());
+        }
+        header.cty = T::CONTENT_TYPE.map(str::to_string);
+        let chain = T::certificate_chain();
+        if !chain.is_empty() {
+            header.x5c = Some(chain.into_iter().map(base64::encode).collect());
+        }
+        header
+    }
+
+    fn encode_with_key(self, key: &EncodingKey) -> Result<Claims<T, Encoded>> {
+        self.encode_with(Self::default_header(), key)
+    }
+
+    /// The claims as they're put in a token, once the Subject passed
+    /// [`ClaimsSubTrait::pre_sign_validate`]
+    pub(crate) fn into_payload(self) -> Result<Decoded<T>> {
+        self.claims
+            .sub
+            .pre_sign_validate()
+            .map_err(Error::SubjectRejected)?;
+        payload::check_reserved_claims(&self.claims.sub)?;
+
+        let mut claims = self.claims;
+        claims.exp = claims.exp.saturating_sub(T::EPOCH_OFFSET);
+        claims.iat = claims.iat.map(|iat| iat.saturating_sub(T::EPOCH_OFFSET));
+        claims.nbf = claims.nbf.map(|nbf| nbf.saturating_sub(T::EPOCH_OFFSET));
+        Ok(claims)
+    }
+
+    fn encode_with(self, header: Header, key: &EncodingKey) -> Result<Claims<T, Encoded>> {
+        let claims = self.into_payload()?;
+
+        let extra = T::additional_headers();
+        let encoded_claim = if T::COMPRESS {
+            compression::encode(&header, &extra, &claims, key)?
+        } else if extra.is_empty() {
+            jsonwebtoken::encode(&header, &claims, key)?
+        } else {
+            // Signed by hand, as `Header` can't hold the extra parameters
+            let header_json = serde_json::to_vec(&header_json(&header, &extra)?)?;
+            let message = format!(
+                "{}.{}",
+                base64::encode_config(header_json, base64::URL_SAFE_NO_PAD),
+                base64::encode_config(serde_json::to_vec(&claims)?, base64::URL_SAFE_NO_PAD)
+            );
+            let signature = jsonwebtoken::crypto::sign(message.as_bytes(), key, header.alg)?;
+            format!("{}.{}", message, signature)
+        };
+
+        metrics::minted::<T>();
+
+        Ok(Claims {
+            claims: Encoded(encoded_claim),
+            _type: PhantomData,
+        })
+    }
+
+    /// Time since the token was issued, zero if `iat` lies in the future.
+    /// `None` without an `iat` claim, see [`ClaimsSubTrait::INCLUDE_IAT`].
+    pub fn age(&self) -> Option<Duration> {
+        self.age_at(clock::now())
+    }
+
+    /// `age` at the unix timestamp `now`
+    pub fn age_at(&self, now: i64) -> Option<Duration> {
+        let iat = self.claims.iat?;
+        Some(Duration::from_secs(now.saturating_sub(iat).max(0) as u64))
+    }
+
+    /// How far the token is through its lifetime, from 0.0 when issued to
+    /// 1.0 once expired. Without an `iat` the lifetime is taken to be
+    /// [`ClaimsSubTrait::duration`].
+    pub fn lifetime_elapsed_fraction(&self) -> f64 {
+        self.lifetime_elapsed_fraction_at(clock::now())
+    }
+
+    /// `lifetime_elapsed_fraction` at the unix timestamp `now`
+    pub fn lifetime_elapsed_fraction_at(&self, now: i64) -> f64 {
+        let exp = self.claims.exp;
+        let iat = self
+            .claims
+            .iat
+            .unwrap_or_else(|| exp.saturating_sub(T::duration().as_secs() as i64));
+
+        // A token without a lifetime is expired as soon as it's issued
+        if exp <= iat {
+            return 1.0;
+        }
+
+        let fraction = now.saturating_sub(iat) as f64 / exp.saturating_sub(iat) as f64;
+        fraction.clamp(0.0, 1.0)
+    }
+
+    /// Whether the token is at least `threshold` through its lifetime, e.g.
+    /// `should_refresh(0.75)` to get a new one before it expires
+    pub fn should_refresh(&self, threshold: f64) -> bool {
+        self.lifetime_elapsed_fraction() >= threshold
+    }
+
+    /// Sign the claims again as they are, keeping `iat` and `exp`, with the
+    /// current secret version
+    pub fn reencode_preserving(mut self) -> Result<Claims<T, Encoded>>
+    where
+        T: EncodingClaims,
+    {
+        self.claims.sv = T::SECRET_VERSION;
+        self.encode()
+    }
+
+    /// The claims of `data` that jsonwebtoken verified `token` to directly, the
+    /// header is dropped. That `data` is that of `token` is checked in debug
+    /// builds.
+    pub fn from_token_data(data: TokenData<Decoded<T>>, token: String) -> Self {
+        debug_assert!(
+            ClaimsEncoded::<T>::unverified_payload_of(&T::pre_decode(&token))
+                .ok()
+                .and_then(|payload| payload.get("exp")?.as_i64())
+                == Some(data.claims.exp),
+            "`data` wasn't decoded from `token`"
+        );
+
+        Claims {
+            claims: data.claims,
+            _type: PhantomData,
+        }
+    }
+
+    /// The claims of the payload `value` of a token, e.g. one already verified
+    /// by a gateway. Nothing is checked, chain the `require_*` checks for that:
+    /// `Claims::from_claims_value(value)?.require_not_expired()?`.
+    pub fn from_claims_value(value: serde_json::Value) -> Result<Self>
+    where
+        Decoded<T>: DeserializeOwned,
+    {
+        let mut claims: Decoded<T> = serde_json::from_value(value)?;
+        claims.exp = claims.exp.saturating_add(T::EPOCH_OFFSET);
+        claims.iat = claims.iat.map(|iat| iat.saturating_add(T::EPOCH_OFFSET));
+        claims.nbf = claims.nbf.map(|nbf| nbf.saturating_add(T::EPOCH_OFFSET));
+
+        Ok(Claims {
+            claims,
+            _type: PhantomData,
+        })
+    }
+
+    pub fn claims(self) -> Decoded<T> {
+        self.claims
+    }
+
+    /// Decode each of the tokens in `input` separated by `delimiter`, e.g. a
+    /// cookie holding several of them, failing if any of them is invalid.
+    pub fn decode_set(input: &str, delimiter: char) -> Result<Vec<Self>>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        Self::decode_set_lenient(input, delimiter)
+            .into_iter()
+            .collect()
+    }
+
+    /// `decode_set`, with the result of every token instead of failing on the
+    /// first invalid one
+    pub fn decode_set_lenient(input: &str, delimiter: char) -> Vec<Result<Self>>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        let fixed_key = ClaimsEncoded::<T>::fixed_decoding_key();
+
+        input
+            .split(delimiter)
+            .map(|token| {
+                let token = T::pre_decode(trim_token::<T>(token));
+                T::validate_token_format(&token)?;
+
+                let key = match &fixed_key {
+                    Some(key) => Cow::Borrowed(key),
+                    None => Cow::Owned(ClaimsEncoded::<T>::decoding_key_for(&token)?),
+                };
+                ClaimsEncoded::<T>::decode_token(&token, &key).map(|(_, claims)| claims)
+            })
+            .collect()
+    }
+
+    /// Lazily decode each line of `reader`, e.g. a file of newline delimited
+    /// tokens, skipping blank lines.
+    pub fn decode_from_reader<R: BufRead>(reader: R) -> impl Iterator<Item = Result<Self>>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        let fixed_key = ClaimsEncoded::<T>::fixed_decoding_key();
+
+        reader
+            .lines()
+            .filter(|line| !matches!(line, Ok(line) if trim_token::<T>(line).is_empty()))
+            .map(move |line| {
+                let line = line.map_err(Error::Read)?;
+                let token = T::pre_decode(trim_token::<T>(&line));
+                T::validate_token_format(&token)?;
+
+                let key = match &fixed_key {
+                    Some(key) => Cow::Borrowed(key),
+                    None => Cow::Owned(ClaimsEncoded::<T>::decoding_key_for(&token)?),
+                };
+                ClaimsEncoded::<T>::decode_token(&token, &key).map(|(_, claims)| claims)
+            })
+    }
+
+    /// Whether `exp` lies in the past, disregarding any leeway
+    pub fn is_expired(&self) -> bool {
+        self.claims.exp < clock::now()
+    }
+
+    /// When the token becomes valid, `None` without an `nbf` claim (or one out
+    /// of the range of `OffsetDateTime`).
+    pub fn activates_at(&self) -> Option<OffsetDateTime> {
+        OffsetDateTime::from_unix_timestamp(self.claims.nbf?).ok()
+    }
+
+    /// Whether `now` lies between `nbf` and `exp`, disregarding any leeway
+    pub fn is_active(&self, now: OffsetDateTime) -> bool {
+        let now = now.unix_timestamp();
+        !matches!(self.claims.nbf, Some(nbf) if nbf > now) && now <= self.claims.exp
+    }
+
+    /// The first of the audiences of the token, e.g. to route it by
+    pub fn primary_audience(&self) -> Option<&str> {
+        self.claims.aud.first().map(String::as_str)
+    }
+
+    /// Whether `aud` is one of the audiences of the token
+    pub fn has_audience(&self, aud: &str) -> bool {
+        self.claims.aud.iter().any(|audience| audience == aud)
+    }
+
+    /// The scopes of the OAuth style `scope` claim, a whitespace separated list
+    pub fn scopes(&self) -> Vec<&str> {
+        self.claims
+            .scope
+            .as_deref()
+            .map(|scope| scope.split_whitespace().collect())
+            .unwrap_or_default()
+    }
+
+    /// Whether `scope` is one of the [`Claims::scopes`] of the token
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes().contains(&scope)
+    }
+
+    /// Require the registered `sub` claim, see [`ClaimsSubTrait::subject_id`].
+    ///
+    /// The `require_*` checks chain, e.g.
+    /// `decoded.require_sub_present()?.require_audience("api")?.require_not_expired()?`.
+    pub fn require_sub_present(&self) -> Result<&Self> {
+        match self.claims.subject_id {
+            Some(_) => Ok(self),
+            None => Err(ErrorKind::MissingRequiredClaim("sub".to_string()).into()),
+        }
+    }
+
+    /// Require `aud` to be one of the audiences of the token
+    pub fn require_audience(&self, aud: &str) -> Result<&Self> {
+        if !self.has_audience(aud) {
+            return Err(ErrorKind::InvalidAudience.into());
+        }
+        Ok(self)
+    }
+
+    /// Require the `iss` claim to be `iss`
+    pub fn require_issuer(&self, iss: &str) -> Result<&Self> {
+        if self.claims.issuer() != Some(iss) {
+            return Err(ErrorKind::InvalidIssuer.into());
+        }
+        Ok(self)
+    }
+
+    /// Require the token not to have expired by now, e.g. when the claims were
+    /// decoded a while ago
+    pub fn require_not_expired(&self) -> Result<&Self> {
+        if self.is_expired() {
+            return Err(Error::Expired);
+        }
+        Ok(self)
+    }
+
+    /// The whole payload, registered claims and the Subject alike, as `U`,
+    /// e.g. to flatten both into one struct.
+    pub fn claims_as<U: DeserializeOwned>(&self) -> Result<U> {
+        Ok(serde_json::from_value(serde_json::to_value(&self.claims)?)?)
+    }
+
+    /// The Subject alone as JSON, without any of the registered claims
+    pub fn subject_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(&self.claims.sub)?)
+    }
+
+    /// The Subject alone as YAML, without any of the registered claims
+    #[cfg(feature = "yaml")]
+    pub fn subject_yaml(&self) -> Result<String> {
+        Ok(serde_yaml::to_string(&self.claims.sub)?)
+    }
+
+    pub fn sub(self) -> T {
+        self.claims.sub
+    }
+
+    /// The value of an `Authorization` header forwarding the original token,
+    /// `Bearer <token>`, when it was retained by [`VerifiedToken::into_claims`]
+    pub fn as_bearer(&self) -> Option<String> {
+        let token = self.claims.token.as_ref()?;
+        Some(format!("Bearer {}", token.0))
+    }
+
+    /// Whether both tokens were issued for equal Subjects, e.g. the old and new
+    /// token of a refresh
+    pub fn same_subject(&self, other: &Self) -> bool
+    where
+        T: PartialEq,
+    {
+        self.claims.sub == other.claims.sub
+    }
+
+    /// `sub` after running [`ClaimsSubTrait::validate_sub`] again
+    pub fn try_sub(self) -> Result<T> {
+        self.claims
+            .sub
+            .validate_sub()
+            .map_err(Error::SubjectRejected)?;
+
+        Ok(self.claims.sub)
+    }
+
+    /// Whether the claim `key` equals `expected`, `false` when the claim is absent.
+    pub fn verify_claim<V>(&self, key: &str, expected: &V) -> Result<bool>
+    where
+        V: PartialEq + Serialize,
+    {
+        let claims = serde_json::to_value(&self.claims)?;
+        let expected = serde_json::to_value(expected)?;
+
+        Ok(claims.get(key) == Some(&expected))
+    }
+
+    /// The `jti` of the parent token, see [`ClaimsSubTrait::parent_jti`]
+    pub fn parent_jti(&self) -> Option<&str> {
+        self.claims.sub.parent_jti()
+    }
+
+    /// Check that this token was delegated from `parent`: it references the
+    /// `jti` of `parent` and doesn't outlive it.
+    ///
+    /// Only the link is checked, both tokens must have been decoded already.
+    pub fn validate_chain(&self, parent: &ClaimsDecoded<T>) -> Result<()> {
+        match (self.parent_jti(), parent.claims.jti()) {
+            (None, _) => return Err(Error::InvalidChain("missing parent `jti`")),
+            (Some(parent_jti), Some(jti)) if parent_jti == jti => {}
+            _ => return Err(Error::InvalidChain("parent `jti` doesn't match")),
+        }
+
+        if self.claims.exp > parent.claims.exp {
+            return Err(Error::InvalidChain("token outlives its parent"));
+        }
+        Ok(())
+    }
+}
+
+impl<T: ClaimsSubTrait> Claims<T, Encoded> {
+    /// Verify the token and issue a new one for the same Subject, expiring
+    /// after `new_ttl`.
+    pub fn slide(self, new_ttl: Duration) -> Result<Claims<T, Encoded>>
+    where
+        T: EncodingClaims + DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        let sub = self.decode()?.sub();
+
+        Claims::new_with_ttl(sub, new_ttl)
+    }
+
+    pub fn decode(self) -> Result<Claims<T, Decoded<T>>>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        self.decode_full().map(|(_, claims)| claims)
+    }
+
+    /// `decode`, with the claims in an `Arc`, e.g. to share them between tasks
+    /// without cloning the Subject for each.
+    pub fn decode_arc(self) -> Result<Arc<Decoded<T>>>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        self.decode()
+            .map(|decoded_claims| Arc::new(decoded_claims.claims))
+    }
+
+    /// Decode the token and map the Subject with `f`, e.g. to get a user id.
+    pub fn validate_and_extract<R>(self, f: impl FnOnce(&T) -> R) -> Result<R>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        let decoded_claims = self.decode()?;
+
+        Ok(f(&decoded_claims.claims.sub))
+    }
+
+    /// `decode` a one-time token, rejecting it when its nonce was used before.
+    ///
+    /// A token without a nonce is rejected as well, it can't be told apart
+    /// from a replayed one.
+    pub fn decode_once(self, store: &impl ReplayStore) -> Result<Claims<T, Decoded<T>>>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        let decoded_claims = self.decode()?;
+
+        let nonce = match decoded_claims.claims.nonce.as_deref() {
+            Some(nonce) => nonce,
+            None => {
+                let kind = ErrorKind::MissingRequiredClaim("nonce".to_string());
+                return Err(Error::Jsonwebtoken(kind.into()));
+            }
+        };
+        if !store.check_and_consume(nonce) {
+            return Err(Error::Replayed(nonce.to_string()));
+        }
+
+        Ok(decoded_claims)
+    }
+
+    /// `decode`, ignoring whether the token expired when `skip` is set, e.g.
+    /// to inspect any token in an admin tool.
+    pub fn decode_skip_expiry(&self, skip: bool) -> Result<Claims<T, Decoded<T>>>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        T::validate_token_format(&self.claims.0)?;
+
+        let mut validation = Self::validation();
+        if skip {
+            validation.validate_exp = false;
+        }
+
+        Self::decode_token_with(
+            &self.claims.0,
+            &Self::decoding_key_for(&self.claims.0)?,
+            validation,
+        )
+        .map(|(_, claims)| claims)
+    }
+
+    /// `decode`, also accepting tokens that expired less than `grace` ago, e.g.
+    /// to finish an upload that was in flight. Tells whether the token was
+    /// only accepted thanks to `grace`.
+    pub fn decode_with_grace(&self, grace: Duration) -> Result<(Claims<T, Decoded<T>>, bool)>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        T::validate_token_format(&self.claims.0)?;
+
+        let mut validation = Self::validation();
+        let exp_leeway = match T::NBF_LEEWAY {
+            Some(_) => 0,
+            None => validation.leeway as i64,
+        };
+        let validate_exp = std::mem::replace(&mut validation.validate_exp, false);
+
+        let (_, decoded_claims) = Self::decode_token_with(
+            &self.claims.0,
+            &Self::decoding_key_for(&self.claims.0)?,
+            validation,
+        )?;
+        if !validate_exp {
+            return Ok((decoded_claims, false));
+        }
+
+        let now = clock::now();
+        let expires_at = decoded_claims.claims.exp.saturating_add(exp_leeway);
+        if expires_at.saturating_add(grace.as_secs() as i64) < now {
+            return Err(notify_expired::<T>(Error::Expired));
+        }
+
+        Ok((decoded_claims, expires_at < now))
+    }
+
+    /// `decode`, rejecting tokens issued more than `max_age` ago like
+    /// [`ClaimsSubTrait::MAX_AGE`] does, but for this call only. E.g. to
+    /// require a recent login for sensitive operations.
+    pub fn decode_fresh(&self, max_age: Duration) -> Result<Claims<T, Decoded<T>>>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        T::validate_token_format(&self.claims.0)?;
+
+        let (_, decoded_claims) =
+            Self::decode_token(&self.claims.0, &Self::decoding_key_for(&self.claims.0)?)?;
+
+        let age = decoded_claims
+            .age()
+            .ok_or_else(|| ErrorKind::MissingRequiredClaim("iat".to_string()))?
+            .as_secs();
+        let max_age = max_age.as_secs();
+        if age > max_age {
+            return Err(Error::TooOld { age, max_age });
+        }
+
+        Ok(decoded_claims)
+    }
+
+    /// Verify the token for `T` and sign the same claims again for `U`, with
+    /// its algorithm and key, e.g. to migrate tokens to another algorithm
+    /// without the client authenticating again. The timestamps are kept, the
+    /// secret version is that of `U`.
+    pub fn resign<U>(self) -> Result<Claims<U, Encoded>>
+    where
+        T: DecodingClaims + Into<U>,
+        U: EncodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        let Decoded {
+            sub,
+            subject_id,
+            iss,
+            nbf,
+            exp,
+            iat,
+            sv: _,
+            nonce,
+            aud,
+            jti,
+            fingerprint,
+            scope,
+            token: _,
+        } = self.decode()?.claims;
+
+        let claims = Decoded {
+            sub: sub.into(),
+            subject_id,
+            iss,
+            nbf,
+            exp,
+            iat,
+            sv: U::SECRET_VERSION,
+            nonce,
+            aud,
+            jti,
+            fingerprint,
+            scope,
+            token: None,
+        };
+
+        Claims {
+            claims,
+            _type: PhantomData,
+        }
+        .encode()
+    }
+
+    /// `decode` a token bound to a client, its fingerprint must be
+    /// `expected_fingerprint`, e.g. the hash of the IP address of the request.
+    pub fn decode_bound(&self, expected_fingerprint: &str) -> Result<Claims<T, Decoded<T>>>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        use subtle::ConstantTimeEq;
+
+        T::validate_token_format(&self.claims.0)?;
+
+        let (_, decoded_claims) =
+            Self::decode_token(&self.claims.0, &Self::decoding_key_for(&self.claims.0)?)?;
+
+        let matches = match decoded_claims.claims.fingerprint.as_deref() {
+            Some(fingerprint) => fingerprint
+                .as_bytes()
+                .ct_eq(expected_fingerprint.as_bytes())
+                .into(),
+            None => false,
+        };
+        if !matches {
+            return Err(Error::FingerprintMismatch);
+        }
+
+        Ok(decoded_claims)
+    }
+
+    /// `decode` a token scoped to a single principal, its registered `sub`
+    /// claim must be `expected`.
+    pub fn decode_expecting_sub(&self, expected: &str) -> Result<Claims<T, Decoded<T>>>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        T::validate_token_format(&self.claims.0)?;
+
+        let mut validation = Self::validation();
+        validation.sub = Some(expected.to_string());
+        validation.required_spec_claims.insert("sub".to_string());
+
+        Self::decode_token_with(
+            &self.claims.0,
+            &Self::decoding_key_for(&self.claims.0)?,
+            validation,
+        )
+        .map(|(_, claims)| claims)
+    }
+
+    /// `decode`, retaining the token alongside its claims so it can be had
+    /// back as is with [`VerifiedToken::to_encoded`].
+    pub fn decode_retaining(self) -> Result<VerifiedToken<T>>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        VerifiedToken::verify(self.token())
+    }
+
+    /// `decode`, with `None` instead of an error when the token is valid
+    /// except for having expired
+    pub fn decode_optional(&self) -> Result<Option<Claims<T, Decoded<T>>>>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        match self.clone().decode() {
+            Ok(claims) => Ok(Some(claims)),
+            Err(Error::Expired) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// `decode`, also reporting the validation that was applied, e.g. to debug
+    /// clock skew.
+    pub fn decode_with_report(&self) -> Result<(Claims<T, Decoded<T>>, ValidationReport)>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        let validation = Self::validation();
+        let report = ValidationReport {
+            // Same as `verify`, `NBF_LEEWAY` replaces the leeway on `exp`
+            leeway_used: T::NBF_LEEWAY.map_or(validation.leeway, |_| 0),
+            algorithms: validation.algorithms,
+            checked_exp: validation.validate_exp,
+            checked_nbf: validation.validate_nbf,
+        };
+
+        Ok((self.clone().decode()?, report))
+    }
+
+    /// `decode`, also returning the verified header of the token
+    pub fn decode_full(self) -> Result<(Header, Claims<T, Decoded<T>>)>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        T::validate_token_format(&self.claims.0)?;
+
+        Self::decode_token(&self.claims.0, &Self::decoding_key_for(&self.claims.0)?)
+    }
+
+    /// `decode_full`, with the header as JSON so parameters [`Header`] has no
+    /// field for are kept, like those of [`ClaimsSubTrait::additional_headers`].
+    pub fn decode_full_json(self) -> Result<(Map<String, Value>, ClaimsDecoded<T>)>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        let [header, _, _] = segments(&self.claims.0)?;
+        let header = base64::decode_config(header, base64::URL_SAFE_NO_PAD)
+            .map_err(|err| Error::Jsonwebtoken(err.into()))?;
+        let header = serde_json::from_slice(&header)?;
+
+        let (_, claims) = self.decode_full()?;
+
+        Ok((header, claims))
+    }
+
+    /// `decode_full` as the [`TokenData`] jsonwebtoken decodes to
+    pub fn into_token_data(self) -> Result<TokenData<Decoded<T>>>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        let (header, claims) = self.decode_full()?;
+
+        Ok(TokenData {
+            header,
+            claims: claims.claims,
+        })
+    }
+
+    /// `decode_full` of `token` with `key`, the token format is expected to be
+    /// validated already
+    pub(crate) fn decode_token(
+        token: &str,
+        key: &DecodingKey,
+    ) -> Result<(Header, Claims<T, Decoded<T>>)>
+    where
+        Decoded<T>: DeserializeOwned,
+    {
+        Self::decode_token_with(token, key, Self::validation())
+    }
+
+    /// `decode_token` with `validation` instead of the validation of `T`
+    fn decode_token_with(
+        token: &str,
+        key: &DecodingKey,
+        validation: Validation,
+    ) -> Result<(Header, Claims<T, Decoded<T>>)>
+    where
+        Decoded<T>: DeserializeOwned,
+    {
+        let decoded = Self::check_header(token)
+            .and_then(|()| Self::verify(token, key, validation, true).map_err(notify_expired::<T>))
+            .and_then(|TokenData { header, claims }| Ok((header, Self::check_claims(claims)?)));
+        metrics::decoded::<T>(decoded.as_ref().err());
+
+        decoded
+    }
+
+    /// Checks on the header of `token` before it's verified
+    fn check_header(token: &str) -> Result<()> {
+        if is_unsecured(token) {
+            return Err(Error::AlgorithmNone);
+        }
+
+        if let Some(expected) = T::EXPECTED_TYP {
+            let found = jsonwebtoken::decode_header(token)?.typ;
+            if found.as_deref() != Some(expected) {
+                return Err(Error::UnexpectedTokenType { expected, found });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The claims of `payload`, authenticated some other way than by the
+    /// signature of a token, e.g. decrypted from a JWE. They're validated
+    /// exactly like those of a signed token.
+    #[cfg(feature = "jwe")]
+    pub(crate) fn decode_payload(payload: &[u8]) -> Result<Claims<T, Decoded<T>>>
+    where
+        Decoded<T>: DeserializeOwned,
+    {
+        let header = serde_json::to_vec(&Header::new(T::ALGORITHM))?;
+        let token = Zeroizing::new(format!(
+            "{}.{}.",
+            base64::encode_config(header, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(payload, base64::URL_SAFE_NO_PAD)
+        ));
+
+        let mut validation = Self::validation();
+        validation.insecure_disable_signature_validation();
+
+        let TokenData {
+            claims: decoded_claims,
+            ..
+        } = Self::verify_claims(&token, &DecodingKey::from_secret(&[]), validation)
+            .map_err(notify_expired::<T>)?;
+
+        Self::check_claims(decoded_claims)
+    }
+
+    /// The checks on verified claims that jsonwebtoken doesn't make
+    fn check_claims(decoded_claims: Decoded<T>) -> Result<Claims<T, Decoded<T>>> {
+        // Whatever the current time, these can't have been issued like this
+        if matches!(decoded_claims.iat, Some(iat) if iat > decoded_claims.exp) {
+            return Err(Error::InconsistentTimestamps("`iat` after `exp`"));
+        }
+        if matches!((decoded_claims.iat, decoded_claims.nbf), (Some(iat), Some(nbf)) if iat > nbf) {
+            return Err(Error::InconsistentTimestamps("`iat` after `nbf`"));
+        }
+
+        if decoded_claims.sv < T::MIN_SECRET_VERSION {
+            return Err(Error::OutdatedSecret {
+                version: decoded_claims.sv,
+                min: T::MIN_SECRET_VERSION,
+            });
+        }
+
+        // jsonwebtoken only checks for any of the audiences
+        if T::AUDIENCE_MATCH == AudienceMatch::All {
+            let named = |aud: &str| decoded_claims.aud.iter().any(|named| named == aud);
+            if !accepted_audiences::<T>().iter().all(named) {
+                return Err(Error::Jsonwebtoken(ErrorKind::InvalidAudience.into()));
+            }
+        }
+
+        if T::ROTATION_GRACE > 0 && decoded_claims.sv < T::SECRET_VERSION {
+            let previous = T::SECRET_VERSION - 1;
+            if decoded_claims.sv < previous {
+                return Err(Error::OutdatedSecret {
+                    version: decoded_claims.sv,
+                    min: previous,
+                });
+            }
+
+            let now = clock::now();
+            let window = T::secret_rotation_window().as_secs() as i64;
+            if !matches!(decoded_claims.iat, Some(iat) if now.saturating_sub(iat) <= window) {
+                return Err(Error::OutdatedSecret {
+                    version: decoded_claims.sv,
+                    min: T::SECRET_VERSION,
+                });
+            }
+        }
+
+        if let Some(max_age) = T::MAX_AGE {
+            let iat = decoded_claims.iat.ok_or_else(|| {
+                Error::Jsonwebtoken(ErrorKind::MissingRequiredClaim("iat".to_string()).into())
+            })?;
+            let now = clock::now();
+            let age = now.saturating_sub(iat).max(0) as u64;
+            if age > max_age {
+                return Err(Error::TooOld { age, max_age });
+            }
+        }
+
+        // The registered `sub` claim is required once the Subject provides one
+        if let Some(expected) = decoded_claims.sub.subject_id() {
+            match decoded_claims.subject_id.as_deref() {
+                None => {
+                    let kind = ErrorKind::MissingRequiredClaim("sub".to_string());
+                    return Err(Error::Jsonwebtoken(kind.into()));
+                }
+                Some(sub) if sub != expected => {
+                    return Err(Error::Jsonwebtoken(ErrorKind::InvalidSubject.into()))
+                }
+                Some(_) => {}
+            }
+        }
+
+        decoded_claims
+            .sub
+            .validate_sub()
+            .map_err(Error::SubjectRejected)?;
+
+        T::on_decode(&decoded_claims);
+
+        Ok(Claims {
+            claims: decoded_claims,
+            _type: PhantomData,
+        })
+    }
+
+    /// The validation of `T` with the runtime issuer and audiences applied
+    fn validation() -> Validation {
+        let mut validation = T::validation();
+        if !T::ALLOW_EXPIRED {
+            validation.validate_exp = true;
+        }
+        if let Some(issuer) = T::issuer() {
+            validation.set_issuer(&[issuer]);
+        }
+        let audiences = accepted_audiences::<T>();
+        if !audiences.is_empty() {
+            validation.aud = Some(audiences.into_iter().collect());
+            validation.required_spec_claims.insert("aud".to_string());
+        }
+        validation
+    }
+
+    /// Decode the token, reporting every failing check instead of only the first.
+    ///
+    /// The signature, expiry, not before, issuer and audience are each checked
+    /// on their own.
+    pub fn decode_diagnostic(&self) -> std::result::Result<Claims<T, Decoded<T>>, Vec<Error>>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        let token = self.claims.0.as_str();
+        T::validate_token_format(token).map_err(|err| vec![err])?;
+        Self::check_header(token).map_err(|err| vec![err])?;
+
+        let validation = Self::validation();
+
+        // Everything but the checks isolated below
+        let mut signature = validation.clone();
+        signature
+            .required_spec_claims
+            .retain(|claim| !["exp", "nbf", "iss", "aud"].contains(&claim.as_str()));
+        signature.validate_exp = false;
+        signature.validate_nbf = false;
+        signature.iss = None;
+        signature.aud = None;
+
+        let required = |claim: &str| {
+            let mut required = std::collections::HashSet::new();
+            if validation.required_spec_claims.contains(claim) {
+                required.insert(claim.to_string());
+            }
+            required
+        };
+
+        let mut exp = signature.clone();
+        exp.required_spec_claims = required("exp");
+        exp.validate_exp = validation.validate_exp;
+
+        let mut nbf = signature.clone();
+        nbf.required_spec_claims = required("nbf");
+        nbf.validate_nbf = validation.validate_nbf;
+
+        let mut iss = signature.clone();
+        iss.required_spec_claims = required("iss");
+        iss.iss = validation.iss.clone();
+
+        let mut aud = signature.clone();
+        aud.required_spec_claims = required("aud");
+        aud.aud = validation.aud.clone();
+
+        let checks = [
+            (exp, ErrorKind::ExpiredSignature, "exp"),
+            (nbf, ErrorKind::ImmatureSignature, "nbf"),
+            (iss, ErrorKind::InvalidIssuer, "iss"),
+            (aud, ErrorKind::InvalidAudience, "aud"),
+        ];
+
+        let key = Self::decoding_key_for(token).map_err(|err| vec![err])?;
+        let mut errors = Vec::new();
+
+        let verified = match Self::verify(token, &key, signature, true) {
+            Ok(TokenData { claims, .. }) => Some(claims),
+            Err(err) => {
+                errors.push(err);
+                None
+            }
+        };
+
+        // Anything other than the isolated check failing is already reported above
+        for (validation, kind, claim) in checks {
+            let missing = ErrorKind::MissingRequiredClaim(claim.to_string());
+            match Self::verify(token, &key, validation, false) {
+                Err(Error::Expired) if kind == ErrorKind::ExpiredSignature => {
+                    errors.push(notify_expired::<T>(Error::Expired))
+                }
+                Err(err @ Error::NotYetValid { .. }) if kind == ErrorKind::ImmatureSignature => {
+                    errors.push(err)
+                }
+                Err(Error::Jsonwebtoken(err)) if *err.kind() == kind || *err.kind() == missing => {
+                    errors.push(err.into())
+                }
+                _ => {}
+            }
+        }
+
+        match verified {
+            Some(decoded_claims) if errors.is_empty() => {
+                Self::check_claims(decoded_claims).map_err(|err| vec![err])
+            }
+            _ => Err(errors),
+        }
+    }
+
+    /// Verify a JWS with a detached `payload` (RFC 7797), e.g. a signed webhook.
+    ///
+    /// With `"b64": false` in the header the payload is signed as is, otherwise
+    /// base64url encoded like in a compact token.
+    pub fn verify_detached(header_b64: &str, payload: &[u8], signature_b64: &str) -> Result<()>
+    where
+        T: DecodingClaims,
+    {
+        let header = base64::decode_config(header_b64, base64::URL_SAFE_NO_PAD).map_err(|err| {
+            Error::Malformed {
+                segment: Segment::Header,
+                reason: err.to_string(),
+            }
+        })?;
+        let header: serde_json::Value = serde_json::from_slice(&header)?;
+        let encoded = header.get("b64").and_then(serde_json::Value::as_bool) != Some(false);
+        // A verifier unaware of `b64` must not take the payload for encoded (RFC 7797 §6)
+        let critical = header
+            .get("crit")
+            .and_then(serde_json::Value::as_array)
+            .into_iter()
+            .flatten()
+            .any(|param| param == "b64");
+        if !encoded && !critical {
+            return Err(Error::Malformed {
+                segment: Segment::Header,
+                reason: "`b64` isn't listed in `crit`".to_string(),
+            });
+        }
+        let header: Header = serde_json::from_value(header)?;
+
+        let family = T::algorithm_family();
+        let mut algorithms = T::accepted_algorithms();
+        algorithms.retain(|alg| family.contains(*alg));
+        if !algorithms.contains(&header.alg) {
+            return Err(ErrorKind::InvalidAlgorithm.into());
+        }
+
+        let mut message = format!("{}.", header_b64).into_bytes();
+        if encoded {
+            message.extend(base64::encode_config(payload, base64::URL_SAFE_NO_PAD).bytes());
+        } else {
+            message.extend(payload);
+        }
+
+        T::validate_decoding_key()?;
+        if !jsonwebtoken::crypto::verify(signature_b64, &message, &T::decoding_key(), header.alg)? {
+            return Err(ErrorKind::InvalidSignature.into());
+        }
+
+        Ok(())
+    }
+
+    /// Check the structure, optionally the signature and the `validation` of `token`.
+    fn verify(
+        token: &str,
+        key: &DecodingKey,
+        mut validation: Validation,
+        signature: bool,
+    ) -> Result<TokenData<Decoded<T>>>
+    where
+        Decoded<T>: DeserializeOwned,
+    {
+        segments(token)?;
+
+        let family = T::algorithm_family();
+        validation.algorithms.retain(|alg| family.contains(*alg));
+
+        let token = if T::COMPRESS {
+            compression::inflate(token, key, &validation, signature, max_inflated_len::<T>())?
+        } else {
+            token.to_string()
+        };
+
+        // The signature of a compressed token covers the compressed payload and
+        // was verified by `inflate`
+        if !signature || T::COMPRESS {
+            validation.insecure_disable_signature_validation();
+        }
+
+        Self::verify_claims(&token, key, validation)
+    }
+
+    /// Check the signature of `token` unless disabled in `validation`, then
+    /// its claims
+    fn verify_claims(
+        token: &str,
+        key: &DecodingKey,
+        mut validation: Validation,
+    ) -> Result<TokenData<Decoded<T>>>
+    where
+        Decoded<T>: DeserializeOwned,
+    {
+        // jsonwebtoken only finds the registered claims under their own names
+        let token = if T::rename_registered_claims().is_empty() {
+            token.to_string()
+        } else {
+            let restored = restore_registered_names::<T>(token, key, &validation)?;
+            validation.insecure_disable_signature_validation();
+            restored
+        };
+
+        // jsonwebtoken only recognizes a numeric `exp`, its presence is also
+        // required when deserializing the claims
+        if T::LENIENT_TIME_FORMAT {
+            validation.required_spec_claims.remove("exp");
+        }
+
+        // `exp` and `nbf` are checked here, as jsonwebtoken assumes the unix epoch
+        let validate_exp = std::mem::replace(&mut validation.validate_exp, false);
+        let validate_nbf = std::mem::replace(&mut validation.validate_nbf, false);
+
+        let mut token_data: TokenData<Decoded<T>> =
+            jsonwebtoken::decode(token.as_str(), key, &validation).map_err(|err| {
+                match (err.kind(), numeric_exp(&token)) {
+                    (ErrorKind::MissingRequiredClaim(claim), Some(value)) if claim == "exp" => {
+                        Error::InvalidNumericDate {
+                            claim: "exp",
+                            value,
+                        }
+                    }
+                    _ => map_jwt_error(err),
+                }
+            })?;
+
+        let claims = &mut token_data.claims;
+        claims.exp = claims.exp.saturating_add(T::EPOCH_OFFSET);
+        claims.iat = claims.iat.map(|iat| iat.saturating_add(T::EPOCH_OFFSET));
+        claims.nbf = claims.nbf.map(|nbf| nbf.saturating_add(T::EPOCH_OFFSET));
+        check_numeric_dates(claims)?;
+
+        let (exp_leeway, nbf_leeway) = match T::NBF_LEEWAY {
+            Some(nbf_leeway) => (0, nbf_leeway),
+            None => (validation.leeway, validation.leeway),
+        };
+
+        let now = clock::now();
+        let not_yet_valid = |timestamp: Option<i64>| {
+            let valid_in = timestamp?.saturating_sub(now.saturating_add(nbf_leeway as i64));
+            (valid_in > 0).then(|| Error::NotYetValid {
+                valid_in: Duration::from_secs(valid_in as u64),
+            })
+        };
+
+        if validate_exp && claims.exp < now.saturating_sub(exp_leeway as i64) {
+            return Err(Error::Expired);
+        }
+        if validate_nbf {
+            if let Some(err) = not_yet_valid(claims.nbf) {
+                return Err(err);
+            }
+        }
+        if T::NBF_LEEWAY.is_some() {
+            if let Some(err) = not_yet_valid(claims.iat) {
+                return Err(err);
+            }
+        }
+
+        Ok(token_data)
+    }
+}
+
+/// Surrounding whitespace is trimmed, e.g. from a token copied out of a log,
+/// then [`ClaimsSubTrait::pre_decode`] applied
+impl<T: ClaimsSubTrait> From<String> for Claims<T, Encoded> {
+    fn from(value: String) -> Self {
+        let value = match T::pre_decode(trim_token::<T>(&value)) {
+            Cow::Borrowed(token) if token.len() == value.len() => value,
+            token => token.into_owned(),
+        };
+
+        Self {
+            claims: Encoded(value),
+            _type: PhantomData,
+        }
+    }
+}
+
+/// A token that has been verified, together with its claims
+#[derive(Debug)]
+pub struct VerifiedToken<T: ClaimsSubTrait> {
+    token: String,
+    claims: Decoded<T>,
+}
+
+impl<T: ClaimsSubTrait> VerifiedToken<T> {
+    /// Verify `token`, as `decode` would.
+    pub fn verify(token: String) -> Result<Self>
+    where
+        T: DecodingClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        let inner = T::pre_decode(&token);
+        T::validate_token_format(&inner)?;
+
+        let key = ClaimsEncoded::<T>::decoding_key_for(&inner)?;
+        let (_, claims) = ClaimsEncoded::<T>::decode_token(&inner, &key)?;
+
+        Ok(Self {
+            token,
+            claims: claims.claims,
+        })
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn claims(&self) -> &Decoded<T> {
+        &self.claims
+    }
+
+    pub fn sub(&self) -> &T {
+        &self.claims.sub
+    }
+
+    pub fn into_parts(self) -> (String, Decoded<T>) {
+        (self.token, self.claims)
+    }
+
+    /// The original token, unlike [`Claims::reencode_preserving`] without
+    /// signing it again, which could differ byte-wise.
+    pub fn to_encoded(self) -> ClaimsEncoded<T> {
+        Claims::from(self.token)
+    }
+
+    /// The claims, still retaining the token for [`Claims::as_bearer`]
+    pub fn into_claims(self) -> ClaimsDecoded<T> {
+        Claims {
+            claims: Decoded {
+                token: Some(Encoded(self.token)),
+                ..self.claims
+            },
+            _type: PhantomData,
+        }
+    }
+}
+
+/// Fails with the first failing check, see [`Error::step`] for which one
+impl<T> TryFrom<String> for ClaimsDecoded<T>
+where
+    T: DecodingClaims,
+    Decoded<T>: for<'a> Deserialize<'a>,
+{
+    type Error = Error;
+
+    fn try_from(token: String) -> std::result::Result<Self, Self::Error> {
+        let claims: ClaimsEncoded<T> = From::from(token);
+        let claims = claims.decode()?;
+
+        Ok(claims)
+    }
+}
+
+/// Decodes a borrowed token without copying it into a `String`
+impl<'a, T> TryFrom<Cow<'a, str>> for ClaimsDecoded<T>
+where
+    T: DecodingClaims,
+    Decoded<T>: for<'de> Deserialize<'de>,
+{
+    type Error = Error;
+
+    fn try_from(token: Cow<'a, str>) -> std::result::Result<Self, Self::Error> {
+        match token {
+            Cow::Borrowed(token) => {
+                let token = T::pre_decode(trim_token::<T>(token));
+                T::validate_token_format(&token)?;
+
+                let key = ClaimsEncoded::<T>::decoding_key_for(&token)?;
+                let (_, claims) = ClaimsEncoded::<T>::decode_token(&token, &key)?;
+
+                Ok(claims)
+            }
+            Cow::Owned(token) => token.try_into(),
+        }
+    }
+}
+
+/// [`Claims::reencode_preserving`], the claims are signed again so the token
+/// may differ byte-wise from the one they were decoded from, see
+/// [`VerifiedToken`] to keep that one.
+impl<T: EncodingClaims> TryFrom<ClaimsDecoded<T>> for ClaimsEncoded<T> {
+    type Error = Error;
+
+    fn try_from(claims: ClaimsDecoded<T>) -> std::result::Result<Self, Self::Error> {
+        claims.reencode_preserving()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+    use testing::TEST_SECRET;
+
+    const DEFAULT: u32 = 0;
+    const EXPIRED_TOKEN: u32 = 1;
+    const DIFFRENT_SECRETS: u32 = 2;
+    const SUBJECT_ID: u32 = 3;
+
+    // A struct for testing purposes
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    pub struct GenericTestSub<const TEST: u32> {
+        id: String,
+        name: String,
+    }
+
+    impl<const TEST: u32> fmt::Display for GenericTestSub<TEST> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{} ({})", self.name, self.id)
+        }
+    }
+
+    impl ClaimsSubTrait for GenericTestSub<DEFAULT> {
+        const DURATION: u64 = 60 * 60; // 1 hour
+    }
+
+    impl SymmetricClaims for GenericTestSub<DEFAULT> {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    impl ClaimsSubTrait for GenericTestSub<EXPIRED_TOKEN> {
+        const DURATION: u64 = 1; // 1 second
+    }
+
+    impl SymmetricClaims for GenericTestSub<EXPIRED_TOKEN> {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+    impl ClaimsSubTrait for GenericTestSub<DIFFRENT_SECRETS> {
+        const DURATION: u64 = 60 * 60; // 1 hour
+    }
+
+    impl SymmetricClaims for GenericTestSub<DIFFRENT_SECRETS> {
+        fn secret<'a>() -> &'a [u8] {
+            b"different_test_secret_at_32_bytes"
+        }
+    }
+
+    impl ClaimsSubTrait for GenericTestSub<SUBJECT_ID> {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn subject_id(&self) -> Option<String> {
+            Some(format!("user|{}", self.id))
+        }
+    }
+
+    impl SymmetricClaims for GenericTestSub<SUBJECT_ID> {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+    struct IssuerTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for IssuerTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn validation() -> Validation {
+            let mut validation = Validation::new(Self::ALGORITHM);
+            validation.set_issuer(&["test_issuer"]);
+            validation.set_required_spec_claims(&["exp", "iss"]);
+            validation
+        }
+    }
+
+    impl SymmetricClaims for IssuerTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+    struct CompressedTestSub<const COMPRESS: bool> {
+        id: String,
+        description: String,
+    }
+
+    impl<const COMPRESS: bool> ClaimsSubTrait for CompressedTestSub<COMPRESS> {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const COMPRESS: bool = COMPRESS;
+    }
+
+    impl<const COMPRESS: bool> SymmetricClaims for CompressedTestSub<COMPRESS> {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    static AUDIT_LOG: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+    struct AuditTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for AuditTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn on_decode(decoded: &Decoded<Self>) {
+            AUDIT_LOG.lock().unwrap().push(decoded.sub.id.clone());
+        }
+    }
+
+    impl SymmetricClaims for AuditTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    static EXPIRED_COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+    struct ExpiryHookTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for ExpiryHookTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn on_expired() {
+            EXPIRED_COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    impl SymmetricClaims for ExpiryHookTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct DelegationTestSub {
+        id: String,
+        parent: Option<String>,
+    }
+
+    impl ClaimsSubTrait for DelegationTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn parent_jti(&self) -> Option<&str> {
+            self.parent.as_deref()
+        }
+    }
+
+    impl SymmetricClaims for DelegationTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    static KEY_LOOKUPS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct KeyLookupTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for KeyLookupTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+    }
+
+    impl EncodingClaims for KeyLookupTestSub {
+        fn encoding_key() -> EncodingKey {
+            EncodingKey::from_secret(TEST_SECRET)
+        }
+    }
+
+    impl DecodingClaims for KeyLookupTestSub {
+        fn decoding_key() -> DecodingKey {
+            KEY_LOOKUPS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            DecodingKey::from_secret(TEST_SECRET)
+        }
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+    struct MigrationTestSub<const VERSION: u32> {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for MigrationTestSub<1> {
+        const DURATION: u64 = 60 * 60; // 1 hour
+    }
+
+    impl SymmetricClaims for MigrationTestSub<1> {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    impl ClaimsSubTrait for MigrationTestSub<2> {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const ALGORITHM: Algorithm = Algorithm::HS512;
+
+        fn accepted_algorithms() -> Vec<Algorithm> {
+            vec![Algorithm::HS256, Algorithm::HS512]
+        }
+    }
+
+    impl SymmetricClaims for MigrationTestSub<2> {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    fn issuer_token(sub: &IssuerTestSub, iss: &str) -> String {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let decoded = Decoded {
+            sub: sub.clone(),
+            subject_id: None,
+            iss: Some(iss.to_string()),
+            nbf: None,
+            exp: now + IssuerTestSub::DURATION as i64,
+            iat: Some(now),
+            sv: 0,
+            nonce: None,
+            aud: Vec::new(),
+            jti: None,
+            fingerprint: None,
+            scope: None,
+            token: None,
+        };
+        jsonwebtoken::encode(
+            &Header::default(),
+            &decoded,
+            &EncodingKey::from_secret(IssuerTestSub::secret()),
+        )
+        .unwrap()
+    }
+
+    static RUNTIME_ISSUER: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct RuntimeIssuerTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for RuntimeIssuerTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn issuer() -> Option<String> {
+            RUNTIME_ISSUER.get().cloned()
+        }
+    }
+
+    impl SymmetricClaims for RuntimeIssuerTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct AudienceTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for AudienceTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn audiences() -> AudienceSet {
+            // As read from a config file
+            "api, admin".split(", ").collect()
+        }
+    }
+
+    impl SymmetricClaims for AudienceTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    static RUNTIME_AUDIENCES: RuntimeAudiences = RuntimeAudiences::new();
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct RuntimeAudienceTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for RuntimeAudienceTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn audiences() -> AudienceSet {
+            ["static"].into_iter().collect()
+        }
+
+        fn audiences_runtime() -> Vec<String> {
+            RUNTIME_AUDIENCES.get().to_vec()
+        }
+    }
+
+    impl SymmetricClaims for RuntimeAudienceTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct AudienceMatchTestSub<const ALL: bool> {
+        id: String,
+    }
+
+    impl<const ALL: bool> ClaimsSubTrait for AudienceMatchTestSub<ALL> {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const AUDIENCE_MATCH: AudienceMatch = if ALL {
+            AudienceMatch::All
+        } else {
+            AudienceMatch::Any
+        };
+
+        fn audiences() -> AudienceSet {
+            ["api", "admin"].into_iter().collect()
+        }
+    }
+
+    impl<const ALL: bool> SymmetricClaims for AudienceMatchTestSub<ALL> {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    impl From<MigrationTestSub<1>> for MigrationTestSub<2> {
+        fn from(sub: MigrationTestSub<1>) -> Self {
+            Self { id: sub.id }
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct EnvelopeTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for EnvelopeTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn post_encode(token: String) -> String {
+            format!("v1:{}", token)
+        }
+
+        fn pre_decode(token: &str) -> Cow<'_, str> {
+            Cow::Borrowed(token.strip_prefix("v1:").unwrap_or(token))
+        }
+    }
+
+    impl SymmetricClaims for EnvelopeTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct HeaderTestSub<const COMPRESS: bool> {
+        id: String,
+    }
+
+    impl<const COMPRESS: bool> ClaimsSubTrait for HeaderTestSub<COMPRESS> {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const COMPRESS: bool = COMPRESS;
+
+        fn additional_headers() -> BTreeMap<String, Value> {
+            BTreeMap::from([
+                ("x-env".to_string(), Value::from("prod")),
+                ("alg".to_string(), Value::from("none")),
+            ])
+        }
+    }
+
+    impl<const COMPRESS: bool> SymmetricClaims for HeaderTestSub<COMPRESS> {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct BoundTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for BoundTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn fingerprint() -> Option<String> {
+            Some("203.0.113.7".to_string())
+        }
+    }
+
+    impl SymmetricClaims for BoundTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct ChainTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for ChainTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn certificate_chain() -> Vec<Vec<u8>> {
+            vec![b"leaf".to_vec(), b"root".to_vec()]
+        }
+    }
+
+    impl SymmetricClaims for ChainTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct NoIatTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for NoIatTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const INCLUDE_IAT: bool = false;
+    }
+
+    impl SymmetricClaims for NoIatTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    static BLOCKED: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+    struct BlockableTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for BlockableTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn validate_sub(&self) -> std::result::Result<(), String> {
+            if BLOCKED.lock().unwrap().contains(&self.id) {
+                return Err(format!("user {} is blocked", self.id));
+            }
+            Ok(())
+        }
+    }
+
+    impl SymmetricClaims for BlockableTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct EpochTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for EpochTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const EPOCH_OFFSET: i64 = 946_684_800; // 2000-01-01
+    }
+
+    impl SymmetricClaims for EpochTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct NbfLeewayTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for NbfLeewayTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const NBF_LEEWAY: Option<u64> = Some(10);
+
+        fn validation() -> Validation {
+            let mut validation = Validation::new(Self::ALGORITHM);
+            validation.validate_nbf = true;
+            validation
+        }
+    }
+
+    impl SymmetricClaims for NbfLeewayTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct LeewayTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for LeewayTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn validation() -> Validation {
+            let mut validation = Validation::new(Self::ALGORITHM);
+            validation.leeway = 30;
+            validation
+        }
+    }
+
+    impl SymmetricClaims for LeewayTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct TypTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for TypTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const EXPECTED_TYP: Option<&'static str> = Some("at+jwt");
+    }
+
+    impl SymmetricClaims for TypTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct MaxAgeTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for MaxAgeTestSub {
+        const DURATION: u64 = 24 * 60 * 60; // 1 day
+        const MAX_AGE: Option<u64> = Some(60 * 60);
+    }
+
+    impl SymmetricClaims for MaxAgeTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct SecretVersionTestSub<const VERSION: u32> {
+        id: String,
+    }
+
+    impl<const VERSION: u32> ClaimsSubTrait for SecretVersionTestSub<VERSION> {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const SECRET_VERSION: u32 = VERSION;
+        const MIN_SECRET_VERSION: u32 = 2;
+    }
+
+    impl<const VERSION: u32> SymmetricClaims for SecretVersionTestSub<VERSION> {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct RotationTestSub<const VERSION: u32> {
+        id: String,
+    }
+
+    fn rotation_secret(version: u32) -> &'static [u8] {
+        match version {
+            0 => b"rotation_test_secret_version_000",
+            1 => b"rotation_test_secret_version_001",
+            _ => b"rotation_test_secret_version_002",
+        }
+    }
+
+    impl<const VERSION: u32> ClaimsSubTrait for RotationTestSub<VERSION> {
+        const DURATION: u64 = 24 * 60 * 60; // 1 day
+        const SECRET_VERSION: u32 = VERSION;
+        const ROTATION_GRACE: u64 = 60 * 60; // 1 hour
+    }
+
+    impl<const VERSION: u32> SymmetricClaims for RotationTestSub<VERSION> {
+        fn secret<'a>() -> &'a [u8] {
+            rotation_secret(VERSION)
+        }
+
+        fn decoding_secrets() -> Vec<(u32, Zeroizing<Vec<u8>>)> {
+            (0..VERSION)
+                .map(|version| (version, Zeroizing::new(rotation_secret(version).to_vec())))
+                .collect()
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct QuotesTestSub<const TOLERANT: bool> {
+        id: String,
+    }
+
+    impl<const TOLERANT: bool> ClaimsSubTrait for QuotesTestSub<TOLERANT> {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const TOLERANT_QUOTES: bool = TOLERANT;
+    }
+
+    impl<const TOLERANT: bool> SymmetricClaims for QuotesTestSub<TOLERANT> {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct WeakSecretTestSub<const LEN: usize> {
+        id: String,
+    }
+
+    impl<const LEN: usize> ClaimsSubTrait for WeakSecretTestSub<LEN> {
+        const DURATION: u64 = 60 * 60; // 1 hour
+    }
+
+    impl<const LEN: usize> SymmetricClaims for WeakSecretTestSub<LEN> {
+        fn secret<'a>() -> &'a [u8] {
+            &[b's'; 64][..LEN]
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct ConfusionTestSub {
+        id: String,
+    }
+
+    fn rsa_public_jwk() -> jsonwebtoken::jwk::Jwk {
+        let jwks: jsonwebtoken::jwk::JwkSet =
+            serde_json::from_str(include_str!("../tests/fixtures/jwks.json")).unwrap();
+        jwks.keys[0].clone()
+    }
+
+    impl ClaimsSubTrait for ConfusionTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const ALGORITHM: Algorithm = Algorithm::RS256;
+
+        // Misconfigured to accept an HMAC algorithm as well
+        fn accepted_algorithms() -> Vec<Algorithm> {
+            vec![Algorithm::RS256, Algorithm::HS256]
+        }
+    }
+
+    impl DecodingClaims for ConfusionTestSub {
+        fn decoding_key() -> DecodingKey {
+            DecodingKey::from_jwk(&rsa_public_jwk()).unwrap()
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct AllowExpiredTestSub<const ALLOW: bool> {
+        id: String,
+    }
+
+    impl<const ALLOW: bool> ClaimsSubTrait for AllowExpiredTestSub<ALLOW> {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const ALLOW_EXPIRED: bool = ALLOW;
+
+        fn validation() -> Validation {
+            let mut validation = Validation::new(Self::ALGORITHM);
+            validation.validate_exp = false;
+            validation
+        }
+    }
+
+    impl<const ALLOW: bool> SymmetricClaims for AllowExpiredTestSub<ALLOW> {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct PreSignTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for PreSignTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn pre_sign_validate(&self) -> std::result::Result<(), String> {
+            if self.id.is_empty() {
+                return Err("empty id".to_string());
+            }
+            Ok(())
+        }
+    }
+
+    impl SymmetricClaims for PreSignTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct NestedTestSub {
+        token: String,
+    }
+
+    impl ClaimsSubTrait for NestedTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const CONTENT_TYPE: Option<&'static str> = Some("JWT");
+    }
+
+    impl SymmetricClaims for NestedTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct RenameTestSub {
+        id: String,
+        // Collides with the renamed `exp`
+        expiry: Option<String>,
+    }
+
+    impl ClaimsSubTrait for RenameTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn rename_registered_claims() -> &'static [(&'static str, &'static str)] {
+            &[("exp", "expiry"), ("iss", "issuer")]
+        }
+
+        fn issuer() -> Option<String> {
+            Some("https://auth.example.com".to_string())
+        }
+    }
+
+    impl SymmetricClaims for RenameTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct NonceTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for NonceTestSub {
+        const DURATION: u64 = 5 * 60; // 5 minutes
+
+        fn generate_nonce() -> Option<String> {
+            static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+            let nonce = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            Some(format!("nonce-{}", nonce))
+        }
+    }
+
+    impl SymmetricClaims for NonceTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Default)]
+    struct MemoryReplayStore(std::sync::Mutex<std::collections::HashSet<String>>);
+
+    impl ReplayStore for MemoryReplayStore {
+        fn check_and_consume(&self, nonce: &str) -> bool {
+            self.0.lock().unwrap().insert(nonce.to_string())
+        }
+    }
+
+    fn payload(token: &str) -> serde_json::Value {
+        let payload = token.split('.').nth(1).unwrap();
+        let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).unwrap();
+        serde_json::from_slice(&payload).unwrap()
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct DurationTestSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for DurationTestSub {
+        fn duration() -> Duration {
+            Duration::from_secs(2 * 60 * 60)
+        }
+    }
+
+    impl SymmetricClaims for DurationTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct FormatTestSub {}
+
+    impl ClaimsSubTrait for FormatTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const MAX_TOKEN_LEN: usize = 64;
+    }
+
+    impl SymmetricClaims for FormatTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            panic!("the token format is checked before verification")
+        }
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let claims: TestSub = TestSub {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        };
+
+        let encoded_claims = Claims::new(claims).unwrap();
+        let decoded_claims: ClaimsDecoded<TestSub> = encoded_claims.token().try_into().unwrap();
+
+        let sub = decoded_claims.sub();
+
+        assert_eq!(sub.id, "123");
+        assert_eq!(sub.name, "John");
+    }
+
+    #[test]
+    fn test_claims() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let claims: TestSub = TestSub {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        };
+
+        let iat_min = OffsetDateTime::now_utc().unix_timestamp();
+        let encoded_claims = Claims::new(claims).unwrap();
+        let iat_max = OffsetDateTime::now_utc().unix_timestamp();
+        let decoded_claims: ClaimsDecoded<TestSub> = encoded_claims.token().try_into().unwrap();
+
+        let claims = decoded_claims.claims();
+
+        assert!(claims.iat.unwrap() >= iat_min);
+        assert!(claims.iat.unwrap() <= iat_max);
+
+        assert_eq!(claims.iat.unwrap() + TestSub::DURATION as i64, claims.exp);
+    }
+
+    // TODO: Find a better way to test this than waiting 62 seconds
+    #[test]
+    fn test_expired_token() {
+        type TestSub = GenericTestSub<EXPIRED_TOKEN>;
+        let claims = TestSub {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        };
+
+        let encoded_claims = Claims::new(claims).unwrap();
+
+        std::thread::sleep(Duration::from_secs(2)); // Wait for token to expire
+        std::thread::sleep(Duration::from_secs(60)); // Wait for the leeway in validation to pass
+
+        let result: Result<ClaimsDecoded<TestSub>> = encoded_claims.token().try_into();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_diffrent_secrets() {
+        type TestSub1 = GenericTestSub<DEFAULT>;
+        type TestSub2 = GenericTestSub<DIFFRENT_SECRETS>;
+
+        let sub1 = TestSub1 {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        };
+        let sub2 = TestSub2 {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        };
+
+        let encoded_claims1_0 = Claims::new(sub1.clone()).unwrap();
+        let encoded_claims1_1 = Claims::new(sub1.clone()).unwrap();
+        let encoded_claims2_0 = Claims::new(sub2.clone()).unwrap();
+        let encoded_claims2_1 = Claims::new(sub2.clone()).unwrap();
+
+        let result: Result<ClaimsDecoded<TestSub1>> = encoded_claims1_0.token().try_into();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().sub(), sub1);
+
+        let result: Result<ClaimsDecoded<TestSub2>> = encoded_claims1_1.token().try_into();
+        assert!(result.is_err());
+
+        let result: Result<ClaimsDecoded<TestSub2>> = encoded_claims2_0.token().try_into();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().sub(), sub2);
+
+        let result: std::result::Result<ClaimsDecoded<TestSub1>, Error> =
+            encoded_claims2_1.token().try_into();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_subject_id() {
+        type TestSub = GenericTestSub<SUBJECT_ID>;
+
+        let sub = TestSub {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        };
+
+        let encoded_claims = Claims::new(sub.clone()).unwrap();
+        let decoded_claims: ClaimsDecoded<TestSub> = encoded_claims.token().try_into().unwrap();
+
+        let claims = decoded_claims.claims();
+        assert_eq!(claims.subject_id(), Some("user|123"));
+        assert_eq!(claims.sub, sub);
+
+        // A token without the registered `sub` claim is rejected
+        let encoded_claims = Claims::new(GenericTestSub::<DEFAULT> {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        })
+        .unwrap();
+        let result: Result<ClaimsDecoded<TestSub>> = encoded_claims.token().try_into();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_len() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let encoded_claims = Claims::new(TestSub {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        })
+        .unwrap();
+
+        assert!(!encoded_claims.is_empty());
+        assert_eq!(encoded_claims.len(), encoded_claims.clone().token().len());
+
+        let empty: ClaimsEncoded<TestSub> = From::from(String::new());
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_validation() {
+        let sub = IssuerTestSub {
+            id: "123".to_string(),
+        };
+        let result: Result<ClaimsDecoded<IssuerTestSub>> =
+            issuer_token(&sub, "test_issuer").try_into();
+        assert_eq!(result.unwrap().sub(), sub);
+
+        let result: Result<ClaimsDecoded<IssuerTestSub>> =
+            issuer_token(&sub, "other_issuer").try_into();
+        assert!(result.is_err());
+
+        // `iss` is required
+        let encoded_claims = Claims::new(sub).unwrap();
+        let result: Result<ClaimsDecoded<IssuerTestSub>> = encoded_claims.token().try_into();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_compressed() {
+        let description = "a very repetitive description ".repeat(50);
+
+        let sub = CompressedTestSub::<true> {
+            id: "123".to_string(),
+            description: description.clone(),
+        };
+        let compressed = Claims::new(sub.clone()).unwrap().token();
+
+        let uncompressed = Claims::new(CompressedTestSub::<false> {
+            id: "123".to_string(),
+            description,
+        })
+        .unwrap()
+        .token();
+
+        assert!(compressed.len() < uncompressed.len());
+
+        let header = jsonwebtoken::decode_header(&compressed);
+        assert!(header.is_ok());
+
+        let result: Result<ClaimsDecoded<CompressedTestSub<true>>> = compressed.try_into();
+        assert_eq!(result.unwrap().sub(), sub);
+
+        // An uncompressed token isn't accepted in place of a compressed one
+        let result: Result<ClaimsDecoded<CompressedTestSub<true>>> = uncompressed.try_into();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_decoded_default() {
+        let decoded = Decoded::<GenericTestSub<DEFAULT>>::default();
+
+        assert_eq!(decoded.sub, GenericTestSub::<DEFAULT>::default());
+        assert_eq!(decoded.subject_id(), None);
+        assert_eq!(decoded.exp, 0);
+        assert_eq!(decoded.iat, Some(0));
+    }
+
+    #[test]
+    fn test_malformed() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let token = Claims::new(TestSub::default()).unwrap().token();
+        let (header, rest) = token.split_once('.').unwrap();
+        let (payload, _signature) = rest.split_once('.').unwrap();
+
+        let bad_header = format!("{}!.{}", header, rest);
+        let result: Result<ClaimsDecoded<TestSub>> = bad_header.try_into();
+        assert!(matches!(
+            result,
+            Err(Error::Malformed {
+                segment: Segment::Header,
+                ..
+            })
+        ));
+
+        let missing_signature = format!("{}.{}", header, payload);
+        let result: Result<ClaimsDecoded<TestSub>> = missing_signature.try_into();
+        assert!(matches!(
+            result,
+            Err(Error::Malformed {
+                segment: Segment::Signature,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_on_decode() {
+        let sub = AuditTestSub {
+            id: "123".to_string(),
+        };
+        let token = Claims::new(sub).unwrap().token();
+
+        let result: Result<ClaimsDecoded<AuditTestSub>> = token.clone().try_into();
+        assert!(result.is_ok());
+        assert_eq!(*AUDIT_LOG.lock().unwrap(), ["123"]);
+
+        let result: Result<ClaimsDecoded<AuditTestSub>> = format!("{}x", token).try_into();
+        assert!(result.is_err());
+        assert_eq!(*AUDIT_LOG.lock().unwrap(), ["123"]);
+
+        let result: Result<ClaimsDecoded<AuditTestSub>> = token.try_into();
+        assert!(result.is_ok());
+        assert_eq!(*AUDIT_LOG.lock().unwrap(), ["123", "123"]);
+    }
+
+    #[test]
+    fn test_accepted_algorithms() {
+        let old_token = Claims::new(MigrationTestSub::<1> {
+            id: "123".to_string(),
+        })
+        .unwrap()
+        .token();
+        let new_token = Claims::new(MigrationTestSub::<2> {
+            id: "456".to_string(),
+        })
+        .unwrap()
+        .token();
+
+        assert_eq!(
+            jsonwebtoken::decode_header(&new_token).unwrap().alg,
+            Algorithm::HS512
+        );
+
+        let result: Result<ClaimsDecoded<MigrationTestSub<2>>> = old_token.try_into();
+        assert_eq!(result.unwrap().sub().id, "123");
+
+        let result: Result<ClaimsDecoded<MigrationTestSub<2>>> = new_token.clone().try_into();
+        assert_eq!(result.unwrap().sub().id, "456");
+
+        // Only HS256 is accepted for the old Subject
+        let result: Result<ClaimsDecoded<MigrationTestSub<1>>> = new_token.try_into();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_decode_diagnostic() {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let decoded = Decoded {
+            sub: IssuerTestSub {
+                id: "123".to_string(),
+            },
+            subject_id: None,
+            iss: Some("other_issuer".to_string()),
+            nbf: None,
+            exp: now - 2 * 60 * 60,
+            iat: Some(now - 3 * 60 * 60),
+            sv: 0,
+            nonce: None,
+            aud: Vec::new(),
+            jti: None,
+            fingerprint: None,
+            scope: None,
+            token: None,
+        };
+        let token = jsonwebtoken::encode(
+            &Header::default(),
+            &decoded,
+            &EncodingKey::from_secret(IssuerTestSub::secret()),
+        )
+        .unwrap();
+
+        let encoded_claims: ClaimsEncoded<IssuerTestSub> = Claims::from(token);
+        let errors = encoded_claims.decode_diagnostic().unwrap_err();
+
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().any(|err| matches!(err, Error::Expired)));
+        assert!(errors.iter().any(|err| matches!(
+            err,
+            Error::Jsonwebtoken(err) if *err.kind() == ErrorKind::InvalidIssuer
+        )));
+
+        let sub = IssuerTestSub {
+            id: "123".to_string(),
+        };
+        let encoded_claims: ClaimsEncoded<IssuerTestSub> =
+            Claims::from(issuer_token(&sub, "test_issuer"));
+        assert!(encoded_claims.decode_diagnostic().is_ok());
+
+        // The token format is checked before anything else
+        let encoded_claims: ClaimsEncoded<IssuerTestSub> = Claims::from("a".repeat(8 * 1024 + 1));
+        let errors = encoded_claims.decode_diagnostic().unwrap_err();
+        assert!(matches!(errors[..], [Error::TokenTooLong { .. }]));
+    }
+
+    #[test]
+    fn test_new_at() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let iat = OffsetDateTime::now_utc().unix_timestamp();
+        let token_at = Claims::new_at(TestSub::default(), iat).unwrap().token();
+        let token = Claims::new(TestSub::default()).unwrap().token();
+
+        let payload_at = payload(&token_at);
+        let payload = payload(&token);
+
+        let keys = |payload: &serde_json::Value| {
+            payload
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(&payload_at), keys(&payload));
+
+        assert_eq!(payload_at["iat"], iat);
+        assert_eq!(payload_at["exp"], iat + TestSub::DURATION as i64);
+
+        let result: Result<ClaimsDecoded<TestSub>> = token_at.try_into();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_duration() {
+        let token = Claims::new(DurationTestSub::default()).unwrap().token();
+
+        let decoded_claims: ClaimsDecoded<DurationTestSub> = token.try_into().unwrap();
+        let claims = decoded_claims.claims();
+
+        assert_eq!(claims.exp - claims.iat.unwrap(), 2 * 60 * 60);
+    }
+
+    #[test]
+    fn test_verify_claim() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let sub = TestSub {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        };
+        let token = Claims::new(sub).unwrap().token();
+        let decoded_claims: ClaimsDecoded<TestSub> = token.try_into().unwrap();
+
+        assert!(decoded_claims.verify_claim("name", &"John").unwrap());
+        assert!(!decoded_claims.verify_claim("name", &"Jane").unwrap());
+        assert!(!decoded_claims.verify_claim("role", &"admin").unwrap());
+    }
+
+    #[test]
+    fn test_validate_token_format() {
+        let result: Result<ClaimsDecoded<FormatTestSub>> = "garbage".to_string().try_into();
+        assert!(matches!(result, Err(Error::Malformed { .. })));
+
+        let result: Result<ClaimsDecoded<FormatTestSub>> = "a.b.c".repeat(20).try_into();
+        assert!(matches!(
+            result,
+            Err(Error::TokenTooLong { len: 100, max: 64 })
+        ));
+    }
+
+    #[test]
+    fn test_encoded_hash() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let encoded_claims1 = Claims::new(TestSub::default()).unwrap();
+        let encoded_claims2 = Claims::new(TestSub {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        })
+        .unwrap();
+
+        let mut cache = std::collections::HashMap::new();
+        cache.insert(encoded_claims1.clone(), 1);
+        cache.insert(encoded_claims2.clone(), 2);
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache[&encoded_claims1], 1);
+        assert_eq!(cache[&encoded_claims2], 2);
+        assert_ne!(encoded_claims1, encoded_claims2);
+    }
+
+    #[test]
+    fn test_slide() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let sub = TestSub {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        };
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let encoded_claims = Claims::new_at(sub.clone(), now - 10 * 60).unwrap();
+        let old_exp = payload(&encoded_claims.clone().token())["exp"]
+            .as_i64()
+            .unwrap();
+
+        let slid = encoded_claims
+            .slide(Duration::from_secs(2 * 60 * 60))
+            .unwrap();
+        let decoded_claims = slid.decode().unwrap();
+
+        assert!(decoded_claims.claims.exp > old_exp);
+        assert!(decoded_claims.claims.exp >= now + 2 * 60 * 60);
+        assert_eq!(decoded_claims.sub(), sub);
+
+        // Tokens that don't verify can't be slid
+        let encoded_claims: ClaimsEncoded<TestSub> = Claims::from("a.b.c".to_string());
+        assert!(encoded_claims.slide(Duration::from_secs(60)).is_err());
+    }
+
+    #[test]
+    fn test_runtime_issuer() {
+        RUNTIME_ISSUER.get_or_init(|| "https://auth.example.com".to_string());
+
+        let sub = RuntimeIssuerTestSub {
+            id: "123".to_string(),
+        };
+        let token = Claims::new(sub.clone()).unwrap().token();
+        assert_eq!(payload(&token)["iss"], "https://auth.example.com");
+
+        let decoded_claims: ClaimsDecoded<RuntimeIssuerTestSub> = token.try_into().unwrap();
+        let decoded = decoded_claims.claims();
+        assert_eq!(decoded.issuer(), Some("https://auth.example.com"));
+        assert_eq!(decoded.sub, sub);
+
+        // Tokens from any other issuer are rejected
+        let decoded = Decoded {
+            sub,
+            subject_id: None,
+            iss: Some("https://other.example.com".to_string()),
+            nbf: None,
+            exp: OffsetDateTime::now_utc().unix_timestamp() + 60,
+            iat: Some(OffsetDateTime::now_utc().unix_timestamp()),
+            sv: 0,
+            nonce: None,
+            aud: Vec::new(),
+            jti: None,
+            fingerprint: None,
+            scope: None,
+            token: None,
+        };
+        let token = jsonwebtoken::encode(
+            &Header::default(),
+            &decoded,
+            &EncodingKey::from_secret(RuntimeIssuerTestSub::secret()),
+        )
+        .unwrap();
+        let result: Result<ClaimsDecoded<RuntimeIssuerTestSub>> = token.try_into();
+        assert!(matches!(
+            result,
+            Err(Error::Jsonwebtoken(err)) if *err.kind() == ErrorKind::InvalidIssuer
+        ));
+    }
+
+    #[test]
+    fn test_try_sub() {
+        let sub = BlockableTestSub {
+            id: "123".to_string(),
+        };
+        let token = Claims::new(sub.clone()).unwrap().token();
+
+        let decoded_claims: ClaimsDecoded<BlockableTestSub> = token.clone().try_into().unwrap();
+
+        // Blocked after the token was decoded
+        BLOCKED.lock().unwrap().push(sub.id.clone());
+        assert!(matches!(
+            decoded_claims.try_sub(),
+            Err(Error::SubjectRejected(reason)) if reason == "user 123 is blocked"
+        ));
+
+        let result: Result<ClaimsDecoded<BlockableTestSub>> = token.try_into();
+        assert!(matches!(result, Err(Error::SubjectRejected(_))));
+
+        BLOCKED.lock().unwrap().clear();
+        let decoded_claims: ClaimsDecoded<BlockableTestSub> =
+            Claims::new(sub.clone()).unwrap().decode().unwrap();
+        assert_eq!(decoded_claims.try_sub().unwrap(), sub);
+    }
+
+    #[test]
+    fn test_decode_full() {
+        type TestSub = MigrationTestSub<2>;
+
+        let sub = TestSub {
+            id: "123".to_string(),
+        };
+        let encoded_claims = Claims::new(sub.clone()).unwrap();
+        let (header, decoded_claims) = encoded_claims.decode_full().unwrap();
+
+        assert_eq!(header.alg, TestSub::ALGORITHM);
+        assert_eq!(decoded_claims.sub(), sub);
+    }
+
+    #[test]
+    fn test_reencode_preserving() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let sub = TestSub {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        };
+        // In the past, so a freshly minted token would differ
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - 60;
+        let decoded_claims = Claims::new_at(sub.clone(), iat).unwrap().decode().unwrap();
+
+        let decoded = decoded_claims
+            .reencode_preserving()
+            .unwrap()
+            .decode()
+            .unwrap()
+            .claims();
+
+        assert_eq!(decoded.iat, Some(iat));
+        assert_eq!(decoded.exp, iat + TestSub::DURATION as i64);
+        assert_eq!(decoded.sub, sub);
+    }
+
+    #[test]
+    fn test_mint_all() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let subjects = ["1", "2", "3"].map(|id| TestSub {
+            id: id.to_string(),
+            name: "John".to_string(),
+        });
+
+        let tokens = Claims::mint_all(subjects.clone())
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(tokens.len(), 3);
+
+        for (token, sub) in tokens.into_iter().zip(subjects) {
+            let decoded_claims: ClaimsDecoded<TestSub> = token.try_into().unwrap();
+            assert_eq!(decoded_claims.sub(), sub);
+        }
+    }
+
+    #[test]
+    fn test_epoch_offset() {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        let token = Claims::new_at(EpochTestSub::default(), now)
+            .unwrap()
+            .token();
+        let payload = payload(&token);
+        assert_eq!(payload["iat"], now - EpochTestSub::EPOCH_OFFSET);
+        assert_eq!(
+            payload["exp"],
+            now - EpochTestSub::EPOCH_OFFSET + EpochTestSub::DURATION as i64
+        );
+
+        let decoded = Claims::<EpochTestSub, Encoded>::from(token)
+            .decode()
+            .unwrap()
+            .claims();
+        assert_eq!(decoded.iat, Some(now));
+        assert_eq!(decoded.exp, now + EpochTestSub::DURATION as i64);
+
+        let expired = Claims::new_at(EpochTestSub::default(), now - 2 * 60 * 60).unwrap();
+        assert!(matches!(expired.decode(), Err(Error::Expired)));
+    }
+
+    #[test]
+    fn test_try_from_cow() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let token = Claims::new(TestSub::default()).unwrap().token();
+
+        let decoded_claims: ClaimsDecoded<TestSub> =
+            Cow::Borrowed(token.as_str()).try_into().unwrap();
+        assert_eq!(decoded_claims.sub(), TestSub::default());
+
+        let result: Result<ClaimsDecoded<TestSub>> = Cow::Borrowed("not.a.token").try_into();
+        assert!(result.is_err());
+
+        let decoded_claims: ClaimsDecoded<TestSub> = Cow::<str>::Owned(token).try_into().unwrap();
+        assert_eq!(decoded_claims.sub(), TestSub::default());
+    }
+
+    #[test]
+    fn test_decode_optional() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let encoded_claims = Claims::new(TestSub::default()).unwrap();
+        let decoded_claims = encoded_claims.decode_optional().unwrap();
+        assert_eq!(decoded_claims.unwrap().sub(), TestSub::default());
+
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - 2 * 60 * 60;
+        let expired = Claims::new_at(TestSub::default(), iat).unwrap();
+        assert!(expired.decode_optional().unwrap().is_none());
+
+        let tampered: ClaimsEncoded<TestSub> = Claims::from(format!("{}x", expired.token()));
+        assert!(tampered.decode_optional().is_err());
+    }
+
+    #[test]
+    fn test_nbf_leeway() {
+        let token = |nbf: i64, exp: i64| {
+            let decoded = Decoded {
+                sub: NbfLeewayTestSub::default(),
+                subject_id: None,
+                iss: None,
+                nbf: Some(nbf),
+                exp,
+                iat: Some(OffsetDateTime::now_utc().unix_timestamp()),
+                sv: 0,
+                nonce: None,
+                aud: Vec::new(),
+                jti: None,
+                fingerprint: None,
+                scope: None,
+                token: None,
+            };
+            let token = jsonwebtoken::encode(
+                &Header::default(),
+                &decoded,
+                &EncodingKey::from_secret(NbfLeewayTestSub::secret()),
+            )
+            .unwrap();
+            ClaimsEncoded::<NbfLeewayTestSub>::from(token).decode()
+        };
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        // A few seconds ahead is within the leeway
+        assert!(token(now + 5, now + 60).is_ok());
+
+        assert!(matches!(
+            token(now + 30, now + 60),
+            Err(Error::NotYetValid { .. })
+        ));
+
+        // `exp` is strict, where the default leeway would have let this through
+        assert!(matches!(token(now, now - 5), Err(Error::Expired)));
+    }
+
+    #[test]
+    fn test_decoded_display() {
+        let decoded = Decoded {
+            sub: GenericTestSub::<DEFAULT> {
+                id: "123".to_string(),
+                name: "John".to_string(),
+            },
+            subject_id: None,
+            iss: None,
+            nbf: None,
+            exp: 60 * 60,
+            iat: Some(0),
+            sv: 0,
+            nonce: None,
+            aud: Vec::new(),
+            jti: None,
+            fingerprint: None,
+            scope: None,
+            token: None,
+        };
+
+        assert_eq!(
+            decoded.to_string(),
+            "John (123) (issued 1970-01-01T00:00:00Z, expires 1970-01-01T01:00:00Z)"
+        );
+    }
+
+    #[test]
+    fn test_verified_token() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let sub = TestSub {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        };
+        let token = Claims::new(sub.clone()).unwrap().token();
+
+        let verified_token = VerifiedToken::<TestSub>::verify(token.clone()).unwrap();
+        assert_eq!(verified_token.token(), token);
+        assert_eq!(verified_token.sub(), &sub);
+
+        let (raw, decoded) = verified_token.into_parts();
+        assert_eq!(raw, token);
+        assert_eq!(decoded.sub, sub);
+
+        assert!(VerifiedToken::<TestSub>::verify(format!("{}x", token)).is_err());
+    }
+
+    #[test]
+    fn test_decode_arbitrary_input() {
+        // xorshift, so failures are reproducible
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let alphabet = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_.=";
+        let valid = Claims::new(GenericTestSub::<DEFAULT>::default())
+            .unwrap()
+            .token();
+
+        for i in 0..2000 {
+            let len = (next() % 256) as usize;
+            let token = match i % 3 {
+                // Arbitrary bytes
+                0 => {
+                    let bytes: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+                    String::from_utf8_lossy(&bytes).into_owned()
+                }
+                // Token-like characters, to get past the format checks
+                1 => (0..len)
+                    .map(|_| alphabet[next() as usize % alphabet.len()] as char)
+                    .collect(),
+                // A valid token with a few bytes flipped
+                _ => {
+                    let mut bytes = valid.clone().into_bytes();
+                    for _ in 0..1 + next() % 4 {
+                        let i = next() as usize % bytes.len();
+                        bytes[i] = alphabet[next() as usize % alphabet.len()];
+                    }
+                    String::from_utf8(bytes).unwrap()
+                }
+            };
+
+            let result: Result<ClaimsDecoded<GenericTestSub<DEFAULT>>> = token.clone().try_into();
+            assert!(result.is_err() || token == valid, "{token}");
+            let result: Result<ClaimsDecoded<CompressedTestSub<true>>> = token.try_into();
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn test_validate_and_extract() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let encoded_claims = Claims::new(TestSub {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        })
+        .unwrap();
+
+        let id = encoded_claims
+            .clone()
+            .validate_and_extract(|sub| sub.id.clone());
+        assert_eq!(id.unwrap(), "123");
+
+        let tampered: ClaimsEncoded<TestSub> = Claims::from(format!("{}x", encoded_claims.token()));
+        let result = tampered.validate_and_extract(|sub| sub.id.clone());
+        assert!(matches!(
+            result,
+            Err(Error::Jsonwebtoken(err)) if *err.kind() == ErrorKind::InvalidSignature
+        ));
+    }
+
+    #[test]
+    fn test_expected_typ() {
+        let encoded_claims = Claims::new(TypTestSub::default()).unwrap();
+        let header = jsonwebtoken::decode_header(&encoded_claims.clone().token()).unwrap();
+        assert_eq!(header.typ.as_deref(), Some("at+jwt"));
+        assert!(encoded_claims.decode().is_ok());
+
+        let token = |typ: Option<&str>| {
+            let header = Header {
+                typ: typ.map(str::to_string),
+                ..Header::default()
+            };
+            let now = OffsetDateTime::now_utc().unix_timestamp();
+            let decoded = Decoded {
+                sub: TypTestSub::default(),
+                subject_id: None,
+                iss: None,
+                nbf: None,
+                exp: now + 60,
+                iat: Some(now),
+                sv: 0,
+                nonce: None,
+                aud: Vec::new(),
+                jti: None,
+                fingerprint: None,
+                scope: None,
+                token: None,
+            };
+            let token = jsonwebtoken::encode(
+                &header,
+                &decoded,
+                &EncodingKey::from_secret(TypTestSub::secret()),
+            )
+            .unwrap();
+            ClaimsEncoded::<TypTestSub>::from(token).decode()
+        };
+
+        assert!(matches!(
+            token(Some("JWT")),
+            Err(Error::UnexpectedTokenType { expected: "at+jwt", found: Some(found) }) if found == "JWT"
+        ));
+        assert!(matches!(
+            token(None),
+            Err(Error::UnexpectedTokenType { found: None, .. })
+        ));
+    }
+
+    #[test]
+    fn test_signing_input() {
+        let encoded_claims = Claims::new(GenericTestSub::<DEFAULT>::default()).unwrap();
+
+        let signing_input = encoded_claims.signing_input().unwrap();
+        let token = encoded_claims.token();
+        let signature = token.rsplit('.').next().unwrap();
+        assert_eq!(format!("{}.{}", signing_input, signature), token);
+
+        let malformed: ClaimsEncoded<GenericTestSub<DEFAULT>> = Claims::from("garbage".to_string());
+        assert!(matches!(
+            malformed.signing_input(),
+            Err(Error::Malformed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_max_age() {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        let encoded_claims = Claims::new_at(MaxAgeTestSub::default(), now - 30 * 60).unwrap();
+        assert!(encoded_claims.decode().is_ok());
+
+        // Valid for another 22 hours, but issued too long ago
+        let encoded_claims = Claims::new_at(MaxAgeTestSub::default(), now - 2 * 60 * 60).unwrap();
+        let result = encoded_claims.decode();
+        assert!(matches!(
+            result,
+            Err(Error::TooOld { age, max_age: 3600 }) if age >= 2 * 60 * 60
+        ));
+        assert_eq!(result.unwrap_err().code(), "expired");
+    }
+
+    #[test]
+    fn test_age() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let decoded_claims = Claims::new(TestSub::default()).unwrap().decode().unwrap();
+        assert!(decoded_claims.age().unwrap() < Duration::from_secs(5));
+
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - 10 * 60;
+        let decoded_claims = Claims::new_at(TestSub::default(), iat)
+            .unwrap()
+            .decode()
+            .unwrap();
+        assert_eq!(
+            decoded_claims.age_at(iat + 10 * 60),
+            Some(Duration::from_secs(10 * 60))
+        );
+        assert!(decoded_claims.age().unwrap() >= Duration::from_secs(10 * 60));
+
+        // Issued in the future
+        assert_eq!(decoded_claims.age_at(iat - 60), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_decode_with_report() {
+        let ttl = LeewayTestSub::duration().as_secs() as i64;
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - ttl - 10;
+
+        // Only valid thanks to the leeway
+        let encoded_claims = Claims::new_at(LeewayTestSub::default(), iat).unwrap();
+        let (decoded_claims, report) = encoded_claims.decode_with_report().unwrap();
+        assert_eq!(decoded_claims.claims().iat, Some(iat));
+        assert_eq!(
+            report,
+            ValidationReport {
+                leeway_used: 30,
+                algorithms: vec![Algorithm::HS256],
+                checked_exp: true,
+                checked_nbf: false,
+            }
+        );
+
+        let encoded_claims = Claims::new(NbfLeewayTestSub::default()).unwrap();
+        let (_, report) = encoded_claims.decode_with_report().unwrap();
+        assert_eq!(report.leeway_used, 0);
+        assert!(report.checked_nbf);
+    }
+
+    #[test]
+    fn test_algorithm_none() {
+        let token = Claims::new(GenericTestSub::<DEFAULT>::default())
+            .unwrap()
+            .token();
+        let payload = token.split('.').nth(1).unwrap();
+
+        for alg in ["none", "None"] {
+            let header = format!(r#"{{"alg":"{alg}","typ":"JWT"}}"#);
+            let header = base64::encode_config(header, base64::URL_SAFE_NO_PAD);
+            let unsecured = format!("{header}.{payload}.");
+
+            let encoded_claims: ClaimsEncoded<GenericTestSub<DEFAULT>> = Claims::from(unsecured);
+            assert!(matches!(encoded_claims.decode(), Err(Error::AlgorithmNone)));
+        }
+    }
+
+    #[test]
+    fn test_peek_kid() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let encoded_claims = Claims::new(TestSub::default()).unwrap();
+        assert_eq!(encoded_claims.peek_kid().unwrap(), None);
+
+        let header = Header {
+            kid: Some("key-1".to_string()),
+            ..Header::default()
+        };
+        let token = jsonwebtoken::encode(
+            &header,
+            &payload(&encoded_claims.token()),
+            &EncodingKey::from_secret(b"other_secret"),
+        )
+        .unwrap();
+
+        // Not verified, the token isn't signed with the secret of `TestSub`
+        let encoded_claims: ClaimsEncoded<TestSub> = Claims::from(token);
+        assert_eq!(encoded_claims.peek_kid().unwrap().as_deref(), Some("key-1"));
+        assert!(encoded_claims.decode().is_err());
+    }
+
+    #[test]
+    fn test_secret_version() {
+        let token = Claims::new(SecretVersionTestSub::<2>::default())
+            .unwrap()
+            .token();
+        assert_eq!(payload(&token)["sv"], 2);
+
+        let encoded_claims: ClaimsEncoded<SecretVersionTestSub<2>> = Claims::from(token);
+        let decoded_claims = encoded_claims.decode().unwrap();
+        assert_eq!(decoded_claims.claims().secret_version(), 2);
+
+        let token = Claims::new(SecretVersionTestSub::<1>::default())
+            .unwrap()
+            .token();
+        let encoded_claims: ClaimsEncoded<SecretVersionTestSub<2>> = Claims::from(token);
+        assert!(matches!(
+            encoded_claims.decode(),
+            Err(Error::OutdatedSecret { version: 1, min: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_token_data() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let sub = TestSub {
+            id: "123".to_string(),
+            ..Default::default()
+        };
+        let token = Claims::new(sub.clone()).unwrap().token();
+
+        let token_data = ClaimsEncoded::<TestSub>::from(token.clone())
+            .into_token_data()
+            .unwrap();
+        assert_eq!(token_data.header.alg, TestSub::ALGORITHM);
+        assert_eq!(token_data.claims.sub, sub);
+
+        let token_data = jsonwebtoken::decode::<Decoded<TestSub>>(
+            &token,
+            &TestSub::decoding_key(),
+            &TestSub::validation(),
+        )
+        .unwrap();
+        let decoded_claims = ClaimsDecoded::from_token_data(token_data, token);
+        assert_eq!(decoded_claims.sub(), sub);
+    }
+
+    #[test]
+    fn test_surrounding_whitespace() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let token = Claims::new(TestSub::default()).unwrap().token();
+
+        let wrapped = format!("\n  {}\r\n", token);
+        let decoded_claims: Result<ClaimsDecoded<TestSub>> = wrapped.clone().try_into();
+        assert!(decoded_claims.is_ok());
+        let decoded_claims: Result<ClaimsDecoded<TestSub>> =
+            Cow::Borrowed(wrapped.as_str()).try_into();
+        assert!(decoded_claims.is_ok());
+        assert_eq!(ClaimsEncoded::<TestSub>::from(wrapped).token(), token);
+
+        let (header, rest) = token.split_at(token.find('.').unwrap() + 5);
+        let decoded_claims: Result<ClaimsDecoded<TestSub>> =
+            format!("{} {}", header, rest).try_into();
+        assert!(matches!(
+            decoded_claims,
+            Err(Error::Malformed { segment: Segment::Payload, reason }) if reason == "contains whitespace"
+        ));
+    }
+
+    #[test]
+    fn test_decode_once() {
+        let store = MemoryReplayStore::default();
+
+        let encoded_claims = Claims::new(NonceTestSub::default()).unwrap();
+        let decoded_claims = encoded_claims.clone().decode_once(&store).unwrap();
+        let nonce = decoded_claims.claims().nonce.unwrap();
+
+        let result = encoded_claims.clone().decode_once(&store);
+        assert!(matches!(result, Err(Error::Replayed(replayed)) if replayed == nonce));
+        // Still a valid token otherwise
+        assert!(encoded_claims.decode().is_ok());
+
+        let encoded_claims = Claims::new(NonceTestSub::default()).unwrap();
+        assert!(encoded_claims.decode_once(&store).is_ok());
+
+        let encoded_claims = Claims::new(GenericTestSub::<DEFAULT>::default()).unwrap();
+        assert!(matches!(
+            encoded_claims.decode_once(&store),
+            Err(Error::Jsonwebtoken(err)) if matches!(err.kind(), ErrorKind::MissingRequiredClaim(claim) if claim == "nonce")
+        ));
+    }
+
+    #[test]
+    fn test_subject_json() {
+        let sub = GenericTestSub::<SUBJECT_ID> {
+            id: "123".to_string(),
+            name: "John Doe".to_string(),
+        };
+        let decoded_claims = Claims::new(sub).unwrap().decode().unwrap();
+
+        let json = decoded_claims.subject_json().unwrap();
+        assert_eq!(json, r#"{"id":"123","name":"John Doe"}"#);
+
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        for claim in ["sub", "exp", "iat", "sv"] {
+            assert!(json.get(claim).is_none());
+        }
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_subject_yaml() {
+        let sub = GenericTestSub::<SUBJECT_ID> {
+            id: "123".to_string(),
+            name: "John Doe".to_string(),
+        };
+        let decoded_claims = Claims::new(sub).unwrap().decode().unwrap();
+
+        let yaml = decoded_claims.subject_yaml().unwrap();
+        assert_eq!(yaml, "id: '123'\nname: John Doe\n");
+        assert!(!yaml.contains("exp"));
+    }
+
+    #[test]
+    fn test_map_jwt_error() {
+        let err = jsonwebtoken::errors::Error::from(ErrorKind::ExpiredSignature);
+        let err = map_jwt_error(err);
+        assert!(matches!(err, Error::Expired));
+        assert_eq!(err.code(), "expired");
+
+        let err = map_jwt_error(ErrorKind::InvalidSignature.into());
+        assert!(matches!(
+            err,
+            Error::Jsonwebtoken(err) if *err.kind() == ErrorKind::InvalidSignature
+        ));
+    }
+
+    #[test]
+    fn test_verify_detached() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let sign = |header: &str, message: &[u8]| {
+            let header = base64::encode_config(header, base64::URL_SAFE_NO_PAD);
+            let mut signing_input = format!("{}.", header).into_bytes();
+            signing_input.extend(message);
+            let signature = jsonwebtoken::crypto::sign(
+                &signing_input,
+                &TestSub::encoding_key(),
+                Algorithm::HS256,
+            )
+            .unwrap();
+            (header, signature)
+        };
+        let payload = br#"{"event":"push"}"#;
+
+        let (header, signature) = sign(r#"{"alg":"HS256","b64":false,"crit":["b64"]}"#, payload);
+        assert!(ClaimsEncoded::<TestSub>::verify_detached(&header, payload, &signature).is_ok());
+
+        let tampered = br#"{"event":"pull"}"#;
+        assert!(matches!(
+            ClaimsEncoded::<TestSub>::verify_detached(&header, tampered, &signature),
+            Err(Error::Jsonwebtoken(err)) if *err.kind() == ErrorKind::InvalidSignature
+        ));
+
+        let encoded_payload = base64::encode_config(payload, base64::URL_SAFE_NO_PAD);
+        let (header, signature) = sign(r#"{"alg":"HS256"}"#, encoded_payload.as_bytes());
+        assert!(ClaimsEncoded::<TestSub>::verify_detached(&header, payload, &signature).is_ok());
+
+        let (header, signature) = sign(r#"{"alg":"HS512","b64":false,"crit":["b64"]}"#, payload);
+        assert!(matches!(
+            ClaimsEncoded::<TestSub>::verify_detached(&header, payload, &signature),
+            Err(Error::Jsonwebtoken(err)) if *err.kind() == ErrorKind::InvalidAlgorithm
+        ));
+
+        // `b64` has to be critical
+        let (header, signature) = sign(r#"{"alg":"HS256","b64":false}"#, payload);
+        assert!(matches!(
+            ClaimsEncoded::<TestSub>::verify_detached(&header, payload, &signature),
+            Err(Error::Malformed {
+                segment: Segment::Header,
+                ..
+            })
+        ));
+
+        // Algorithms outside the family are rejected, though accepted
+        let (header, signature) = sign(r#"{"alg":"HS256","b64":false,"crit":["b64"]}"#, payload);
+        assert!(matches!(
+            ClaimsEncoded::<ConfusionTestSub>::verify_detached(&header, payload, &signature),
+            Err(Error::Jsonwebtoken(err)) if *err.kind() == ErrorKind::InvalidAlgorithm
+        ));
+    }
+
+    #[test]
+    fn test_guarded_key() {
+        static ZEROIZED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+        struct Witness(Vec<u8>);
+
+        impl Zeroize for Witness {
+            fn zeroize(&mut self) {
+                self.0.zeroize();
+                ZEROIZED.store(true, std::sync::atomic::Ordering::SeqCst);
+            }
+        }
+
+        impl AsRef<[u8]> for Witness {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let secret = Zeroizing::new(Witness(TestSub::secret().to_vec()));
+        let key = guarded_key(secret, |secret| {
+            assert!(!ZEROIZED.load(std::sync::atomic::Ordering::SeqCst));
+            EncodingKey::from_secret(secret)
+        });
+        assert!(ZEROIZED.load(std::sync::atomic::Ordering::SeqCst));
+
+        let token = Claims::at(
+            TestSub::default(),
+            OffsetDateTime::now_utc().unix_timestamp(),
+            TestSub::duration(),
+        )
+        .encode_with_key(&key)
+        .unwrap();
+        assert!(token.decode().is_ok());
+    }
+
+    #[test]
+    fn test_decode_set() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let sub = |id: &str| TestSub {
+            id: id.to_string(),
+            ..Default::default()
+        };
+        let first = Claims::new(sub("1")).unwrap().token();
+        let second = Claims::new(sub("2")).unwrap().token();
+
+        let input = format!("{}|{}", first, second);
+        let decoded_claims = ClaimsDecoded::<TestSub>::decode_set(&input, '|').unwrap();
+        let subs: Vec<_> = decoded_claims.into_iter().map(Claims::sub).collect();
+        assert_eq!(subs, [sub("1"), sub("2")]);
+
+        let input = format!("{}|garbage", first);
+        assert!(ClaimsDecoded::<TestSub>::decode_set(&input, '|').is_err());
+
+        let results = ClaimsDecoded::<TestSub>::decode_set_lenient(&input, '|');
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(Error::Malformed { .. })));
+    }
+
+    #[test]
+    fn test_token_hash() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let encoded_claims = Claims::new(TestSub::default()).unwrap();
+        let hash = encoded_claims.token_hash();
+        assert_eq!(hash.len(), 32);
+        assert_eq!(hash, encoded_claims.clone().token_hash());
+        assert!(!encoded_claims.clone().token().starts_with(&hash));
+
+        let other = Claims::new(TestSub {
+            id: "other".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_ne!(other.token_hash(), hash);
+
+        // Known SHA-256 of "abc"
+        let encoded_claims: ClaimsEncoded<TestSub> = Claims::from("abc".to_string());
+        assert_eq!(
+            encoded_claims.token_hash(),
+            "ba7816bf8f01cfea414140de5dae2223"
+        );
+    }
+
+    #[test]
+    fn test_inconsistent_timestamps() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let token = |iat: i64, nbf: Option<i64>| {
+            let decoded = Decoded {
+                nbf,
+                exp: now + 60 * 60,
+                iat: Some(iat),
+                ..Decoded::<TestSub>::default()
+            };
+            let token =
+                jsonwebtoken::encode(&Header::default(), &decoded, &TestSub::encoding_key())
+                    .unwrap();
+            ClaimsEncoded::<TestSub>::from(token).decode()
+        };
+
+        assert!(token(now, Some(now)).is_ok());
+
+        // `exp` is still in the future
+        assert!(matches!(
+            token(now + 2 * 60 * 60, None),
+            Err(Error::InconsistentTimestamps(reason)) if reason.contains("exp")
+        ));
+        assert!(matches!(
+            token(now, Some(now - 60)),
+            Err(Error::InconsistentTimestamps(reason)) if reason.contains("nbf")
+        ));
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_zeroize_on_drop() {
+        fn zeroize_on_drop<Z: zeroize::ZeroizeOnDrop>(_: &Z) {}
+
+        // The freed buffer can't be inspected, check it's zeroized on drop instead
+        let encoded_claims = Claims::new(GenericTestSub::<DEFAULT>::default()).unwrap();
+        zeroize_on_drop(&encoded_claims.claims);
+
+        // Moved out before the wrapper is dropped, so left intact
+        let token = encoded_claims.clone().token();
+        assert_eq!(token.len(), encoded_claims.len());
+        assert!(ClaimsEncoded::<GenericTestSub<DEFAULT>>::from(token)
+            .decode()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_audience() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let decoded_claims = Claims::builder(TestSub::default())
+            .audience("billing")
+            .build()
+            .unwrap()
+            .decode()
+            .unwrap();
+        assert_eq!(decoded_claims.primary_audience(), Some("billing"));
+        assert!(decoded_claims.has_audience("billing"));
+        assert!(!decoded_claims.has_audience("search"));
+
+        let token = decoded_claims.reencode_preserving().unwrap().token();
+        assert_eq!(payload(&token)["aud"], "billing");
+
+        let encoded_claims = Claims::builder(TestSub::default())
+            .audience("search")
+            .audience("billing")
+            .build()
+            .unwrap();
+        assert_eq!(
+            payload(&encoded_claims.clone().token())["aud"],
+            serde_json::json!(["search", "billing"])
+        );
+
+        let decoded_claims = encoded_claims.decode().unwrap();
+        assert_eq!(decoded_claims.primary_audience(), Some("search"));
+        assert!(decoded_claims.has_audience("billing"));
+
+        let decoded_claims = Claims::new(TestSub::default()).unwrap().decode().unwrap();
+        assert_eq!(decoded_claims.primary_audience(), None);
+        assert!(!decoded_claims.has_audience("billing"));
+    }
+
+    #[test]
+    fn test_decode_expecting_sub() {
+        type TestSub = GenericTestSub<SUBJECT_ID>;
+
+        let encoded_claims = Claims::new(TestSub {
+            id: "123".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let decoded_claims = encoded_claims.decode_expecting_sub("user|123").unwrap();
+        assert_eq!(decoded_claims.claims().subject_id(), Some("user|123"));
+
+        assert!(matches!(
+            encoded_claims.decode_expecting_sub("user|456"),
+            Err(Error::Jsonwebtoken(err)) if *err.kind() == ErrorKind::InvalidSubject
+        ));
+
+        // Without any `sub` claim at all
+        let encoded_claims = Claims::new(GenericTestSub::<DEFAULT>::default()).unwrap();
+        assert!(matches!(
+            encoded_claims.decode_expecting_sub("user|123"),
+            Err(Error::Jsonwebtoken(err)) if matches!(err.kind(), ErrorKind::MissingRequiredClaim(claim) if claim == "sub")
+        ));
+    }
+
+    #[test]
+    fn test_to_encoded() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        // Issued elsewhere, with the claims in a different order than ours
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let payload = format!(
+            r#"{{"iat":{},"exp":{},"name":"John","id":"123"}}"#,
+            now,
+            now + 60
+        );
+        let token = jsonwebtoken::encode(
+            &Header::default(),
+            &serde_json::from_str::<Box<serde_json::value::RawValue>>(&payload).unwrap(),
+            &TestSub::encoding_key(),
+        )
+        .unwrap();
+
+        let verified_token = ClaimsEncoded::<TestSub>::from(token.clone())
+            .decode_retaining()
+            .unwrap();
+        assert_eq!(verified_token.sub().name, "John");
+        assert_eq!(verified_token.to_encoded().token(), token);
+
+        let reencoded = ClaimsEncoded::<TestSub>::from(token.clone())
+            .decode()
+            .unwrap()
+            .reencode_preserving()
+            .unwrap();
+        assert_ne!(reencoded.token(), token);
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<S: Send + Sync>(_: &S) {}
+
+        #[derive(Debug, Default, Serialize, Deserialize)]
+        struct NotSyncSub {
+            id: String,
+            #[serde(skip)]
+            cache: std::cell::Cell<u8>,
+        }
+
+        impl ClaimsSubTrait for NotSyncSub {
+            const DURATION: u64 = 60 * 60; // 1 hour
+        }
+
+        impl SymmetricClaims for NotSyncSub {
+            fn secret<'a>() -> &'a [u8] {
+                TEST_SECRET
+            }
+        }
+
+        let encoded_claims = Claims::new(NotSyncSub::default()).unwrap();
+        assert_send_sync(&encoded_claims);
+
+        let encoded_claims = std::thread::spawn(move || encoded_claims).join().unwrap();
+        assert_eq!(encoded_claims.decode().unwrap().claims().sub.cache.get(), 0);
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        #[derive(Debug, Serialize, Deserialize)]
+        struct EmailSub {
+            email: String,
+        }
+
+        impl ClaimsSubTrait for EmailSub {
+            const DURATION: u64 = 60 * 60; // 1 hour
+
+            fn canonicalize(self) -> Self {
+                Self {
+                    email: self.email.to_lowercase(),
+                }
+            }
+        }
+
+        impl SymmetricClaims for EmailSub {
+            fn secret<'a>() -> &'a [u8] {
+                TEST_SECRET
+            }
+        }
+
+        let encoded_claims = Claims::new(EmailSub {
+            email: "John.Doe@Example.com".to_string(),
+        })
+        .unwrap();
+        assert_eq!(
+            payload(&encoded_claims.clone().token())["email"],
+            "john.doe@example.com"
+        );
+        assert_eq!(
+            encoded_claims.decode().unwrap().sub().email,
+            "john.doe@example.com"
+        );
+    }
+
+    #[test]
+    fn test_error_body() {
+        let error = Error::Expired;
+
+        let (body, content_type) = error.error_body(ErrorFormat::Json);
+        assert_eq!(body, r#"{"error":"expired"}"#);
+        assert_eq!(content_type, "application/json");
+
+        let (body, content_type) = error.error_body(ErrorFormat::PlainText);
+        assert_eq!(body, "expired");
+        assert_eq!(content_type, "text/plain; charset=utf-8");
+
+        assert_eq!(
+            ErrorFormat::from_accept("text/plain"),
+            ErrorFormat::PlainText
+        );
+        assert_eq!(
+            ErrorFormat::from_accept("text/html, text/plain;q=0.9, application/json;q=0.8"),
+            ErrorFormat::PlainText
+        );
+        assert_eq!(
+            ErrorFormat::from_accept("application/json, text/plain"),
+            ErrorFormat::Json
+        );
+        assert_eq!(ErrorFormat::from_accept("*/*"), ErrorFormat::Json);
+    }
+
+    #[test]
+    fn test_is_expired_unverified() {
+        fn check<T>(encoded_claims: ClaimsEncoded<T>, expired: bool)
+        where
+            T: DecodingClaims,
+            Decoded<T>: DeserializeOwned,
+        {
+            assert_eq!(encoded_claims.is_expired_unverified().unwrap(), expired);
+            assert_eq!(encoded_claims.decode().unwrap().is_expired(), expired);
+        }
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        // Expired 10 seconds ago, within the leeway so it still decodes
+        let expired_at = now - 10 - 60 * 60;
+
+        check(
+            Claims::new(GenericTestSub::<DEFAULT>::default()).unwrap(),
+            false,
+        );
+        check(
+            Claims::new_at(GenericTestSub::<DEFAULT>::default(), expired_at).unwrap(),
+            true,
+        );
+        check(Claims::new(EpochTestSub::default()).unwrap(), false);
+        check(
+            Claims::new_at(EpochTestSub::default(), expired_at).unwrap(),
+            true,
+        );
+
+        let sub = CompressedTestSub::<true> {
+            id: "123".to_string(),
+            description: "a".repeat(100),
+        };
+        check(Claims::new_at(sub, expired_at).unwrap(), true);
+
+        // Not verified, so the signature doesn't matter
+        let token = Claims::new_at(GenericTestSub::<DEFAULT>::default(), expired_at)
+            .unwrap()
+            .token();
+        let encoded_claims: ClaimsEncoded<GenericTestSub<DEFAULT>> =
+            Claims::from(format!("{}x", token));
+        assert!(encoded_claims.is_expired_unverified().unwrap());
+    }
+
+    #[test]
+    fn test_decode_skip_expiry() {
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - 2 * 60 * 60;
+        let encoded_claims = Claims::new_at(GenericTestSub::<DEFAULT>::default(), iat).unwrap();
+
+        let decoded_claims = encoded_claims.decode_skip_expiry(true).unwrap();
+        assert!(decoded_claims.is_expired());
+
+        assert!(matches!(
+            encoded_claims.decode_skip_expiry(false),
+            Err(Error::Expired)
+        ));
+
+        // Everything else is still checked
+        let encoded_claims: ClaimsEncoded<GenericTestSub<DIFFRENT_SECRETS>> =
+            Claims::from(encoded_claims.token());
+        assert!(encoded_claims.decode_skip_expiry(true).is_err());
+    }
+
+    #[test]
+    fn test_decode_fresh() {
+        let encoded_claims = Claims::new(GenericTestSub::<DEFAULT>::default()).unwrap();
+        assert!(encoded_claims
+            .decode_fresh(Duration::from_secs(5 * 60))
+            .is_ok());
+
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - 10 * 60;
+        let encoded_claims = Claims::new_at(GenericTestSub::<DEFAULT>::default(), iat).unwrap();
+        assert!(matches!(
+            encoded_claims.decode_fresh(Duration::from_secs(5 * 60)),
+            Err(Error::TooOld { age, max_age: 300 }) if age >= 10 * 60
+        ));
+    }
+
+    #[test]
+    fn test_audience_set() {
+        let audiences = AudienceTestSub::audiences();
+        assert_eq!(audiences.len(), 2);
+        assert!(audiences.contains("api"));
+        assert!(!audiences.contains("billing"));
+
+        let mut extended = audiences.clone();
+        extended.extend(["billing".to_string(), "api".to_string()]);
+        assert_eq!(
+            extended.iter().collect::<Vec<_>>(),
+            ["admin", "api", "billing"]
+        );
+
+        let encoded_claims = Claims::builder(AudienceTestSub::default())
+            .audience("admin")
+            .build()
+            .unwrap();
+        assert!(encoded_claims.decode().is_ok());
+
+        let encoded_claims = Claims::builder(AudienceTestSub::default())
+            .audience("billing")
+            .build()
+            .unwrap();
+        assert!(encoded_claims.decode().is_err());
+
+        let encoded_claims = Claims::new(AudienceTestSub::default()).unwrap();
+        assert!(encoded_claims.decode().is_err());
+    }
+
+    #[test]
+    fn test_resign() {
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - 60;
+        let sub = MigrationTestSub::<1> {
+            id: "123".to_string(),
+        };
+        let encoded_claims = Claims::new_at(sub, iat).unwrap();
+        let exp = encoded_claims.clone().decode().unwrap().claims().exp;
+
+        let resigned: ClaimsEncoded<MigrationTestSub<2>> = encoded_claims.resign().unwrap();
+        let header = jsonwebtoken::decode_header(&resigned.clone().token()).unwrap();
+        assert_eq!(header.alg, Algorithm::HS512);
+
+        let decoded = resigned.decode().unwrap().claims();
+        assert_eq!(decoded.sub.id, "123");
+        assert_eq!(decoded.iat, Some(iat));
+        assert_eq!(decoded.exp, exp);
+
+        // The token must verify for the old Subject first
+        let forged: ClaimsEncoded<MigrationTestSub<1>> = Claims::from(format!(
+            "{}x",
+            Claims::new(MigrationTestSub::<1> {
+                id: "123".to_string(),
+            })
+            .unwrap()
+            .token()
+        ));
+        assert!(forged.resign::<MigrationTestSub<2>>().is_err());
+    }
+
+    #[test]
+    fn test_same_subject() {
+        let sub = GenericTestSub::<DEFAULT> {
+            id: "123".to_string(),
+            name: "Test".to_string(),
+        };
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - 60;
+        let old = Claims::new_at(sub.clone(), iat).unwrap().decode().unwrap();
+        let new = Claims::new(sub).unwrap().decode().unwrap();
+        assert!(old.same_subject(&new));
+
+        let other = Claims::new(GenericTestSub::<DEFAULT>::default())
+            .unwrap()
+            .decode()
+            .unwrap();
+        assert!(!old.same_subject(&other));
+    }
+
+    #[test]
+    fn test_secure_eq() {
+        let encoded_claims = Claims::new(GenericTestSub::<DEFAULT>::default()).unwrap();
+        let same = encoded_claims.clone();
+        assert!(encoded_claims.secure_eq(&same));
+        assert_eq!(encoded_claims.secure_eq(&same), encoded_claims == same);
+
+        let other: ClaimsEncoded<GenericTestSub<DEFAULT>> =
+            Claims::from(format!("{}x", encoded_claims.clone().token()));
+        assert!(!encoded_claims.secure_eq(&other));
+        assert_eq!(encoded_claims.secure_eq(&other), encoded_claims == other);
+    }
+
+    #[test]
+    fn test_token_envelope() {
+        let sub = EnvelopeTestSub {
+            id: "123".to_string(),
+        };
+        let token = Claims::new(sub.clone()).unwrap().token();
+        assert!(token.starts_with("v1:ey"));
+
+        let encoded_claims: ClaimsEncoded<EnvelopeTestSub> = Claims::from(token.clone());
+        assert_eq!(encoded_claims.clone().token(), token);
+        assert_eq!(encoded_claims.decode().unwrap().sub(), sub);
+
+        let decoded_claims = ClaimsDecoded::<EnvelopeTestSub>::try_from(Cow::Borrowed(&*token));
+        assert_eq!(decoded_claims.unwrap().sub(), sub);
+
+        let verified = VerifiedToken::<EnvelopeTestSub>::verify(token.clone()).unwrap();
+        assert_eq!(verified.token(), token);
+    }
+
+    #[test]
+    fn test_into_parts_json() {
+        let encoded_claims = Claims::new(GenericTestSub::<DEFAULT>::default()).unwrap();
+        let token = encoded_claims.clone().token();
+
+        let (header, payload, signature) = encoded_claims.into_parts_json().unwrap();
+        assert_eq!(header["alg"], "HS256");
+        assert!(payload["exp"].is_i64());
+        assert_eq!(token.rsplit('.').next(), Some(signature.as_str()));
+
+        let sub = CompressedTestSub::<true> {
+            id: "123".to_string(),
+            description: "test".to_string(),
+        };
+        let (header, payload, _) = Claims::new(sub).unwrap().into_parts_json().unwrap();
+        assert_eq!(header["zip"], "DEF");
+        assert_eq!(payload["id"], "123");
+    }
+
+    #[test]
+    fn test_decode_with_grace() {
+        let grace = Duration::from_secs(10 * 60);
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        let encoded_claims = Claims::new(GenericTestSub::<DEFAULT>::default()).unwrap();
+        let (_, in_grace) = encoded_claims.decode_with_grace(grace).unwrap();
+        assert!(!in_grace);
+
+        // Expired 5 minutes ago, beyond the leeway of a minute
+        let iat = now - 60 * 60 - 5 * 60;
+        let encoded_claims = Claims::new_at(GenericTestSub::<DEFAULT>::default(), iat).unwrap();
+        let (_, in_grace) = encoded_claims.decode_with_grace(grace).unwrap();
+        assert!(in_grace);
+        assert!(matches!(encoded_claims.decode(), Err(Error::Expired)));
+
+        // Expired 20 minutes ago
+        let iat = now - 60 * 60 - 20 * 60;
+        let encoded_claims = Claims::new_at(GenericTestSub::<DEFAULT>::default(), iat).unwrap();
+        assert!(matches!(
+            encoded_claims.decode_with_grace(grace),
+            Err(Error::Expired)
+        ));
+    }
+
+    #[test]
+    fn test_additional_headers() {
+        fn check<const COMPRESS: bool>() {
+            let sub = HeaderTestSub::<COMPRESS> {
+                id: "123".to_string(),
+            };
+            let encoded_claims = Claims::new(sub.clone()).unwrap();
+
+            let (header, decoded_claims) = encoded_claims.decode_full_json().unwrap();
+            assert_eq!(header["x-env"], "prod");
+            assert_eq!(header["alg"], "HS256");
+            assert_eq!(decoded_claims.sub(), sub);
+        }
+
+        check::<false>();
+        check::<true>();
+    }
+
+    #[test]
+    fn test_require() {
+        let sub = GenericTestSub::<SUBJECT_ID> {
+            id: "123".to_string(),
+            name: "Test".to_string(),
+        };
+        let decoded_claims = Claims::builder(sub)
+            .audience("api")
+            .issuer("https://auth.example.com")
+            .build()
+            .unwrap()
+            .decode()
+            .unwrap();
+
+        let checked = || -> Result<()> {
+            decoded_claims
+                .require_sub_present()?
+                .require_audience("api")?
+                .require_issuer("https://auth.example.com")?
+                .require_not_expired()?;
+            Ok(())
+        };
+        assert!(checked().is_ok());
+
+        let result = decoded_claims
+            .require_sub_present()
+            .and_then(|claims| claims.require_audience("admin"))
+            .and_then(|claims| claims.require_not_expired());
+        assert!(matches!(result, Err(err) if err.code() == "invalid_audience"));
+
+        let decoded_claims = Claims::new(GenericTestSub::<DEFAULT>::default())
+            .unwrap()
+            .decode()
+            .unwrap();
+        let result = decoded_claims.require_sub_present();
+        assert!(matches!(result, Err(err) if err.code() == "missing_claim"));
+    }
+
+    #[test]
+    fn test_decode_bound() {
+        let encoded_claims = Claims::new(BoundTestSub::default()).unwrap();
+        assert_eq!(
+            payload(&encoded_claims.clone().token())["cnf"]["fp"],
+            "203.0.113.7"
+        );
+
+        let decoded_claims = encoded_claims.decode_bound("203.0.113.7").unwrap();
+        assert_eq!(decoded_claims.claims().fingerprint(), Some("203.0.113.7"));
+
+        assert!(matches!(
+            encoded_claims.decode_bound("198.51.100.1"),
+            Err(Error::FingerprintMismatch)
+        ));
+
+        // Unbound tokens match no fingerprint
+        let encoded_claims = Claims::new(GenericTestSub::<DEFAULT>::default()).unwrap();
+        assert!(matches!(
+            encoded_claims.decode_bound(""),
+            Err(Error::FingerprintMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_decode_from_reader() {
+        let tokens: Vec<String> = ["1", "2", "3"]
+            .iter()
+            .map(|id| {
+                let sub = GenericTestSub::<DEFAULT> {
+                    id: id.to_string(),
+                    name: "Test".to_string(),
+                };
+                Claims::new(sub).unwrap().token()
+            })
+            .collect();
+        let input = format!("{}\n{}\n\n{}\n", tokens[0], tokens[1], tokens[2]);
+
+        let ids: Vec<String> = ClaimsDecoded::<GenericTestSub<DEFAULT>>::decode_from_reader(
+            std::io::Cursor::new(input),
+        )
+        .map(|decoded_claims| decoded_claims.unwrap().sub().id)
+        .collect();
+        assert_eq!(ids, ["1", "2", "3"]);
+
+        let input = format!("{}\nnot a token\n", tokens[0]);
+        let results: Vec<_> =
+            ClaimsDecoded::<GenericTestSub<DEFAULT>>::decode_from_reader(input.as_bytes())
+                .collect();
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(Error::Malformed { .. })));
+    }
+
+    #[test]
+    fn test_prefix() {
+        let encoded_claims = Claims::new(GenericTestSub::<DEFAULT>::default()).unwrap();
+
+        let stored = encoded_claims.clone().with_prefix("session:");
+        assert_eq!(
+            stored,
+            format!("session:{}", encoded_claims.clone().token())
+        );
+
+        let restored =
+            ClaimsEncoded::<GenericTestSub<DEFAULT>>::strip_prefix(&stored, "session:").unwrap();
+        assert_eq!(restored, encoded_claims);
+        assert!(restored.decode().is_ok());
+
+        let result = ClaimsEncoded::<GenericTestSub<DEFAULT>>::strip_prefix(&stored, "refresh:");
+        assert!(matches!(result, Err(Error::MissingPrefix(prefix)) if prefix == "refresh:"));
+    }
+
+    #[test]
+    fn test_omit_iat() {
+        let token = Claims::new(NoIatTestSub::default()).unwrap().token();
+        let payload = payload(&token);
+        assert!(payload.get("iat").is_none());
+        assert!(payload["exp"].is_i64());
+
+        let decoded_claims: ClaimsDecoded<NoIatTestSub> = token.clone().try_into().unwrap();
+        assert_eq!(decoded_claims.age(), None);
+        assert!(decoded_claims.claims().iat.is_none());
+
+        // Expiry is still checked
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - 2 * 60 * 60;
+        let encoded_claims = Claims::new_at(NoIatTestSub::default(), iat).unwrap();
+        assert!(matches!(encoded_claims.decode(), Err(Error::Expired)));
+
+        // Subjects including it still require it
+        let encoded_claims: ClaimsEncoded<MigrationTestSub<1>> = Claims::from(token);
+        assert!(encoded_claims.decode().is_err());
+    }
+
+    #[test]
+    fn test_claims_as() {
+        #[derive(Deserialize)]
+        struct Flat {
+            id: String,
+            exp: i64,
+            iss: Option<String>,
+        }
+
+        let sub = GenericTestSub::<DEFAULT> {
+            id: "123".to_string(),
+            name: "Test".to_string(),
+        };
+        let decoded_claims = Claims::new(sub).unwrap().decode().unwrap();
+
+        let flat: Flat = decoded_claims.claims_as().unwrap();
+        assert_eq!(flat.id, "123");
+        assert_eq!(flat.exp, decoded_claims.claims().exp);
+        assert_eq!(flat.iss, None);
+    }
+
+    #[test]
+    fn test_certificate_chain() {
+        let encoded_claims = Claims::new(ChainTestSub::default()).unwrap();
+        let (header, _) = encoded_claims.decode_full().unwrap();
+        assert_eq!(
+            header.x5c,
+            Some(vec![base64::encode("leaf"), base64::encode("root")])
+        );
+
+        // No `x5c` without a chain
+        let encoded_claims = Claims::new(NoIatTestSub::default()).unwrap();
+        let (header, _) = encoded_claims.decode_full().unwrap();
+        assert_eq!(header.x5c, None);
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let iat = OffsetDateTime::now_utc().unix_timestamp() + 120;
+        let result = Claims::new_at(NbfLeewayTestSub::default(), iat)
+            .unwrap()
+            .decode();
+        let err = result.unwrap_err();
+        assert!(matches!(err, Error::NotYetValid { .. }));
+        assert_eq!(err.code(), "not_yet_valid");
+
+        // Valid once `iat` is within the leeway
+        let retry_after = err.retry_after().unwrap();
+        let leeway = NbfLeewayTestSub::NBF_LEEWAY.unwrap();
+        assert!(retry_after <= Duration::from_secs(120 - leeway));
+        assert!(retry_after >= Duration::from_secs(120 - leeway - 5));
+
+        assert_eq!(Error::Expired.retry_after(), None);
+        assert_eq!(Error::AlgorithmNone.retry_after(), None);
+    }
+
+    #[test]
+    fn test_secret_rotation() {
+        fn rotated(token: ClaimsEncoded<impl ClaimsSubTrait>) -> ClaimsEncoded<RotationTestSub<2>> {
+            Claims::from(token.token())
+        }
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        // Issued with the newest secret
+        let encoded_claims = Claims::new(RotationTestSub::<2>::default()).unwrap();
+        assert_eq!(payload(&encoded_claims.clone().token())["sv"], 2);
+        assert!(encoded_claims.decode().is_ok());
+
+        // The previous secret is accepted within the window
+        let encoded_claims = Claims::new(RotationTestSub::<1>::default()).unwrap();
+        assert_eq!(rotated(encoded_claims).decode().unwrap().claims().sv, 1);
+
+        let encoded_claims = Claims::new_at(RotationTestSub::<1>::default(), now - 2 * 60 * 60);
+        assert!(matches!(
+            rotated(encoded_claims.unwrap()).decode(),
+            Err(Error::OutdatedSecret { version: 1, min: 2 })
+        ));
+
+        // Older ones not at all, though their secret is known
+        let encoded_claims = Claims::new(RotationTestSub::<0>::default()).unwrap();
+        assert!(matches!(
+            rotated(encoded_claims).decode(),
+            Err(Error::OutdatedSecret { version: 0, min: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_tolerant_quotes() {
+        let token = Claims::new(QuotesTestSub::<true>::default())
+            .unwrap()
+            .token();
+        let quoted = format!(" \"{}\"\n", token);
+
+        let encoded_claims: ClaimsEncoded<QuotesTestSub<true>> = Claims::from(quoted.clone());
+        assert_eq!(encoded_claims.clone().token(), token);
+        assert!(encoded_claims.decode().is_ok());
+
+        // Only a matched pair is stripped
+        let encoded_claims: ClaimsEncoded<QuotesTestSub<true>> =
+            Claims::from(format!("\"{}", token));
+        assert!(encoded_claims.decode().is_err());
+
+        let encoded_claims: ClaimsEncoded<QuotesTestSub<false>> = Claims::from(quoted);
+        assert!(encoded_claims.decode().is_err());
+    }
+
+    #[test]
+    fn test_decode_cost_hint() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let encoded_claims = Claims::new(TestSub::default()).unwrap();
+        assert_eq!(encoded_claims.decode_cost_hint(), DecodeCost::Cheap);
+
+        let key =
+            EncodingKey::from_rsa_pem(include_bytes!("../tests/fixtures/rsa_private.pem")).unwrap();
+        let token = jsonwebtoken::encode(
+            &Header::new(Algorithm::RS256),
+            &payload(&encoded_claims.token()),
+            &key,
+        )
+        .unwrap();
+        let encoded_claims: ClaimsEncoded<TestSub> = Claims::from(token);
+        assert_eq!(encoded_claims.decode_cost_hint(), DecodeCost::Expensive);
+
+        let encoded_claims: ClaimsEncoded<TestSub> = Claims::from("not a token".to_string());
+        assert_eq!(encoded_claims.decode_cost_hint(), DecodeCost::Cheap);
+    }
+
+    #[test]
+    fn test_audience_match() {
+        fn decode<const ALL: bool>(audiences: &[&str]) -> bool {
+            let mut builder = Claims::builder(AudienceMatchTestSub::<ALL>::default());
+            for aud in audiences {
+                builder = builder.audience(*aud);
+            }
+            builder.build().unwrap().decode().is_ok()
+        }
+
+        assert!(decode::<false>(&["api", "admin", "billing"]));
+        assert!(decode::<true>(&["api", "admin", "billing"]));
+
+        // Partial overlap
+        assert!(decode::<false>(&["admin", "billing"]));
+        assert!(!decode::<true>(&["admin", "billing"]));
+
+        assert!(!decode::<false>(&["billing"]));
+        assert!(!decode::<true>(&["billing"]));
+    }
+
+    #[test]
+    fn test_try_from_decoded() {
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - 60;
+        let sub = GenericTestSub::<DEFAULT> {
+            id: "123".to_string(),
+            name: "Test".to_string(),
+        };
+        let decoded_claims = Claims::new_at(sub.clone(), iat).unwrap().decode().unwrap();
+        let exp = decoded_claims.claims.exp;
+
+        let encoded_claims: ClaimsEncoded<_> = decoded_claims.try_into().unwrap();
+        let decoded = encoded_claims.decode().unwrap().claims();
+        assert_eq!(decoded.sub, sub);
+        assert_eq!(decoded.iat, Some(iat));
+        assert_eq!(decoded.exp, exp);
+    }
+
+    #[test]
+    fn test_scopes() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let encoded_claims = Claims::builder(TestSub::default())
+            .scope("read")
+            .scope("write admin")
+            .build()
+            .unwrap();
+        assert_eq!(
+            payload(&encoded_claims.clone().token())["scope"],
+            "read write admin"
+        );
+
+        let decoded_claims = encoded_claims.decode().unwrap();
+        assert_eq!(decoded_claims.scopes(), ["read", "write", "admin"]);
+        assert!(decoded_claims.has_scope("write"));
+        assert!(!decoded_claims.has_scope("delete"));
+
+        let decoded_claims = Claims::new(TestSub::default()).unwrap().decode().unwrap();
+        assert!(decoded_claims.scopes().is_empty());
+        assert!(!decoded_claims.has_scope("read"));
+    }
+
+    #[test]
+    fn test_weak_secret() {
+        let result = Claims::new(WeakSecretTestSub::<6>::default());
+        assert!(matches!(result, Err(Error::WeakSecret { len: 6, min: 32 })));
+        assert_eq!(result.unwrap_err().code(), "internal");
+
+        let token = Claims::new(WeakSecretTestSub::<32>::default())
+            .unwrap()
+            .token();
+        let encoded_claims: ClaimsEncoded<WeakSecretTestSub<32>> = Claims::from(token.clone());
+        assert!(encoded_claims.decode().is_ok());
+
+        // Verifying with a weak secret is refused as well
+        let encoded_claims: ClaimsEncoded<WeakSecretTestSub<6>> = Claims::from(token);
+        assert!(matches!(
+            encoded_claims.decode(),
+            Err(Error::WeakSecret { len: 6, min: 32 })
+        ));
+    }
+
+    #[test]
+    fn test_split_storage() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let encoded_claims = Claims::new(TestSub::default()).unwrap();
+        let token = encoded_claims.clone().token();
+
+        let (header_payload, signature) = encoded_claims.split_storage().unwrap();
+        assert_eq!(header_payload.matches('.').count(), 1);
+        assert!(token.ends_with(&format!(".{}", signature)));
+
+        let rejoined: ClaimsEncoded<TestSub> = Claims::rejoin((header_payload.clone(), signature));
+        assert_eq!(rejoined.clone().token(), token);
+        assert!(rejoined.decode().is_ok());
+
+        let forged: ClaimsEncoded<TestSub> = Claims::rejoin((header_payload, "forged".to_string()));
+        assert!(forged.decode().is_err());
+    }
+
+    #[test]
+    fn test_algorithm_family() {
+        assert_eq!(ConfusionTestSub::algorithm_family(), AlgorithmFamily::Rsa);
+
+        let sub = GenericTestSub::<DEFAULT>::default();
+        let claims = payload(&Claims::new(sub).unwrap().token());
+        let token = |header: Header, key: EncodingKey| {
+            let token = jsonwebtoken::encode(&header, &claims, &key).unwrap();
+            ClaimsEncoded::<ConfusionTestSub>::from(token)
+        };
 
-pub trait ClaimsSubTrait: Serialize {
-    /// The time before expiry from now in seconds
-    const DURATION: u64;
+        let rsa_key =
+            EncodingKey::from_rsa_pem(include_bytes!("../tests/fixtures/rsa_private.pem"));
+        let encoded_claims = token(Header::new(Algorithm::RS256), rsa_key.unwrap());
+        assert!(encoded_claims.decode().is_ok());
 
-    /// The secret key used for encoding and decoding the Subject.
-    fn secret<'a>() -> &'a [u8];
-}
+        // Signed with the public key as the HMAC secret
+        let public_key = serde_json::to_vec(&rsa_public_jwk()).unwrap();
+        let forged = token(
+            Header::new(Algorithm::HS256),
+            EncodingKey::from_secret(&public_key),
+        );
+        assert!(matches!(
+            forged.decode(),
+            Err(Error::Jsonwebtoken(err)) if *err.kind() == ErrorKind::InvalidAlgorithm
+        ));
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Encoded(String);
+    #[test]
+    fn test_allow_expired() {
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - 2 * 60 * 60;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Decoded<T: ClaimsSubTrait> {
-    #[serde(flatten)]
-    pub(crate) sub: T,
-    exp: i64,
-    iat: i64,
-}
+        // Turning off `validate_exp` alone isn't enough
+        let encoded_claims = Claims::new_at(AllowExpiredTestSub::<false>::default(), iat).unwrap();
+        assert!(matches!(encoded_claims.decode(), Err(Error::Expired)));
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(transparent)]
-pub struct Claims<T: ClaimsSubTrait, State = Decoded<T>> {
-    claims: State,
-    #[serde(skip)]
-    _type: PhantomData<T>,
-}
+        let encoded_claims = Claims::new_at(AllowExpiredTestSub::<true>::default(), iat).unwrap();
+        assert!(encoded_claims.decode().is_ok());
+    }
 
-impl<T: ClaimsSubTrait> Claims<T> {
-    pub fn new(claims: T) -> Result<Claims<T, Encoded>> {
-        let iat = OffsetDateTime::now_utc();
-        let exp = iat + Duration::from_secs(T::DURATION);
+    #[test]
+    fn test_new_with_header() {
+        type TestSub = GenericTestSub<DEFAULT>;
 
-        let claim = Self {
-            claims: Decoded {
-                sub: claims,
-                exp: exp.unix_timestamp(),
-                iat: iat.unix_timestamp(),
-            },
-            _type: PhantomData,
-        };
+        let mut header = Header::new(Algorithm::HS512);
+        header.kid = Some("key-1".to_string());
+        let encoded_claims = Claims::new_with_header(TestSub::default(), header).unwrap();
+        assert_eq!(encoded_claims.peek_kid().unwrap().as_deref(), Some("key-1"));
 
-        claim.encode()
+        // Signed with the algorithm of the Subject regardless
+        let (header, _) = encoded_claims.decode_full().unwrap();
+        assert_eq!(header.alg, TestSub::ALGORITHM);
+        assert_eq!(header.kid.as_deref(), Some("key-1"));
     }
-}
 
-impl<T: ClaimsSubTrait> Claims<T, Encoded> {
-    pub fn token(self) -> String {
-        self.claims.0
+    #[test]
+    fn test_into_io_error() {
+        let err = std::io::Error::from(Error::Expired);
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        assert_eq!(err.to_string(), "token expired");
+
+        let encoded_claims: ClaimsEncoded<GenericTestSub<DEFAULT>> =
+            Claims::from("not a token".to_string());
+        let err = std::io::Error::from(encoded_claims.decode().unwrap_err());
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        let read = std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "eof");
+        let err = std::io::Error::from(Error::Read(read));
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
     }
-}
 
-impl<T: ClaimsSubTrait> Claims<T, Decoded<T>> {
-    fn encode(self) -> Result<Claims<T, Encoded>> {
-        let header = Header::default();
-        let claims = self.claims;
-        let key = EncodingKey::from_secret(T::secret());
+    #[test]
+    fn test_lifetime_elapsed_fraction() {
+        type TestSub = GenericTestSub<DEFAULT>;
 
-        let encoded_claim = jsonwebtoken::encode(&header, &claims, &key)?;
+        let ttl = TestSub::duration().as_secs() as i64;
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - ttl / 2;
+        let decoded_claims = Claims::new_at(TestSub::default(), iat)
+            .unwrap()
+            .decode()
+            .unwrap();
 
-        Ok(Claims {
-            claims: Encoded(encoded_claim),
-            _type: PhantomData,
-        })
+        assert_eq!(decoded_claims.lifetime_elapsed_fraction_at(iat), 0.0);
+        assert_eq!(
+            decoded_claims.lifetime_elapsed_fraction_at(iat + ttl / 2),
+            0.5
+        );
+        assert_eq!(
+            decoded_claims.lifetime_elapsed_fraction_at(iat + 2 * ttl),
+            1.0
+        );
+        assert_eq!(decoded_claims.lifetime_elapsed_fraction_at(iat - ttl), 0.0);
+
+        assert!(decoded_claims.should_refresh(0.5));
+        assert!(!decoded_claims.should_refresh(0.75));
+
+        let decoded_claims = Claims::at(TestSub::default(), iat, Duration::ZERO)
+            .encode()
+            .unwrap()
+            .decode_skip_expiry(true)
+            .unwrap();
+        assert_eq!(decoded_claims.lifetime_elapsed_fraction_at(iat), 1.0);
     }
 
-    pub fn claims(self) -> Decoded<T> {
-        self.claims
+    #[test]
+    fn test_header_alg() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let encoded_claims = Claims::new(TestSub::default()).unwrap();
+        assert_eq!(encoded_claims.header_alg().unwrap(), Algorithm::HS256);
+
+        let encoded_claims: ClaimsEncoded<TestSub> = Claims::from("not a token".to_string());
+        assert!(encoded_claims.header_alg().is_err());
     }
 
-    pub fn sub(self) -> T {
-        self.claims.sub
+    #[test]
+    fn test_from_claims_value() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        // The Subject is flattened into the claims
+        let mut value = serde_json::to_value(TestSub::default()).unwrap();
+        value["exp"] = (now + 60).into();
+        value["iat"] = now.into();
+
+        let decoded_claims = ClaimsDecoded::<TestSub>::from_claims_value(value).unwrap();
+        assert!(decoded_claims.require_not_expired().is_ok());
+        assert_eq!(decoded_claims.claims.iat, Some(now));
+        assert_eq!(decoded_claims.sub(), TestSub::default());
+
+        let value = serde_json::json!({ "exp": now + 60 });
+        assert!(ClaimsDecoded::<TestSub>::from_claims_value(value).is_err());
     }
-}
 
-impl<T: ClaimsSubTrait> Claims<T, Encoded> {
-    pub fn decode(self) -> Result<Claims<T, Decoded<T>>>
-    where
-        Decoded<T>: DeserializeOwned,
-    {
-        let token = self.claims.0;
-        let key = DecodingKey::from_secret(T::secret());
-        let validation = Validation::default();
+    #[test]
+    fn test_is_active() {
+        type TestSub = GenericTestSub<DEFAULT>;
 
-        let decoded_claims = jsonwebtoken::decode(token.as_str(), &key, &validation)?.claims;
+        let now = OffsetDateTime::now_utc();
+        let mut value = serde_json::to_value(TestSub::default()).unwrap();
+        value["iat"] = now.unix_timestamp().into();
+        value["nbf"] = (now.unix_timestamp() + 60).into();
+        value["exp"] = (now.unix_timestamp() + 2 * 60).into();
+        let decoded_claims = ClaimsDecoded::<TestSub>::from_claims_value(value).unwrap();
 
-        Ok(Claims {
-            claims: decoded_claims,
-            _type: PhantomData,
-        })
+        let activates_at = decoded_claims.activates_at().unwrap();
+        assert_eq!(activates_at.unix_timestamp(), now.unix_timestamp() + 60);
+
+        // Not yet active, active, expired
+        assert!(!decoded_claims.is_active(now));
+        assert!(decoded_claims.is_active(activates_at));
+        assert!(!decoded_claims.is_active(now + time::Duration::minutes(3)));
+
+        let decoded_claims = Claims::new(TestSub::default()).unwrap().decode().unwrap();
+        assert_eq!(decoded_claims.activates_at(), None);
+        assert!(decoded_claims.is_active(now));
     }
-}
 
-impl<T: ClaimsSubTrait> From<String> for Claims<T, Encoded> {
-    fn from(value: String) -> Self {
-        Self {
-            claims: Encoded(value),
-            _type: PhantomData,
-        }
+    #[test]
+    fn test_pre_sign_validate() {
+        let result = Claims::new(PreSignTestSub::default());
+        assert!(matches!(result, Err(Error::SubjectRejected(reason)) if reason == "empty id"));
+
+        let result = Claims::builder(PreSignTestSub::default()).build();
+        assert!(matches!(result, Err(Error::SubjectRejected(_))));
+
+        let sub = PreSignTestSub {
+            id: "123".to_string(),
+        };
+        let decoded_claims = Claims::new(sub.clone()).unwrap().decode().unwrap();
+        assert_eq!(decoded_claims.sub(), sub);
     }
-}
 
-impl<T> TryFrom<String> for ClaimsDecoded<T>
-where
-    T: ClaimsSubTrait,
-    Decoded<T>: for<'a> Deserialize<'a>,
-{
-    type Error = Error;
+    #[test]
+    fn test_decoded_ord() {
+        use std::{cmp::Reverse, collections::BinaryHeap};
 
-    fn try_from(token: String) -> std::result::Result<Self, Self::Error> {
-        let claims: ClaimsEncoded<T> = From::from(token);
-        let claims = claims.decode()?;
+        type TestSub = GenericTestSub<DEFAULT>;
 
-        Ok(claims)
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let decoded = |iat: i64, ttl: u64| {
+            ByExpiry(Claims::at(TestSub::default(), iat, Duration::from_secs(ttl)).claims)
+        };
+
+        let mut heap = BinaryHeap::new();
+        heap.push(Reverse(decoded(now, 60 * 60)));
+        heap.push(Reverse(decoded(now, 60)));
+        heap.push(Reverse(decoded(now - 60, 2 * 60)));
+        heap.push(Reverse(decoded(now, 10 * 60)));
+
+        let expiries: Vec<_> = std::iter::from_fn(|| heap.pop())
+            .map(|Reverse(ByExpiry(claims))| (claims.exp, claims.iat))
+            .collect();
+        assert_eq!(
+            expiries,
+            [
+                (now + 60, Some(now - 60)),
+                (now + 60, Some(now)),
+                (now + 10 * 60, Some(now)),
+                (now + 60 * 60, Some(now)),
+            ]
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::convert::TryInto;
+    #[test]
+    fn test_invalid_numeric_date() {
+        type TestSub = GenericTestSub<DEFAULT>;
 
-    const DEFAULT: u32 = 0;
-    const EXPIRED_TOKEN: u32 = 1;
-    const DIFFRENT_SECRETS: u32 = 2;
+        let token = |exp: i64, iat: i64| {
+            let mut claims = Claims::at(TestSub::default(), iat, Duration::ZERO);
+            claims.claims.exp = exp;
+            claims.encode().unwrap()
+        };
 
-    // A struct for testing purposes
-    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-    pub struct GenericTestSub<const TEST: u32> {
-        id: String,
-        name: String,
+        assert!(matches!(
+            token(-1, 0).decode(),
+            Err(Error::InvalidNumericDate {
+                claim: "exp",
+                value: -1
+            })
+        ));
+        assert!(matches!(
+            token(i64::MAX, 0).decode(),
+            Err(Error::InvalidNumericDate {
+                claim: "exp",
+                value: i64::MAX
+            })
+        ));
+        assert!(matches!(
+            token(60, -60).decode_skip_expiry(true),
+            Err(Error::InvalidNumericDate {
+                claim: "iat",
+                value: -60
+            })
+        ));
+
+        // Valid NumericDates, just long expired
+        assert!(matches!(token(60, 0).decode(), Err(Error::Expired)));
     }
 
-    impl ClaimsSubTrait for GenericTestSub<DEFAULT> {
-        const DURATION: u64 = 60 * 60; // 1 hour
+    #[test]
+    fn test_query_param() {
+        type TestSub = GenericTestSub<DEFAULT>;
 
-        fn secret<'a>() -> &'a [u8] {
-            b"test_secret"
-        }
+        let token = Claims::new(TestSub::default())
+            .unwrap()
+            .with_prefix("a+b c/");
+        let encoded_claims: ClaimsEncoded<TestSub> = Claims::from(token.clone());
+
+        let param = encoded_claims.to_query_param("magic token");
+        assert!(param.starts_with("magic+token=a%2Bb+c%2F"));
+
+        let query = format!("?next=%2Fhome&{}&other=1", param);
+        let encoded_claims = ClaimsEncoded::<TestSub>::from_query(&query, "magic token").unwrap();
+        assert_eq!(encoded_claims.clone().token(), token);
+
+        let encoded_claims =
+            ClaimsEncoded::<TestSub>::strip_prefix(&encoded_claims.token(), "a+b c/");
+        assert!(encoded_claims.unwrap().decode().is_ok());
+
+        let result = ClaimsEncoded::<TestSub>::from_query(&query, "token");
+        assert!(matches!(result, Err(Error::MissingQueryParam(name)) if name == "token"));
     }
 
-    impl ClaimsSubTrait for GenericTestSub<EXPIRED_TOKEN> {
-        const DURATION: u64 = 1; // 1 second
+    #[test]
+    fn test_as_bearer() {
+        type TestSub = GenericTestSub<DEFAULT>;
 
-        fn secret<'a>() -> &'a [u8] {
-            b"test_secret"
-        }
+        let encoded_claims = Claims::new(TestSub::default()).unwrap();
+        let token = encoded_claims.clone().token();
+
+        let decoded_claims = encoded_claims
+            .clone()
+            .decode_retaining()
+            .unwrap()
+            .into_claims();
+        assert_eq!(
+            decoded_claims.as_bearer(),
+            Some(format!("Bearer {}", token))
+        );
+
+        // Only when the token was retained
+        assert_eq!(encoded_claims.decode().unwrap().as_bearer(), None);
     }
-    impl ClaimsSubTrait for GenericTestSub<DIFFRENT_SECRETS> {
-        const DURATION: u64 = 60 * 60; // 1 hour
 
-        fn secret<'a>() -> &'a [u8] {
-            b"different_test_secret"
+    #[test]
+    fn test_zero_duration() {
+        #[derive(Debug, Default, Serialize, Deserialize)]
+        struct ForgottenDurationSub {
+            id: String,
         }
+
+        impl ClaimsSubTrait for ForgottenDurationSub {}
+
+        impl SymmetricClaims for ForgottenDurationSub {
+            fn secret<'a>() -> &'a [u8] {
+                TEST_SECRET
+            }
+        }
+
+        let result = Claims::new(ForgottenDurationSub::default());
+        assert!(matches!(result, Err(Error::ZeroDuration)));
+
+        let result = Claims::new_with_ttl(GenericTestSub::<DEFAULT>::default(), Duration::ZERO);
+        assert!(matches!(result, Err(Error::ZeroDuration)));
+
+        // Tokens past their expiry are accepted anyway
+        let result = Claims::new_with_ttl(AllowExpiredTestSub::<true>::default(), Duration::ZERO);
+        assert!(result.unwrap().decode().is_ok());
     }
 
     #[test]
-    fn test_encode_decode() {
-        type TestSub = GenericTestSub<DEFAULT>;
+    fn test_content_type() {
+        let inner = Claims::new(GenericTestSub::<DEFAULT>::default())
+            .unwrap()
+            .token();
 
-        let claims: TestSub = TestSub {
+        let (header, decoded_claims) = Claims::new(NestedTestSub {
+            token: inner.clone(),
+        })
+        .unwrap()
+        .decode_full()
+        .unwrap();
+        assert_eq!(header.cty.as_deref(), Some("JWT"));
+
+        let inner: ClaimsEncoded<GenericTestSub<DEFAULT>> =
+            Claims::from(decoded_claims.sub().token);
+        let (header, _) = inner.decode_full().unwrap();
+        assert_eq!(header.cty, None);
+    }
+
+    #[test]
+    fn test_rename_registered_claims() {
+        let sub = RenameTestSub {
             id: "123".to_string(),
-            name: "John".to_string(),
+            expiry: Some("never".to_string()),
         };
 
-        let encoded_claims = Claims::new(claims).unwrap();
-        let decoded_claims: ClaimsDecoded<TestSub> = encoded_claims.token().try_into().unwrap();
+        let encoded_claims = Claims::new(sub.clone()).unwrap();
+        let payload = payload(&encoded_claims.clone().token());
+        assert!(payload.get("exp").is_none());
+        assert!(payload["expiry"].is_i64());
+        assert_eq!(payload["issuer"], "https://auth.example.com");
+        assert_eq!(payload["shadowed"]["expiry"], "never");
 
-        let sub = decoded_claims.sub();
+        assert!(!encoded_claims.is_expired_unverified().unwrap());
+        let decoded_claims = encoded_claims.decode().unwrap();
+        assert_eq!(
+            decoded_claims.claims.issuer(),
+            Some("https://auth.example.com")
+        );
+        assert_eq!(decoded_claims.sub(), sub);
 
-        assert_eq!(sub.id, "123");
-        assert_eq!(sub.name, "John");
+        // Still validated under their other names
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - 2 * 60 * 60;
+        let encoded_claims = Claims::new_at(sub.clone(), iat).unwrap();
+        assert!(matches!(encoded_claims.decode(), Err(Error::Expired)));
+
+        let mut claims = Claims::at(
+            sub,
+            OffsetDateTime::now_utc().unix_timestamp(),
+            Duration::from_secs(60),
+        );
+        claims.claims.iss = Some("https://evil.example.com".to_string());
+        let result = claims.encode().unwrap().decode();
+        assert!(
+            matches!(result, Err(Error::Jsonwebtoken(err)) if *err.kind() == ErrorKind::InvalidIssuer)
+        );
     }
 
     #[test]
-    fn test_claims() {
-        type TestSub = GenericTestSub<DEFAULT>;
-
-        let claims: TestSub = TestSub {
+    fn test_decode_arc() {
+        let claims = Claims::new(GenericTestSub::<DEFAULT> {
             id: "123".to_string(),
             name: "John".to_string(),
+        })
+        .unwrap()
+        .decode_arc()
+        .unwrap();
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let claims = Arc::clone(&claims);
+                std::thread::spawn(move || claims.sub.id.clone())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), "123");
+        }
+        assert_eq!(Arc::strong_count(&claims), 1);
+    }
+
+    #[test]
+    fn test_on_expired() {
+        use std::sync::atomic::Ordering;
+
+        let sub = ExpiryHookTestSub {
+            id: "123".to_string(),
         };
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - 2 * 60 * 60;
 
-        let iat_min = OffsetDateTime::now_utc().unix_timestamp();
-        let encoded_claims = Claims::new(claims).unwrap();
-        let iat_max = OffsetDateTime::now_utc().unix_timestamp();
-        let decoded_claims: ClaimsDecoded<TestSub> = encoded_claims.token().try_into().unwrap();
+        let result = Claims::new(sub.clone()).unwrap().decode();
+        assert!(result.is_ok());
+        assert_eq!(EXPIRED_COUNT.load(Ordering::SeqCst), 0);
 
-        let claims = decoded_claims.claims();
+        let result = Claims::new_at(sub.clone(), iat).unwrap().decode();
+        assert!(matches!(result, Err(Error::Expired)));
+        assert_eq!(EXPIRED_COUNT.load(Ordering::SeqCst), 1);
+
+        let result = Claims::new_at(sub.clone(), iat)
+            .unwrap()
+            .decode_with_grace(Duration::from_secs(60));
+        assert!(matches!(result, Err(Error::Expired)));
+        assert_eq!(EXPIRED_COUNT.load(Ordering::SeqCst), 2);
 
-        assert!(claims.iat >= iat_min);
-        assert!(claims.iat <= iat_max);
+        let result = Claims::new_at(sub.clone(), iat)
+            .unwrap()
+            .decode_diagnostic();
+        assert!(matches!(result.unwrap_err()[..], [Error::Expired]));
+        assert_eq!(EXPIRED_COUNT.load(Ordering::SeqCst), 3);
 
-        assert_eq!(claims.iat + TestSub::DURATION as i64, claims.exp);
+        // Signed with another secret, expired as well but rejected for that first
+        let claims = Claims::at(sub, iat, Duration::from_secs(60 * 60)).claims;
+        let key = EncodingKey::from_secret(b"other_secret_of_at_least_32_bytes");
+        let token = jsonwebtoken::encode(&Header::default(), &claims, &key).unwrap();
+        let result = ClaimsEncoded::<ExpiryHookTestSub>::from(token).decode();
+        assert!(result.is_err() && !matches!(result, Err(Error::Expired)));
+        assert_eq!(EXPIRED_COUNT.load(Ordering::SeqCst), 3);
     }
 
-    // TODO: Find a better way to test this than waiting 62 seconds
     #[test]
-    fn test_expired_token() {
-        type TestSub = GenericTestSub<EXPIRED_TOKEN>;
-        let claims = TestSub {
+    fn test_as_ref_bytes() {
+        fn digest(data: impl AsRef<[u8]>) -> Vec<u8> {
+            data.as_ref().to_vec()
+        }
+
+        let encoded_claims = Claims::new(GenericTestSub::<DEFAULT>::default()).unwrap();
+
+        let digested = digest(&encoded_claims);
+        assert_eq!(digested, encoded_claims.token().as_bytes());
+    }
+
+    #[test]
+    fn test_validate_chain() {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let token = |parent: Option<&str>, jti: &str, ttl: u64| {
+            let sub = DelegationTestSub {
+                id: "123".to_string(),
+                parent: parent.map(str::to_string),
+            };
+            let mut claims = Claims::at(sub, now, Duration::from_secs(ttl));
+            claims.claims.jti = Some(jti.to_string());
+            claims.encode().unwrap().decode().unwrap()
+        };
+
+        let parent = token(None, "parent", 60 * 60);
+        let child = token(Some("parent"), "child", 60);
+        assert_eq!(child.parent_jti(), Some("parent"));
+        assert!(child.validate_chain(&parent).is_ok());
+
+        let grandchild = token(Some("child"), "grandchild", 30);
+        assert!(grandchild.validate_chain(&child).is_ok());
+        assert!(matches!(
+            grandchild.validate_chain(&parent),
+            Err(Error::InvalidChain(_))
+        ));
+        assert!(matches!(
+            parent.validate_chain(&parent),
+            Err(Error::InvalidChain("missing parent `jti`"))
+        ));
+
+        let outliving = token(Some("child"), "outliving", 60 * 60);
+        assert!(matches!(
+            outliving.validate_chain(&child),
+            Err(Error::InvalidChain("token outlives its parent"))
+        ));
+    }
+
+    #[test]
+    fn test_size_breakdown() {
+        let encoded_claims = Claims::new(GenericTestSub::<DEFAULT> {
             id: "123".to_string(),
             name: "John".to_string(),
+        })
+        .unwrap();
+
+        let breakdown = encoded_claims.size_breakdown().unwrap();
+        assert!(breakdown.payload > breakdown.header);
+        assert_eq!(breakdown.signature, 43); // 32 bytes of HS256
+
+        let total = breakdown.header + breakdown.payload + breakdown.signature + 2;
+        assert_eq!(total, encoded_claims.token().len());
+
+        let encoded_claims: ClaimsEncoded<GenericTestSub<DEFAULT>> =
+            Claims::from("a.b".to_string());
+        assert!(matches!(
+            encoded_claims.size_breakdown(),
+            Err(Error::Malformed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_audiences_runtime() {
+        let static_token = || {
+            let mut claims = Claims::at(
+                RuntimeAudienceTestSub::default(),
+                OffsetDateTime::now_utc().unix_timestamp(),
+                Duration::from_secs(60),
+            );
+            claims.claims.aud = vec!["static".to_string()];
+            claims.encode().unwrap()
         };
 
-        let encoded_claims = Claims::new(claims).unwrap();
+        // Only the static audiences until the runtime ones are set
+        let encoded_claims = Claims::new(RuntimeAudienceTestSub::default()).unwrap();
+        assert!(payload(&encoded_claims.clone().token())
+            .get("aud")
+            .is_none());
+        assert!(encoded_claims.decode().is_err());
+        assert!(static_token().decode().is_ok());
 
-        std::thread::sleep(Duration::from_secs(2)); // Wait for token to expire
-        std::thread::sleep(Duration::from_secs(60)); // Wait for the leeway in validation to pass
+        assert!(RUNTIME_AUDIENCES.set(["staging-api", "staging-admin"]));
+        assert!(!RUNTIME_AUDIENCES.set(["prod-api"]));
 
-        let result: Result<ClaimsDecoded<TestSub>> = encoded_claims.token().try_into();
+        let encoded_claims = Claims::new(RuntimeAudienceTestSub::default()).unwrap();
+        let payload = payload(&encoded_claims.clone().token());
+        assert_eq!(
+            payload["aud"],
+            serde_json::json!(["staging-api", "staging-admin"])
+        );
+        let decoded_claims = encoded_claims.decode().unwrap();
+        assert_eq!(
+            decoded_claims.claims.audience(),
+            ["staging-api", "staging-admin"]
+        );
 
-        assert!(result.is_err());
+        let encoded_claims = Claims::builder(RuntimeAudienceTestSub::default())
+            .build()
+            .unwrap();
+        assert!(encoded_claims.decode().is_ok());
+
+        let result = static_token().decode();
+        assert!(
+            matches!(result, Err(Error::Jsonwebtoken(err)) if *err.kind() == ErrorKind::InvalidAudience)
+        );
     }
 
     #[test]
-    fn test_diffrent_secrets() {
-        type TestSub1 = GenericTestSub<DEFAULT>;
-        type TestSub2 = GenericTestSub<DIFFRENT_SECRETS>;
+    fn test_validation_step() {
+        type TestSub = GenericTestSub<DEFAULT>;
 
-        let sub1 = TestSub1 {
+        fn step<T>(token: String) -> Option<ValidationStep>
+        where
+            T: DecodingClaims,
+            Decoded<T>: DeserializeOwned,
+        {
+            let result: Result<ClaimsDecoded<T>> = token.try_into();
+            result.err().and_then(|err| err.step())
+        }
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let sign = |payload: Value, secret: &[u8]| {
+            let key = EncodingKey::from_secret(secret);
+            jsonwebtoken::encode(&Header::default(), &payload, &key).unwrap()
+        };
+        let secret = TestSub::secret();
+        let valid = serde_json::json!({ "id": "123", "name": "John", "exp": now + 60, "iat": now });
+
+        assert_eq!(
+            step::<TestSub>("not a token".to_string()),
+            Some(ValidationStep::Format)
+        );
+        assert_eq!(
+            step::<TestSub>(sign(valid.clone(), b"other_secret_of_at_least_32_bytes")),
+            Some(ValidationStep::Signature)
+        );
+
+        let mut expired = valid.clone();
+        expired["exp"] = (now - 2 * 60 * 60).into();
+        expired["iat"] = (now - 3 * 60 * 60).into();
+        assert_eq!(
+            step::<TestSub>(sign(expired, secret)),
+            Some(ValidationStep::Expiry)
+        );
+
+        let mut not_yet_valid = valid.clone();
+        not_yet_valid["nbf"] = (now + 30 * 60).into();
+        assert_eq!(
+            step::<NbfLeewayTestSub>(sign(not_yet_valid, secret)),
+            Some(ValidationStep::NotBefore)
+        );
+
+        let sub = IssuerTestSub {
             id: "123".to_string(),
-            name: "John".to_string(),
         };
-        let sub2 = TestSub2 {
+        assert_eq!(
+            step::<IssuerTestSub>(issuer_token(&sub, "other_issuer")),
+            Some(ValidationStep::Issuer)
+        );
+        assert_eq!(
+            step::<AudienceTestSub>(sign(valid, secret)),
+            Some(ValidationStep::Audience)
+        );
+
+        let missing_field = serde_json::json!({ "id": "123", "exp": now + 60, "iat": now });
+        assert_eq!(
+            step::<TestSub>(sign(missing_field, secret)),
+            Some(ValidationStep::Deserialize)
+        );
+
+        assert_eq!(Error::Revoked("jti".to_string()).step(), None);
+    }
+
+    #[test]
+    fn test_inflate_limit() {
+        // Compresses to a fraction of `MAX_TOKEN_LEN`, inflates way beyond it
+        let sub = CompressedTestSub::<true> {
             id: "123".to_string(),
-            name: "John".to_string(),
+            description: "a".repeat(CompressedTestSub::<true>::MAX_TOKEN_LEN * 32),
         };
+        let encoded_claims = Claims::new(sub).unwrap();
+        assert!(encoded_claims.clone().token().len() < CompressedTestSub::<true>::MAX_TOKEN_LEN);
 
-        let encoded_claims1_0 = Claims::new(sub1.clone()).unwrap();
-        let encoded_claims1_1 = Claims::new(sub1.clone()).unwrap();
-        let encoded_claims2_0 = Claims::new(sub2.clone()).unwrap();
-        let encoded_claims2_1 = Claims::new(sub2.clone()).unwrap();
+        assert!(matches!(
+            encoded_claims.is_expired_unverified(),
+            Err(Error::Compression(_))
+        ));
+        assert!(matches!(
+            encoded_claims.decode(),
+            Err(Error::Compression(_))
+        ));
+    }
 
-        let result: Result<ClaimsDecoded<TestSub1>> = encoded_claims1_0.token().try_into();
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().sub(), sub1);
+    #[test]
+    fn test_batch_decoding_key() {
+        use std::sync::atomic::Ordering;
 
-        let result: Result<ClaimsDecoded<TestSub2>> = encoded_claims1_1.token().try_into();
-        assert!(result.is_err());
+        let tokens: Vec<_> = (0..3)
+            .map(|_| Claims::new(KeyLookupTestSub::default()).unwrap().token())
+            .collect();
 
-        let result: Result<ClaimsDecoded<TestSub2>> = encoded_claims2_0.token().try_into();
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().sub(), sub2);
+        // The key is built once for all tokens
+        let input = tokens.join("|");
+        let results = ClaimsDecoded::<KeyLookupTestSub>::decode_set_lenient(&input, '|');
+        assert_eq!(KEY_LOOKUPS.load(Ordering::SeqCst), 1);
+        assert!(results.iter().all(Result::is_ok));
 
-        let result: std::result::Result<ClaimsDecoded<TestSub1>, Error> =
-            encoded_claims2_1.token().try_into();
-        assert!(result.is_err());
+        let input = tokens.join("\n");
+        let results = ClaimsDecoded::<KeyLookupTestSub>::decode_from_reader(input.as_bytes());
+        assert!(results.collect::<Result<Vec<_>>>().is_ok());
+        assert_eq!(KEY_LOOKUPS.load(Ordering::SeqCst), 2);
+
+        // And looked up for each token when secrets are rotated
+        let input = [
+            Claims::new(RotationTestSub::<1>::default())
+                .unwrap()
+                .token(),
+            Claims::new(RotationTestSub::<2>::default())
+                .unwrap()
+                .token(),
+        ]
+        .join("|");
+        let results = ClaimsDecoded::<RotationTestSub<2>>::decode_set_lenient(&input, '|');
+        assert!(results.iter().all(Result::is_ok));
+
+        let input = input.replace('|', "\n");
+        let results = ClaimsDecoded::<RotationTestSub<2>>::decode_from_reader(input.as_bytes());
+        assert!(results.collect::<Result<Vec<_>>>().is_ok());
+    }
+
+    #[test]
+    fn test_decoded_eq() {
+        type TestSub = GenericTestSub<DEFAULT>;
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let decoded = |id: &str| {
+            let sub = TestSub {
+                id: id.to_string(),
+                ..Default::default()
+            };
+            Claims::at(sub, now, Duration::from_secs(60)).claims
+        };
+
+        assert_eq!(decoded("1"), decoded("1"));
+        assert_ne!(decoded("1"), decoded("2"));
+        assert!(ByExpiry(decoded("1")) == ByExpiry(decoded("2")));
     }
 }
@@ -0,0 +1,116 @@
+use std::{collections::BTreeSet, sync::OnceLock};
+
+/// The audiences a token is accepted for, see [`ClaimsSubTrait::audiences`](super::ClaimsSubTrait::audiences).
+///
+/// Built at runtime, e.g. from a config file:
+///
+/// ```
+/// # use typed_string_encoding::jwt::AudienceSet;
+/// let mut audiences: AudienceSet = ["api", "admin"].into_iter().collect();
+/// audiences.insert("billing");
+///
+/// assert!(audiences.contains("admin"));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AudienceSet {
+    audiences: BTreeSet<String>,
+}
+
+/// How the audiences of a token are matched against the
+/// [`ClaimsSubTrait::audiences`](super::ClaimsSubTrait::audiences) it's
+/// accepted for, see [`ClaimsSubTrait::AUDIENCE_MATCH`](super::ClaimsSubTrait::AUDIENCE_MATCH).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AudienceMatch {
+    /// The token names at least one of the audiences
+    #[default]
+    Any,
+    /// The token names every one of the audiences
+    All,
+}
+
+impl AudienceSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `aud`, `false` if it already was in the set
+    pub fn insert(&mut self, aud: impl Into<String>) -> bool {
+        self.audiences.insert(aud.into())
+    }
+
+    pub fn contains(&self, aud: &str) -> bool {
+        self.audiences.contains(aud)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.audiences.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.audiences.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.audiences.iter().map(String::as_str)
+    }
+}
+
+impl<A: Into<String>> Extend<A> for AudienceSet {
+    fn extend<I: IntoIterator<Item = A>>(&mut self, iter: I) {
+        self.audiences.extend(iter.into_iter().map(Into::into));
+    }
+}
+
+impl<A: Into<String>> FromIterator<A> for AudienceSet {
+    fn from_iter<I: IntoIterator<Item = A>>(iter: I) -> Self {
+        let mut audiences = Self::new();
+        audiences.extend(iter);
+        audiences
+    }
+}
+
+impl IntoIterator for AudienceSet {
+    type Item = String;
+    type IntoIter = std::collections::btree_set::IntoIter<String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.audiences.into_iter()
+    }
+}
+
+/// Audiences set once at runtime, e.g. from the environment at startup so the
+/// same binary serves staging and production, see
+/// [`ClaimsSubTrait::audiences_runtime`](super::ClaimsSubTrait::audiences_runtime).
+///
+/// ```
+/// # use typed_string_encoding::jwt::RuntimeAudiences;
+/// static AUDIENCES: RuntimeAudiences = RuntimeAudiences::new();
+///
+/// assert!(AUDIENCES.set(["staging-api"]));
+/// assert!(!AUDIENCES.set(["prod-api"]));
+/// assert_eq!(AUDIENCES.get(), ["staging-api"]);
+/// ```
+#[derive(Debug, Default)]
+pub struct RuntimeAudiences {
+    audiences: OnceLock<Vec<String>>,
+}
+
+impl RuntimeAudiences {
+    pub const fn new() -> Self {
+        Self {
+            audiences: OnceLock::new(),
+        }
+    }
+
+    /// Set the audiences, `false` if they already were
+    pub fn set<A: Into<String>>(&self, audiences: impl IntoIterator<Item = A>) -> bool {
+        self.audiences
+            .set(audiences.into_iter().map(Into::into).collect())
+            .is_ok()
+    }
+
+    /// The audiences, empty until they're set
+    pub fn get(&self) -> &[String] {
+        self.audiences.get().map_or(&[], Vec::as_slice)
+    }
+}
@@ -0,0 +1,89 @@
+use ::axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use super::{Error, ErrorFormat};
+
+impl Error {
+    fn status(&self) -> StatusCode {
+        match self.code() {
+            "malformed" => StatusCode::BAD_REQUEST,
+            "internal" => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    /// The response to a request with `headers`, in the format its `Accept`
+    /// header asks for
+    pub fn into_response_for(self, headers: &HeaderMap) -> Response {
+        let format = headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .map(ErrorFormat::from_accept)
+            .unwrap_or_default();
+
+        self.response(format)
+    }
+
+    fn response(&self, format: ErrorFormat) -> Response {
+        let (body, content_type) = self.error_body(format);
+
+        (self.status(), [(header::CONTENT_TYPE, content_type)], body).into_response()
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        self.response(ErrorFormat::Json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::errors::ErrorKind;
+
+    async fn body_bytes(response: Response) -> ::axum::body::Bytes {
+        ::axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+    }
+
+    async fn body(response: Response) -> serde_json::Value {
+        serde_json::from_slice(&body_bytes(response).await).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_expired_response() {
+        let error = Error::Expired;
+
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(body(response).await["error"], "expired");
+    }
+
+    #[tokio::test]
+    async fn test_malformed_response() {
+        let error = Error::Jsonwebtoken(ErrorKind::InvalidToken.into());
+
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(body(response).await["error"], "malformed");
+    }
+
+    #[tokio::test]
+    async fn test_plain_text_response() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, "text/plain".parse().unwrap());
+
+        let response = Error::Expired.into_response_for(&headers);
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+
+        assert_eq!(body_bytes(response).await, "expired");
+    }
+}
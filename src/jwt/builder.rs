@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use super::{check_ttl, clock, Claims, ClaimsEncoded, EncodingClaims, Result};
+
+/// Sets the optional registered claims of a token, see [`Claims::builder`].
+///
+/// Anything not set is the same as for [`Claims::new`].
+#[derive(Debug)]
+pub struct ClaimsBuilder<T: EncodingClaims> {
+    sub: T,
+    iat: Option<i64>,
+    ttl: Duration,
+    nbf: Option<i64>,
+    iss: Option<String>,
+    aud: Vec<String>,
+    jti: Option<String>,
+    scopes: Vec<String>,
+}
+
+impl<T: EncodingClaims> ClaimsBuilder<T> {
+    pub fn new(sub: T) -> Self {
+        Self {
+            sub,
+            iat: None,
+            ttl: T::duration(),
+            nbf: None,
+            iss: T::issuer(),
+            aud: T::audiences_runtime(),
+            jti: None,
+            scopes: Vec::new(),
+        }
+    }
+
+    /// Issue the token at the unix timestamp `iat` instead of now
+    pub fn issued_at(mut self, iat: i64) -> Self {
+        self.iat = Some(iat);
+        self
+    }
+
+    /// Expire the token after `ttl` instead of [`ClaimsSubTrait::duration`](super::ClaimsSubTrait::duration)
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// The `nbf` claim, as a unix timestamp
+    pub fn not_before(mut self, nbf: i64) -> Self {
+        self.nbf = Some(nbf);
+        self
+    }
+
+    /// The `iss` claim, instead of [`ClaimsSubTrait::issuer`](super::ClaimsSubTrait::issuer)
+    pub fn issuer(mut self, iss: impl Into<String>) -> Self {
+        self.iss = Some(iss.into());
+        self
+    }
+
+    /// Add an audience to the `aud` claim
+    pub fn audience(mut self, aud: impl Into<String>) -> Self {
+        self.aud.push(aud.into());
+        self
+    }
+
+    /// The `jti` claim
+    pub fn jti(mut self, jti: impl Into<String>) -> Self {
+        self.jti = Some(jti.into());
+        self
+    }
+
+    /// Add a scope to the OAuth style `scope` claim
+    pub fn scope(mut self, scope: impl Into<String>) -> Self {
+        self.scopes.push(scope.into());
+        self
+    }
+
+    pub fn build(self) -> Result<ClaimsEncoded<T>> {
+        check_ttl::<T>(self.ttl)?;
+
+        let iat = self.iat.unwrap_or_else(clock::now);
+
+        let mut claims = Claims::at(self.sub, iat, self.ttl);
+        claims.claims.nbf = self.nbf;
+        claims.claims.iss = self.iss;
+        claims.claims.aud = self.aud;
+        claims.claims.jti = self.jti;
+        if !self.scopes.is_empty() {
+            claims.claims.scope = Some(self.scopes.join(" "));
+        }
+
+        claims.encode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jwt::testing::TestSub;
+    use time::OffsetDateTime;
+
+    #[test]
+    fn test_claims_builder() {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        let decoded = Claims::builder(TestSub::default())
+            .issued_at(now)
+            .ttl(Duration::from_secs(5 * 60))
+            .issuer("https://auth.example.com")
+            .jti("token-1")
+            .build()
+            .unwrap()
+            .decode()
+            .unwrap()
+            .claims();
+
+        assert_eq!(decoded.exp, now + 5 * 60);
+        assert_eq!(decoded.issuer(), Some("https://auth.example.com"));
+        assert_eq!(decoded.jti(), Some("token-1"));
+        assert!(decoded.audience().is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+//! The current time tokens are issued and checked at.
+//!
+//! With the `test-clock` feature a test can stop the clock of its thread with
+//! [`set_mock_now`], instead of backdating tokens or waiting for them to
+//! expire. Without it this is the system clock.
+
+#[cfg(feature = "test-clock")]
+use std::cell::Cell;
+
+use time::OffsetDateTime;
+
+#[cfg(feature = "test-clock")]
+thread_local! {
+    static MOCK_NOW: Cell<Option<i64>> = const { Cell::new(None) };
+}
+
+/// The current unix timestamp
+pub(crate) fn now() -> i64 {
+    #[cfg(feature = "test-clock")]
+    if let Some(now) = MOCK_NOW.with(Cell::get) {
+        return now;
+    }
+
+    OffsetDateTime::now_utc().unix_timestamp()
+}
+
+/// Stop the clock of the current thread at the unix timestamp `now`
+#[cfg(feature = "test-clock")]
+pub fn set_mock_now(now: i64) {
+    MOCK_NOW.with(|mock_now| mock_now.set(Some(now)));
+}
+
+/// Go back to the system clock on the current thread
+#[cfg(feature = "test-clock")]
+pub fn clear_mock_now() {
+    MOCK_NOW.with(|mock_now| mock_now.set(None));
+}
+
+#[cfg(all(test, feature = "test-clock"))]
+mod tests {
+    use super::*;
+    use crate::jwt::{testing::TestSub, Claims, Error};
+
+    #[test]
+    fn test_mock_now() {
+        let real_now = now();
+
+        // Issued two hours ago
+        set_mock_now(real_now - 2 * 60 * 60);
+        let encoded_claims = Claims::new(TestSub::default()).unwrap();
+        clear_mock_now();
+        assert!(matches!(encoded_claims.decode(), Err(Error::Expired)));
+
+        // Checked two hours from now
+        let encoded_claims = Claims::new(TestSub::default()).unwrap();
+        assert!(encoded_claims.clone().decode().is_ok());
+        set_mock_now(real_now + 2 * 60 * 60);
+        assert!(matches!(encoded_claims.decode(), Err(Error::Expired)));
+        clear_mock_now();
+    }
+}
@@ -0,0 +1,122 @@
+//! Compact JWS assembly for DEFLATE compressed payloads (`"zip": "DEF"`).
+//!
+//! `jsonwebtoken` has no notion of the `zip` header, so the token is signed and
+//! verified by hand here.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use jsonwebtoken::{crypto, errors::ErrorKind, DecodingKey, EncodingKey, Header, Validation};
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{Error, Result};
+
+const ZIP: &str = "zip";
+const DEFLATE: &str = "DEF";
+
+/// How many times its maximum length a token's payload may inflate to, see
+/// [`ClaimsSubTrait::MAX_TOKEN_LEN`](super::ClaimsSubTrait::MAX_TOKEN_LEN)
+pub(super) const MAX_INFLATE_RATIO: usize = 16;
+
+fn b64_encode(input: &[u8]) -> String {
+    base64::encode_config(input, base64::URL_SAFE_NO_PAD)
+}
+
+fn b64_decode(input: &str) -> Result<Vec<u8>> {
+    base64::decode_config(input, base64::URL_SAFE_NO_PAD)
+        .map_err(|err| Error::Jsonwebtoken(err.into()))
+}
+
+fn invalid_token() -> Error {
+    Error::Jsonwebtoken(ErrorKind::InvalidToken.into())
+}
+
+/// Sign `claims` with a DEFLATE compressed payload, with the `extra` header
+/// parameters.
+pub(super) fn encode<C: Serialize>(
+    header: &Header,
+    extra: &BTreeMap<String, Value>,
+    claims: &C,
+    key: &EncodingKey,
+) -> Result<String> {
+    let mut header_json = super::header_json(header, extra)?;
+    header_json.insert(ZIP.to_string(), Value::from(DEFLATE));
+    let header_json = serde_json::to_vec(&header_json)?;
+
+    let payload = serde_json::to_vec(claims)?;
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&payload).map_err(Error::Compression)?;
+    let payload = encoder.finish().map_err(Error::Compression)?;
+
+    let message = format!("{}.{}", b64_encode(&header_json), b64_encode(&payload));
+    let signature = crypto::sign(message.as_bytes(), key, header.alg)?;
+
+    Ok(format!("{}.{}", message, signature))
+}
+
+/// Verify the signature of a compressed token, unless `signature` is false,
+/// and return an equivalent token with an uncompressed payload of at most
+/// `max_len` bytes, so a DEFLATE bomb can't exhaust memory.
+///
+/// The signature of the returned token is the signature over the compressed
+/// payload, so it must be decoded with signature validation disabled.
+pub(super) fn inflate(
+    token: &str,
+    key: &DecodingKey,
+    validation: &Validation,
+    signature: bool,
+    max_len: usize,
+) -> Result<String> {
+    let (message, signature_b64) = token.rsplit_once('.').ok_or_else(invalid_token)?;
+    let (header_b64, payload_b64) = message.split_once('.').ok_or_else(invalid_token)?;
+
+    let header_json: Value = serde_json::from_slice(&b64_decode(header_b64)?)?;
+    if header_json.get(ZIP).and_then(Value::as_str) != Some(DEFLATE) {
+        return Err(invalid_token());
+    }
+
+    let header: Header = serde_json::from_value(header_json)?;
+    if signature {
+        verify(message, signature_b64, &header, key, validation)?;
+    }
+
+    let mut payload = Vec::new();
+    DeflateDecoder::new(b64_decode(payload_b64)?.as_slice())
+        .take(max_len as u64 + 1)
+        .read_to_end(&mut payload)
+        .map_err(Error::Compression)?;
+    if payload.len() > max_len {
+        return Err(Error::Compression(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("payload inflates beyond {} bytes", max_len),
+        )));
+    }
+
+    Ok(format!(
+        "{}.{}.{}",
+        header_b64,
+        b64_encode(&payload),
+        signature_b64
+    ))
+}
+
+fn verify(
+    message: &str,
+    signature: &str,
+    header: &Header,
+    key: &DecodingKey,
+    validation: &Validation,
+) -> Result<()> {
+    if !validation.algorithms.contains(&header.alg) {
+        return Err(Error::Jsonwebtoken(ErrorKind::InvalidAlgorithm.into()));
+    }
+    if !crypto::verify(signature, message.as_bytes(), key, header.alg)? {
+        return Err(Error::Jsonwebtoken(ErrorKind::InvalidSignature.into()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use std::borrow::Cow;
+
+use ::cookie::{time, Cookie, CookieBuilder, CookieJar, SameSite};
+use serde::de::DeserializeOwned;
+
+use super::{
+    clock, Claims, ClaimsDecoded, ClaimsEncoded, ClaimsSubTrait, Decoded, DecodingClaims, Error,
+    Result,
+};
+
+/// The size of a cookie, its name, value and attributes together, browsers
+/// have to support at least
+pub const MAX_COOKIE_BYTES: usize = 4096;
+
+impl<T: ClaimsSubTrait> ClaimsEncoded<T> {
+    /// Build a `HttpOnly`, `Secure`, `SameSite=Lax` cookie holding the token
+    /// that lives as long as the token itself, until its `exp`.
+    pub fn to_cookie<'c, N>(self, name: N) -> Cookie<'c>
+    where
+        N: Into<Cow<'c, str>>,
+    {
+        // A token without a readable `exp` can't be decoded anyway
+        let max_age = self
+            .unverified_exp()
+            .map_or(0, |exp| exp.saturating_sub(clock::now()).max(0));
+
+        cookie_builder(name, self.token())
+            .max_age(time::Duration::seconds(max_age))
+            .build()
+    }
+
+    /// [`Claims::to_cookie`], failing when the cookie exceeds [`MAX_COOKIE_BYTES`]
+    /// instead of being dropped by the browser.
+    pub fn try_to_cookie<'c, N>(self, name: N) -> Result<Cookie<'c>>
+    where
+        N: Into<Cow<'c, str>>,
+    {
+        let cookie = self.to_cookie(name);
+
+        let len = cookie.to_string().len();
+        if len > MAX_COOKIE_BYTES {
+            return Err(Error::TokenTooLargeForCookie {
+                len,
+                max: MAX_COOKIE_BYTES,
+            });
+        }
+        Ok(cookie)
+    }
+
+    /// Whether the token fits in a cookie with `overhead` bytes of name and
+    /// attributes, see [`MAX_COOKIE_BYTES`]
+    pub fn fits_in_cookie(&self, overhead: usize) -> bool {
+        self.len().saturating_add(overhead) <= MAX_COOKIE_BYTES
+    }
+
+    /// A cookie clearing the one of [`Claims::to_cookie`] named `name`, with
+    /// the same attributes, an empty value and expired, e.g. on logout.
+    pub fn clear_cookie<'c, N>(name: N) -> Cookie<'c>
+    where
+        N: Into<Cow<'c, str>>,
+    {
+        cookie_builder(name, "")
+            .max_age(time::Duration::ZERO)
+            .expires(time::OffsetDateTime::UNIX_EPOCH)
+            .build()
+    }
+}
+
+/// The attributes of every cookie holding a token
+fn cookie_builder<'c, N, V>(name: N, value: V) -> CookieBuilder<'c>
+where
+    N: Into<Cow<'c, str>>,
+    V: Into<Cow<'c, str>>,
+{
+    Cookie::build((name, value))
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Lax)
+}
+
+impl<T> ClaimsDecoded<T>
+where
+    T: DecodingClaims,
+    Decoded<T>: DeserializeOwned,
+{
+    /// Read the cookie `name` from `jar` and decode it.
+    pub fn from_cookie_jar(jar: &CookieJar, name: &str) -> Result<Self> {
+        let cookie = jar
+            .get(name)
+            .ok_or_else(|| Error::MissingCookie(name.to_string()))?;
+
+        let claims: ClaimsEncoded<T> = Claims::from(cookie.value().to_string());
+
+        claims.decode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::jwt::testing::TestSub;
+
+    #[test]
+    fn test_cookie_roundtrip() {
+        let sub = TestSub::new("123");
+
+        let encoded_claims = Claims::new(sub.clone()).unwrap();
+        let cookie = encoded_claims.to_cookie("session");
+
+        assert_eq!(cookie.name(), "session");
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+        // Until `exp`, a second may have passed since it was issued
+        let max_age = cookie.max_age().unwrap().whole_seconds();
+        assert!((TestSub::DURATION as i64 - 1..=TestSub::DURATION as i64).contains(&max_age));
+
+        let mut jar = CookieJar::new();
+        jar.add(cookie.into_owned());
+
+        let decoded_claims = ClaimsDecoded::<TestSub>::from_cookie_jar(&jar, "session").unwrap();
+        assert_eq!(decoded_claims.sub(), sub);
+    }
+
+    #[test]
+    fn test_cookie_max_age() {
+        let ttl = Duration::from_secs(5 * 60);
+        let cookie = Claims::new_with_ttl(TestSub::new("123"), ttl)
+            .unwrap()
+            .to_cookie("session");
+        let max_age = cookie.max_age().unwrap().whole_seconds();
+        assert!((ttl.as_secs() as i64 - 1..=ttl.as_secs() as i64).contains(&max_age));
+
+        // Issued two hours ago, expired an hour ago
+        let iat = time::OffsetDateTime::now_utc().unix_timestamp() - 2 * 60 * 60;
+        let cookie = Claims::new_at(TestSub::new("123"), iat)
+            .unwrap()
+            .to_cookie("session");
+        assert_eq!(cookie.max_age(), Some(time::Duration::ZERO));
+    }
+
+    #[test]
+    fn test_cookie_missing() {
+        let jar = CookieJar::new();
+
+        let result = ClaimsDecoded::<TestSub>::from_cookie_jar(&jar, "session");
+        assert!(matches!(result, Err(Error::MissingCookie(name)) if name == "session"));
+    }
+
+    #[test]
+    fn test_clear_cookie() {
+        let cookie = ClaimsEncoded::<TestSub>::clear_cookie("session");
+
+        assert_eq!(cookie.name(), "session");
+        assert_eq!(cookie.value(), "");
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+        assert_eq!(cookie.max_age(), Some(time::Duration::ZERO));
+        assert!(cookie.expires_datetime().unwrap() < time::OffsetDateTime::now_utc());
+        assert!(cookie.to_string().contains("Max-Age=0"));
+    }
+
+    #[test]
+    fn test_cookie_size() {
+        let encoded_claims = Claims::new(TestSub::new("123")).unwrap();
+
+        let len = encoded_claims.len();
+        assert!(encoded_claims.fits_in_cookie(MAX_COOKIE_BYTES - len - 1));
+        assert!(encoded_claims.fits_in_cookie(MAX_COOKIE_BYTES - len));
+        assert!(!encoded_claims.fits_in_cookie(MAX_COOKIE_BYTES - len + 1));
+        assert!(encoded_claims.try_to_cookie("session").is_ok());
+
+        let encoded_claims = Claims::new(TestSub {
+            id: "1".repeat(MAX_COOKIE_BYTES),
+        })
+        .unwrap();
+        assert!(!encoded_claims.fits_in_cookie(0));
+        assert!(matches!(
+            encoded_claims.try_to_cookie("session"),
+            Err(Error::TokenTooLargeForCookie { len, max: MAX_COOKIE_BYTES })
+                if len > MAX_COOKIE_BYTES
+        ));
+    }
+}
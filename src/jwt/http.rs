@@ -0,0 +1,124 @@
+use std::borrow::Cow;
+
+use ::http::HeaderValue;
+use serde::de::DeserializeOwned;
+
+use super::{ClaimsDecoded, ClaimsEncoded, ClaimsSubTrait, Decoded, DecodingClaims, Error, Result};
+
+const BEARER: &str = "Bearer";
+
+/// The media type of a bare token as the body of a request or response
+const APPLICATION_JWT: &str = "application/jwt";
+
+/// Decodes the value of an `Authorization: Bearer <token>` header, the scheme
+/// matched case-insensitively as of RFC 7235
+impl<T> TryFrom<&HeaderValue> for ClaimsDecoded<T>
+where
+    T: DecodingClaims,
+    Decoded<T>: DeserializeOwned,
+{
+    type Error = Error;
+
+    fn try_from(value: &HeaderValue) -> std::result::Result<Self, Self::Error> {
+        let value = value
+            .to_str()
+            .map_err(|_| Error::InvalidHeader("not visible ASCII".to_string()))?;
+        let token = match value.split_once(' ') {
+            Some((scheme, token)) if scheme.eq_ignore_ascii_case(BEARER) => token,
+            _ => {
+                return Err(Error::InvalidHeader(
+                    "expected a `Bearer` token".to_string(),
+                ))
+            }
+        };
+
+        Cow::Borrowed(token.trim()).try_into()
+    }
+}
+
+impl<T: ClaimsSubTrait> ClaimsEncoded<T> {
+    /// The `Content-Type: application/jwt` header and body of a request or
+    /// response holding just the token.
+    pub fn to_jwt_body(self) -> (HeaderValue, Vec<u8>) {
+        (
+            HeaderValue::from_static(APPLICATION_JWT),
+            self.token().into_bytes(),
+        )
+    }
+}
+
+impl<T> ClaimsDecoded<T>
+where
+    T: DecodingClaims,
+    Decoded<T>: DeserializeOwned,
+{
+    /// Decode the token of a `body` sent with the `content_type` header, which
+    /// must be `application/jwt`.
+    pub fn from_jwt_body(content_type: &HeaderValue, body: &[u8]) -> Result<Self> {
+        let found = content_type.to_str().unwrap_or_default();
+
+        // Parameters such as `charset` are ignored, like the case of the type
+        let media_type = found.split(';').next().unwrap_or_default().trim();
+        if !media_type.eq_ignore_ascii_case(APPLICATION_JWT) {
+            return Err(Error::UnexpectedContentType(found.to_string()));
+        }
+
+        let body = String::from_utf8_lossy(body);
+        Cow::Borrowed(body.trim()).try_into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jwt::{testing::TestSub, Claims};
+
+    #[test]
+    fn test_bearer_header() {
+        let sub = TestSub::new("123");
+        let token = Claims::new(sub.clone()).unwrap().token();
+
+        let value = HeaderValue::from_str(&format!("Bearer {}", token)).unwrap();
+        let decoded_claims = ClaimsDecoded::<TestSub>::try_from(&value).unwrap();
+        assert_eq!(decoded_claims.sub(), sub);
+
+        let value = HeaderValue::from_str(&format!("bearer {}", token)).unwrap();
+        assert!(ClaimsDecoded::<TestSub>::try_from(&value).is_ok());
+
+        let value = HeaderValue::from_str(&format!("BEARER {}", token)).unwrap();
+        assert!(ClaimsDecoded::<TestSub>::try_from(&value).is_ok());
+
+        let value = HeaderValue::from_str(&format!("Basic {}", token)).unwrap();
+        let result = ClaimsDecoded::<TestSub>::try_from(&value);
+        assert!(matches!(result, Err(Error::InvalidHeader(_))));
+    }
+
+    #[test]
+    fn test_non_ascii_header() {
+        let value = HeaderValue::from_bytes(b"Bearer \xfftoken").unwrap();
+
+        let result = ClaimsDecoded::<TestSub>::try_from(&value);
+        assert!(
+            matches!(result, Err(Error::InvalidHeader(reason)) if reason == "not visible ASCII")
+        );
+    }
+
+    #[test]
+    fn test_jwt_body() {
+        let sub = TestSub::new("123");
+
+        let (content_type, body) = Claims::new(sub.clone()).unwrap().to_jwt_body();
+        assert_eq!(content_type, "application/jwt");
+        let decoded_claims = ClaimsDecoded::<TestSub>::from_jwt_body(&content_type, &body).unwrap();
+        assert_eq!(decoded_claims.sub(), sub);
+
+        let content_type = HeaderValue::from_static("Application/JWT; charset=utf-8");
+        assert!(ClaimsDecoded::<TestSub>::from_jwt_body(&content_type, &body).is_ok());
+
+        let content_type = HeaderValue::from_static("application/json");
+        let result = ClaimsDecoded::<TestSub>::from_jwt_body(&content_type, &body);
+        assert!(
+            matches!(result, Err(Error::UnexpectedContentType(found)) if found == "application/json")
+        );
+    }
+}
@@ -0,0 +1,379 @@
+//! Claims encrypted rather than only signed, as a compact JWE with direct
+//! encryption (`"alg": "dir"`) under AES-256-GCM (`"enc": "A256GCM"`).
+//!
+//! The payload of a signed token can be read by anyone holding it, the payload
+//! of an encrypted one only with the key.
+
+use std::marker::PhantomData;
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use zeroize::Zeroizing;
+
+use super::{
+    check_ttl, clock, Claims, ClaimsDecoded, ClaimsEncoded, ClaimsSubTrait, Decoded, Error, Result,
+};
+
+/// Length of the AES-GCM initialization vector
+const IV_LEN: usize = 12;
+
+/// Length of the AES-GCM authentication tag
+const TAG_LEN: usize = 16;
+
+/// A Subject whose tokens are encrypted.
+pub trait EncryptedClaims: ClaimsSubTrait {
+    /// The 256-bit key tokens are encrypted with
+    fn content_key() -> Zeroizing<[u8; 32]>;
+}
+
+/// A compact JWE holding the claims
+#[derive(Debug)]
+pub struct Encrypted(String);
+
+pub type ClaimsEncrypted<T> = Claims<T, Encrypted>;
+
+fn encode(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode(segment: &str, invalid: &'static str) -> Result<Vec<u8>> {
+    base64::decode_config(segment, base64::URL_SAFE_NO_PAD).map_err(|_| Error::Jwe(invalid))
+}
+
+impl<T: EncryptedClaims> Claims<T> {
+    /// Encrypt the claims for `sub`, issued now
+    pub fn new_encrypted(sub: T) -> Result<ClaimsEncrypted<T>> {
+        Self::new_encrypted_at(sub, clock::now())
+    }
+
+    /// Like [`Claims::new_encrypted`], issued at the unix timestamp `iat`
+    pub fn new_encrypted_at(sub: T, iat: i64) -> Result<ClaimsEncrypted<T>> {
+        check_ttl::<T>(T::duration())?;
+
+        Self::at(sub, iat, T::duration()).encrypt()
+    }
+
+    /// Encrypt the claims as they are
+    pub fn encrypt(self) -> Result<ClaimsEncrypted<T>> {
+        let header = encode(&serde_json::to_vec(
+            &json!({ "alg": "dir", "enc": "A256GCM" }),
+        )?);
+        let plaintext = Zeroizing::new(serde_json::to_vec(&self.into_payload()?)?);
+
+        let cipher = Aes256Gcm::new(T::content_key().as_ref().into());
+        let iv = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut ciphertext = cipher
+            .encrypt(
+                &iv,
+                Payload {
+                    msg: &plaintext,
+                    aad: header.as_bytes(),
+                },
+            )
+            .map_err(|_| Error::Jwe("failed to encrypt"))?;
+        let tag = ciphertext.split_off(ciphertext.len() - TAG_LEN);
+
+        // The encrypted key is empty, the content key is used directly
+        let token = format!(
+            "{}..{}.{}.{}",
+            header,
+            encode(&iv),
+            encode(&ciphertext),
+            encode(&tag)
+        );
+
+        Ok(Claims {
+            claims: Encrypted(token),
+            _type: PhantomData,
+        })
+    }
+}
+
+impl<T: ClaimsSubTrait> Claims<T, Encrypted> {
+    pub fn token(self) -> String {
+        self.claims.0
+    }
+
+    /// Decrypt the token, checking it wasn't tampered with, then validate its
+    /// claims like those of a signed token.
+    pub fn decrypt(&self) -> Result<ClaimsDecoded<T>>
+    where
+        T: EncryptedClaims,
+        Decoded<T>: DeserializeOwned,
+    {
+        // Before decoding and decrypting however much was sent
+        let len = self.claims.0.len();
+        if len > T::MAX_TOKEN_LEN {
+            return Err(Error::TokenTooLong {
+                len,
+                max: T::MAX_TOKEN_LEN,
+            });
+        }
+
+        let segments: Vec<&str> = self.claims.0.split('.').collect();
+        let [header, encrypted_key, iv, ciphertext, tag] = match segments[..] {
+            [header, encrypted_key, iv, ciphertext, tag] => {
+                [header, encrypted_key, iv, ciphertext, tag]
+            }
+            _ => return Err(Error::Jwe("expected 5 segments")),
+        };
+
+        let parsed: serde_json::Value = serde_json::from_slice(&decode(header, "invalid header")?)?;
+        if parsed["alg"] != "dir" || parsed["enc"] != "A256GCM" {
+            return Err(Error::Jwe("expected `dir` with `A256GCM`"));
+        }
+        if !encrypted_key.is_empty() {
+            return Err(Error::Jwe("unexpected encrypted key"));
+        }
+
+        let iv = decode(iv, "invalid initialization vector")?;
+        if iv.len() != IV_LEN {
+            return Err(Error::Jwe("invalid initialization vector"));
+        }
+        let mut ciphertext = decode(ciphertext, "invalid ciphertext")?;
+        ciphertext.extend(decode(tag, "invalid authentication tag")?);
+
+        let cipher = Aes256Gcm::new(T::content_key().as_ref().into());
+        let plaintext = cipher
+            .decrypt(
+                Nonce::from_slice(&iv),
+                Payload {
+                    msg: &ciphertext,
+                    aad: header.as_bytes(),
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| Error::Jwe("failed to decrypt"))?;
+
+        ClaimsEncoded::decode_payload(&plaintext)
+    }
+}
+
+impl<T: ClaimsSubTrait> From<String> for Claims<T, Encrypted> {
+    fn from(value: String) -> Self {
+        Self {
+            claims: Encrypted(value),
+            _type: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jwt::AudienceSet;
+    use jsonwebtoken::errors::ErrorKind;
+    use serde::{Deserialize, Serialize};
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+    use time::OffsetDateTime;
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct EncryptedSub<const KEY: u8> {
+        id: String,
+    }
+
+    impl<const KEY: u8> ClaimsSubTrait for EncryptedSub<KEY> {
+        const DURATION: u64 = 60 * 60; // 1 hour
+    }
+
+    impl<const KEY: u8> EncryptedClaims for EncryptedSub<KEY> {
+        fn content_key() -> Zeroizing<[u8; 32]> {
+            Zeroizing::new([KEY; 32])
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct AudienceEncryptedSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for AudienceEncryptedSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn issuer() -> Option<String> {
+            Some("https://auth.example.com".to_string())
+        }
+
+        fn audiences() -> AudienceSet {
+            ["api"].into_iter().collect()
+        }
+    }
+
+    impl EncryptedClaims for AudienceEncryptedSub {
+        fn content_key() -> Zeroizing<[u8; 32]> {
+            Zeroizing::new([1; 32])
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct EpochEncryptedSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for EpochEncryptedSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const EPOCH_OFFSET: i64 = 946_684_800; // 2000-01-01
+
+        fn pre_sign_validate(&self) -> std::result::Result<(), String> {
+            match self.id.is_empty() {
+                true => Err("empty id".to_string()),
+                false => Ok(()),
+            }
+        }
+    }
+
+    impl EncryptedClaims for EpochEncryptedSub {
+        fn content_key() -> Zeroizing<[u8; 32]> {
+            Zeroizing::new([1; 32])
+        }
+    }
+
+    static EXPIRED_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct ExpiryHookEncryptedSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for ExpiryHookEncryptedSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+
+        fn on_expired() {
+            EXPIRED_COUNT.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    impl EncryptedClaims for ExpiryHookEncryptedSub {
+        fn content_key() -> Zeroizing<[u8; 32]> {
+            Zeroizing::new([1; 32])
+        }
+    }
+
+    fn sub() -> EncryptedSub<1> {
+        EncryptedSub {
+            id: "confidential-id".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let token = Claims::new_encrypted(sub()).unwrap().token();
+        assert_eq!(token.split('.').count(), 5);
+
+        let encrypted_claims: ClaimsEncrypted<EncryptedSub<1>> = Claims::from(token);
+        assert_eq!(encrypted_claims.decrypt().unwrap().sub(), sub());
+
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - 2 * 60 * 60;
+        let encrypted_claims = Claims::new_encrypted_at(sub(), iat).unwrap();
+        assert!(matches!(encrypted_claims.decrypt(), Err(Error::Expired)));
+    }
+
+    #[test]
+    fn test_payload_unreadable() {
+        let token = Claims::new_encrypted(sub()).unwrap().token();
+
+        for segment in token.split('.') {
+            let bytes = decode(segment, "").unwrap();
+            assert!(!String::from_utf8_lossy(&bytes).contains("confidential-id"));
+        }
+
+        // Not without the key either
+        let encrypted_claims: ClaimsEncrypted<EncryptedSub<2>> = Claims::from(token.clone());
+        assert!(matches!(
+            encrypted_claims.decrypt(),
+            Err(Error::Jwe("failed to decrypt"))
+        ));
+
+        // Nor can the header be changed
+        let tampered = token.replacen("eyJ", "eyI", 1);
+        let encrypted_claims: ClaimsEncrypted<EncryptedSub<1>> = Claims::from(tampered);
+        assert!(encrypted_claims.decrypt().is_err());
+    }
+
+    #[test]
+    fn test_decrypt_too_long() {
+        const MAX: usize = EncryptedSub::<1>::MAX_TOKEN_LEN;
+
+        let token = format!("{}....", "a".repeat(MAX));
+        let encrypted_claims: ClaimsEncrypted<EncryptedSub<1>> = Claims::from(token);
+        assert!(matches!(
+            encrypted_claims.decrypt(),
+            Err(Error::TokenTooLong { len, max: MAX }) if len == MAX + 4
+        ));
+    }
+
+    #[test]
+    fn test_decrypt_validates_claims() {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let claims = |iss: &str, aud: &str| {
+            let mut claims = Claims::at(
+                AudienceEncryptedSub::default(),
+                now,
+                Duration::from_secs(60 * 60),
+            );
+            claims.claims.iss = Some(iss.to_string());
+            claims.claims.aud = vec![aud.to_string()];
+            claims.encrypt().unwrap()
+        };
+        let kind = |result: Result<ClaimsDecoded<AudienceEncryptedSub>>| match result {
+            Err(Error::Jsonwebtoken(err)) => Some(err.kind().clone()),
+            _ => None,
+        };
+
+        assert!(claims("https://auth.example.com", "api").decrypt().is_ok());
+        assert_eq!(
+            kind(claims("https://evil.example.com", "api").decrypt()),
+            Some(ErrorKind::InvalidIssuer)
+        );
+        assert_eq!(
+            kind(claims("https://auth.example.com", "billing").decrypt()),
+            Some(ErrorKind::InvalidAudience)
+        );
+
+        // `iat` after `exp` can't have been issued like this
+        let mut claims = Claims::at(EncryptedSub::<1>::default(), now, Duration::from_secs(60));
+        claims.claims.iat = Some(now + 2 * 60);
+        assert!(matches!(
+            claims.encrypt().unwrap().decrypt(),
+            Err(Error::InconsistentTimestamps(_))
+        ));
+    }
+
+    #[test]
+    fn test_encrypt_like_encode() {
+        let result = Claims::new_encrypted(EpochEncryptedSub::default());
+        assert!(matches!(result, Err(Error::SubjectRejected(reason)) if reason == "empty id"));
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let sub = EpochEncryptedSub {
+            id: "123".to_string(),
+        };
+        let encrypted_claims = Claims::new_encrypted_at(sub, now).unwrap();
+
+        // Counted from the offset epoch in the payload, shifted back on decrypt
+        let decoded_claims = encrypted_claims.decrypt().unwrap();
+        assert_eq!(decoded_claims.claims.iat, Some(now));
+    }
+
+    #[test]
+    fn test_decrypt_on_expired() {
+        let sub = ExpiryHookEncryptedSub::default();
+        assert!(Claims::new_encrypted(sub.clone())
+            .unwrap()
+            .decrypt()
+            .is_ok());
+        assert_eq!(EXPIRED_COUNT.load(Ordering::SeqCst), 0);
+
+        let iat = OffsetDateTime::now_utc().unix_timestamp() - 2 * 60 * 60;
+        let encrypted_claims = Claims::new_encrypted_at(sub, iat).unwrap();
+        assert!(matches!(encrypted_claims.decrypt(), Err(Error::Expired)));
+        assert_eq!(EXPIRED_COUNT.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,397 @@
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::{jwk::JwkSet, DecodingKey};
+use serde::de::DeserializeOwned;
+
+use super::{ClaimsDecoded, ClaimsEncoded, ClaimsSubTrait, Decoded, Error, Result};
+
+/// Somewhere a JSON Web Key Set can be fetched from
+pub trait JwksSource {
+    fn fetch(&self) -> Result<JwkSet>;
+}
+
+/// A JWKS served over HTTP(S).
+///
+/// Fetched with `reqwest::blocking`, which panics when called on a thread
+/// running an async runtime, e.g. straight from a tokio task. Decode with a
+/// [`JwksCache`] of it from `tokio::task::spawn_blocking` there, or keep it
+/// fresh with [`JwksCache::spawn_refresher`] so a decode doesn't fetch.
+pub struct HttpSource {
+    url: String,
+    client: reqwest::blocking::Client,
+}
+
+impl HttpSource {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            client: reqwest::blocking::Client::new(),
+        }
+    }
+}
+
+impl JwksSource for HttpSource {
+    fn fetch(&self) -> Result<JwkSet> {
+        let jwks = self
+            .client
+            .get(&self.url)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        Ok(jwks)
+    }
+}
+
+/// A fixed key set, mostly useful for tests
+impl JwksSource for JwkSet {
+    fn fetch(&self) -> Result<JwkSet> {
+        Ok(self.clone())
+    }
+}
+
+/// How long a fetched key set is kept before a `kid` it doesn't hold refetches
+/// it, see [`JwksCache::with_min_refetch_interval`]
+const MIN_REFETCH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Caches the key set of a [`JwksSource`] for `ttl`.
+///
+/// The key set is refetched once the `ttl` has passed, or when a token names
+/// a `kid` that isn't in the cached set (the keys were rotated), at most once
+/// per minimum refetch interval. Callers missing the cache at the same time
+/// wait on a single fetch rather than each fetching.
+pub struct JwksCache<S = HttpSource> {
+    source: S,
+    ttl: Duration,
+    min_refetch_interval: Duration,
+    cached: Mutex<Option<(Instant, JwkSet)>>,
+    /// Held while fetching
+    fetching: Mutex<()>,
+}
+
+/// What the cached key set has to say about a `kid`
+enum Lookup {
+    Found(Result<DecodingKey>),
+    /// Not to be refetched yet
+    Unknown,
+    /// Stale or missing the `kid`, fetched at the given time if at all
+    Refetch(Option<Instant>),
+}
+
+impl JwksCache {
+    /// A cache of the key set at `url`, see [`HttpSource`] for using it in
+    /// async code
+    pub fn new(url: impl Into<String>, ttl: Duration) -> Self {
+        Self::with_source(HttpSource::new(url), ttl)
+    }
+}
+
+impl<S: JwksSource> JwksCache<S> {
+    pub fn with_source(source: S, ttl: Duration) -> Self {
+        Self {
+            source,
+            ttl,
+            min_refetch_interval: MIN_REFETCH_INTERVAL,
+            cached: Mutex::new(None),
+            fetching: Mutex::new(()),
+        }
+    }
+
+    /// Refetch the key set for an unknown `kid` only once it's older than
+    /// `interval`, 10 seconds by default. Otherwise any client could turn
+    /// each of its requests into a fetch by sending random `kid`s.
+    pub fn with_min_refetch_interval(mut self, interval: Duration) -> Self {
+        self.min_refetch_interval = interval;
+        self
+    }
+
+    /// The decoding key with id `kid`
+    pub fn key(&self, kid: &str) -> Result<DecodingKey> {
+        let seen = match self.lookup(kid) {
+            Lookup::Found(key) => return key,
+            Lookup::Unknown => return Err(Error::UnknownKeyId(kid.to_string())),
+            Lookup::Refetch(fetched_at) => fetched_at,
+        };
+
+        let _fetching = self.fetching();
+
+        // Refetched by whoever held the lock before, while this one waited
+        if let Some((fetched_at, jwks)) = self.cached().as_ref() {
+            if Some(*fetched_at) != seen {
+                return find(jwks, kid);
+            }
+        }
+
+        // Fetched without holding the lock of the cache, so decodes with
+        // cached keys don't wait on it
+        let jwks = self.source.fetch()?;
+        let key = find(&jwks, kid);
+        *self.cached() = Some((Instant::now(), jwks));
+
+        key
+    }
+
+    /// Refetch the key set now, keeping the cached one if that fails
+    pub fn refresh(&self) -> Result<()> {
+        let _fetching = self.fetching();
+
+        let jwks = self.source.fetch()?;
+        *self.cached() = Some((Instant::now(), jwks));
+        Ok(())
+    }
+
+    fn lookup(&self, kid: &str) -> Lookup {
+        let cached = self.cached();
+        let (fetched_at, jwks) = match cached.as_ref() {
+            Some(cached) => cached,
+            None => return Lookup::Refetch(None),
+        };
+
+        let age = fetched_at.elapsed();
+        if age >= self.ttl {
+            return Lookup::Refetch(Some(*fetched_at));
+        }
+        match jwks.find(kid) {
+            Some(_) => Lookup::Found(find(jwks, kid)),
+            None if age < self.min_refetch_interval => Lookup::Unknown,
+            None => Lookup::Refetch(Some(*fetched_at)),
+        }
+    }
+
+    fn fetching(&self) -> MutexGuard<'_, ()> {
+        self.fetching.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn cached(&self) -> MutexGuard<'_, Option<(Instant, JwkSet)>> {
+        self.cached.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Refresh the key set every `interval` on a background thread, so a
+    /// decode doesn't wait on a fetch when the cache went stale or the keys
+    /// were rotated. `interval` should be below the `ttl`.
+    ///
+    /// The thread stops once the last `Arc` of the cache is dropped, failed
+    /// fetches are retried on the next tick.
+    pub fn spawn_refresher(self: &Arc<Self>, interval: Duration) -> JoinHandle<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        let cache = Arc::downgrade(self);
+
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            match cache.upgrade() {
+                Some(cache) => {
+                    let _ = cache.refresh();
+                }
+                None => return,
+            }
+        })
+    }
+}
+
+/// The decoding key with id `kid` out of `jwks`
+fn find(jwks: &JwkSet, kid: &str) -> Result<DecodingKey> {
+    match jwks.find(kid) {
+        Some(jwk) => Ok(DecodingKey::from_jwk(jwk)?),
+        None => Err(Error::UnknownKeyId(kid.to_string())),
+    }
+}
+
+impl<T: ClaimsSubTrait> ClaimsEncoded<T> {
+    /// Decode the token with the key named by its `kid` header, taken from `jwks`.
+    pub fn decode_with_jwks<S: JwksSource>(self, jwks: &JwksCache<S>) -> Result<ClaimsDecoded<T>>
+    where
+        Decoded<T>: DeserializeOwned,
+    {
+        T::validate_token_format(&self.claims.0)?;
+
+        let kid = self.peek_kid()?.ok_or(Error::MissingKeyId)?;
+        let key = jwks.key(&kid)?;
+
+        Self::decode_token(&self.claims.0, &key).map(|(_, claims)| claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jwt::{Claims, DecodingClaims};
+    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+    use serde::{Deserialize, Serialize};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+    struct JwksSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for JwksSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const ALGORITHM: Algorithm = Algorithm::RS256;
+    }
+
+    // Only holds the public key, tokens can be verified but not issued
+    impl DecodingClaims for JwksSub {
+        fn decoding_key() -> DecodingKey {
+            DecodingKey::from_jwk(jwks().find("test-key").unwrap()).unwrap()
+        }
+    }
+
+    fn jwks() -> JwkSet {
+        serde_json::from_str(include_str!("../../tests/fixtures/jwks.json")).unwrap()
+    }
+
+    // Serves the key set it holds, which can be swapped to rotate the keys
+    struct RotatingSource {
+        jwks: Mutex<JwkSet>,
+        fetches: AtomicUsize,
+    }
+
+    impl JwksSource for RotatingSource {
+        fn fetch(&self) -> Result<JwkSet> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            Ok(self.jwks.lock().unwrap().clone())
+        }
+    }
+
+    fn token(kid: Option<&str>) -> ClaimsEncoded<JwksSub> {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = kid.map(str::to_string);
+
+        let key = EncodingKey::from_rsa_pem(include_bytes!("../../tests/fixtures/rsa_private.pem"))
+            .unwrap();
+
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let claims = Decoded {
+            sub: JwksSub {
+                id: "123".to_string(),
+            },
+            subject_id: None,
+            iss: None,
+            nbf: None,
+            exp: now + JwksSub::DURATION as i64,
+            iat: Some(now),
+            sv: 0,
+            nonce: None,
+            aud: Vec::new(),
+            jti: None,
+            fingerprint: None,
+            scope: None,
+            token: None,
+        };
+
+        Claims::from(jsonwebtoken::encode(&header, &claims, &key).unwrap())
+    }
+
+    #[test]
+    fn test_decode_with_jwks() {
+        let cache = JwksCache::with_source(jwks(), Duration::from_secs(60));
+
+        let decoded_claims = token(Some("test-key")).decode_with_jwks(&cache).unwrap();
+        assert_eq!(decoded_claims.sub().id, "123");
+
+        let result = token(Some("other-key")).decode_with_jwks(&cache);
+        assert!(matches!(result, Err(Error::UnknownKeyId(kid)) if kid == "other-key"));
+
+        let result = token(None).decode_with_jwks(&cache);
+        assert!(matches!(result, Err(Error::MissingKeyId)));
+    }
+
+    #[test]
+    fn test_decoding_claims() {
+        let decoded_claims = token(Some("test-key")).decode().unwrap();
+        assert_eq!(decoded_claims.sub().id, "123");
+
+        let decoded_claims = token(None).decode().unwrap();
+        assert_eq!(decoded_claims.sub().id, "123");
+    }
+
+    #[test]
+    fn test_spawn_refresher() {
+        let source = RotatingSource {
+            jwks: Mutex::new(jwks()),
+            fetches: AtomicUsize::new(0),
+        };
+        let cache = Arc::new(JwksCache::with_source(source, Duration::from_secs(60 * 60)));
+        assert!(token(Some("test-key")).decode_with_jwks(&cache).is_ok());
+
+        // The same key, rotated to another id
+        let mut rotated = jwks();
+        rotated.keys[0].common.key_id = Some("rotated-key".to_string());
+        *cache.source.jwks.lock().unwrap() = rotated;
+
+        let refresher = cache.spawn_refresher(Duration::from_millis(20));
+        let refreshed = || {
+            let cached = cache.cached();
+            let (_, jwks) = cached.as_ref().unwrap();
+            jwks.find("rotated-key").is_some()
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !refreshed() {
+            assert!(Instant::now() < deadline, "the cache was never refreshed");
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        // Picked up from the refreshed cache, not fetched on the miss
+        let cached = cache.cached();
+        let (_, jwks) = cached.as_ref().unwrap();
+        assert!(jwks.find("test-key").is_none());
+        drop(cached);
+        assert!(token(Some("rotated-key")).decode_with_jwks(&cache).is_ok());
+
+        drop(cache);
+        refresher.join().unwrap();
+    }
+
+    #[test]
+    fn test_single_flight() {
+        // Slow enough for every caller to miss the empty cache
+        struct SlowSource(RotatingSource);
+
+        impl JwksSource for SlowSource {
+            fn fetch(&self) -> Result<JwkSet> {
+                thread::sleep(Duration::from_millis(100));
+                self.0.fetch()
+            }
+        }
+
+        let source = SlowSource(RotatingSource {
+            jwks: Mutex::new(jwks()),
+            fetches: AtomicUsize::new(0),
+        });
+        let cache = JwksCache::with_source(source, Duration::from_secs(60 * 60));
+
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| assert!(cache.key("test-key").is_ok()));
+            }
+        });
+        assert_eq!(cache.source.0.fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_unknown_kid_refetch() {
+        let source = RotatingSource {
+            jwks: Mutex::new(jwks()),
+            fetches: AtomicUsize::new(0),
+        };
+        let cache = JwksCache::with_source(source, Duration::from_secs(60 * 60));
+        assert!(token(Some("test-key")).decode_with_jwks(&cache).is_ok());
+
+        // Not refetched for every unknown `kid`
+        for kid in ["random-1", "random-2", "random-3"] {
+            let result = token(Some(kid)).decode_with_jwks(&cache);
+            assert!(matches!(result, Err(Error::UnknownKeyId(_))));
+        }
+        assert_eq!(cache.source.fetches.load(Ordering::SeqCst), 1);
+
+        let cache = cache.with_min_refetch_interval(Duration::ZERO);
+        let result = token(Some("random-4")).decode_with_jwks(&cache);
+        assert!(matches!(result, Err(Error::UnknownKeyId(_))));
+        assert_eq!(cache.source.fetches.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,117 @@
+use std::{any, sync::OnceLock};
+
+use super::Error;
+
+/// Counts the tokens issued and decoded, e.g. as the OpenTelemetry counters
+/// `tokens_minted_total` and `tokens_decoded_total{result}`, without tying
+/// the crate to a metrics library. Installed for the whole process with
+/// [`set_metrics`].
+///
+/// Only the type name of the Subject and the outcome are passed, never
+/// anything out of a token.
+pub trait Metrics: Send + Sync {
+    /// A token was issued for a Subject of the type `subject`
+    fn token_minted(&self, _subject: &'static str) {}
+
+    /// A token for a Subject of the type `subject` was verified, `result` is
+    /// `"ok"` or the [`Error::code`] of the failure
+    fn token_decoded(&self, _subject: &'static str, _result: &'static str) {}
+}
+
+static METRICS: OnceLock<&'static dyn Metrics> = OnceLock::new();
+
+/// Install `metrics`, `false` if some already were
+pub fn set_metrics(metrics: &'static dyn Metrics) -> bool {
+    METRICS.set(metrics).is_ok()
+}
+
+pub(crate) fn minted<T>() {
+    if let Some(metrics) = METRICS.get() {
+        metrics.token_minted(any::type_name::<T>());
+    }
+}
+
+pub(crate) fn decoded<T>(err: Option<&Error>) {
+    if let Some(metrics) = METRICS.get() {
+        metrics.token_decoded(any::type_name::<T>(), err.map_or("ok", Error::code));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::jwt::{testing::TEST_SECRET, Claims, ClaimsSubTrait, SymmetricClaims};
+
+    #[derive(Default)]
+    struct CountingMetrics {
+        counts: Mutex<HashMap<(&'static str, &'static str), usize>>,
+    }
+
+    impl CountingMetrics {
+        fn count(&self, subject: &'static str, result: &'static str) -> usize {
+            let counts = self.counts.lock().unwrap();
+            counts.get(&(subject, result)).copied().unwrap_or_default()
+        }
+
+        fn add(&self, subject: &'static str, result: &'static str) {
+            *self
+                .counts
+                .lock()
+                .unwrap()
+                .entry((subject, result))
+                .or_default() += 1;
+        }
+    }
+
+    impl Metrics for CountingMetrics {
+        fn token_minted(&self, subject: &'static str) {
+            self.add(subject, "minted");
+        }
+
+        fn token_decoded(&self, subject: &'static str, result: &'static str) {
+            self.add(subject, result);
+        }
+    }
+
+    // Its own Subject, the counts of other tests running alongside are by
+    // the type names of theirs
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct MetricsSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for MetricsSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+    }
+
+    impl SymmetricClaims for MetricsSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        static METRICS: OnceLock<CountingMetrics> = OnceLock::new();
+        let metrics = METRICS.get_or_init(CountingMetrics::default);
+        assert!(set_metrics(metrics));
+        assert!(!set_metrics(metrics));
+
+        let subject = any::type_name::<MetricsSub>();
+        let encoded_claims = Claims::new(MetricsSub::default()).unwrap();
+        let token = encoded_claims.clone().token();
+        assert!(encoded_claims.clone().decode().is_ok());
+        assert!(encoded_claims.decode().is_ok());
+
+        let tampered: crate::jwt::ClaimsEncoded<MetricsSub> = Claims::from(format!("{}x", token));
+        assert!(tampered.decode().is_err());
+
+        assert_eq!(metrics.count(subject, "minted"), 1);
+        assert_eq!(metrics.count(subject, "ok"), 2);
+        assert_eq!(metrics.count(subject, "invalid_signature"), 1);
+    }
+}
@@ -0,0 +1,590 @@
+use serde::{de, de::DeserializeOwned, ser, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use super::{ClaimsSubTrait, Decoded, Error};
+
+/// The registered claims of [`Decoded`]
+const REGISTERED: [&str; 11] = [
+    "sub", "iss", "aud", "jti", "nbf", "exp", "iat", "sv", "nonce", "cnf", "scope",
+];
+
+/// The registered claims no field of a Subject may be named like when its
+/// tokens are issued, see [`check_reserved_claims`]
+const RESERVED: [&str; 6] = ["exp", "iat", "nbf", "iss", "aud", "jti"];
+
+/// Holds the fields of the Subject that collide with a registered claim, and
+/// one named `shadowed` itself
+const SHADOWED: &str = "shadowed";
+
+/// Refuse to issue a token for `sub` when one of its fields is named like a
+/// [`RESERVED`] claim.
+///
+/// Such fields are still read from the `shadowed` object of tokens issued
+/// elsewhere.
+pub(crate) fn check_reserved_claims<T: Serialize>(sub: &T) -> super::Result<()> {
+    let collision = match serde_json::to_value(sub)? {
+        Value::Object(fields) => RESERVED
+            .into_iter()
+            .find(|claim| fields.contains_key(*claim)),
+        _ => None,
+    };
+
+    match collision {
+        Some(claim) => Err(Error::ReservedClaimCollision(claim)),
+        None => Ok(()),
+    }
+}
+
+/// The registered claim `key` is named like in the payload of tokens for `T`,
+/// under its own name or that of [`ClaimsSubTrait::rename_registered_claims`]
+pub(crate) fn registered_claim<T: ClaimsSubTrait>(key: &str) -> Option<&'static str> {
+    REGISTERED
+        .into_iter()
+        .chain(T::rename_registered_claims().iter().map(|(_, name)| *name))
+        .find(|claim| *claim == key)
+}
+
+/// Move the registered claims renamed by
+/// [`ClaimsSubTrait::rename_registered_claims`] back under their own names
+pub(crate) fn restore_registered_names<T: ClaimsSubTrait>(payload: &mut Map<String, Value>) {
+    for (claim, name) in T::rename_registered_claims() {
+        if let Some(value) = payload.remove(*name) {
+            payload.insert(claim.to_string(), value);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Registered {
+    #[serde(rename = "sub", default)]
+    subject_id: Option<String>,
+    #[serde(default)]
+    iss: Option<String>,
+    #[serde(default)]
+    aud: Option<Audience>,
+    #[serde(default)]
+    jti: Option<String>,
+    #[serde(default)]
+    nbf: Option<i64>,
+    exp: i64,
+    #[serde(default)]
+    iat: Option<i64>,
+    // Tokens from before secret versions were introduced have none
+    #[serde(default)]
+    sv: u32,
+    #[serde(default)]
+    nonce: Option<String>,
+    #[serde(default)]
+    cnf: Option<Confirmation>,
+    #[serde(default)]
+    scope: Option<String>,
+}
+
+/// The `cnf` claim binding a token to the fingerprint of a client
+#[derive(Deserialize)]
+struct Confirmation {
+    fp: String,
+}
+
+/// The registered claims holding a timestamp
+const TIMESTAMPS: [&str; 3] = ["exp", "iat", "nbf"];
+
+/// A timestamp claim as either a unix timestamp or an RFC 3339 datetime, see
+/// [`ClaimsSubTrait::LENIENT_TIME_FORMAT`]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LenientTimestamp {
+    Unix(i64),
+    Rfc3339(String),
+}
+
+impl LenientTimestamp {
+    fn unix_timestamp(self) -> Result<i64, time::error::Parse> {
+        match self {
+            Self::Unix(timestamp) => Ok(timestamp),
+            Self::Rfc3339(datetime) => {
+                OffsetDateTime::parse(&datetime, &Rfc3339).map(OffsetDateTime::unix_timestamp)
+            }
+        }
+    }
+}
+
+/// `aud` is either a single audience or an array of them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+// The Subject is flattened into the payload, with any of its fields named like
+// a registered claim moved to `shadowed` so neither clobbers the other.
+impl<T: ClaimsSubTrait> Serialize for Decoded<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut payload = match serde_json::to_value(&self.sub).map_err(ser::Error::custom)? {
+            Value::Object(payload) => payload,
+            Value::Null => Map::new(),
+            _ => return Err(ser::Error::custom("the Subject must serialize to a map")),
+        };
+
+        let mut registered = Map::new();
+        if let Some(subject_id) = &self.subject_id {
+            registered.insert("sub".to_string(), subject_id.as_str().into());
+        }
+        if let Some(iss) = &self.iss {
+            registered.insert("iss".to_string(), iss.as_str().into());
+        }
+        match self.aud.as_slice() {
+            [] => {}
+            [aud] => {
+                registered.insert("aud".to_string(), aud.as_str().into());
+            }
+            aud => {
+                registered.insert("aud".to_string(), aud.into());
+            }
+        }
+        if let Some(jti) = &self.jti {
+            registered.insert("jti".to_string(), jti.as_str().into());
+        }
+        if let Some(nbf) = self.nbf {
+            registered.insert("nbf".to_string(), nbf.into());
+        }
+        registered.insert("exp".to_string(), self.exp.into());
+        if let Some(iat) = self.iat {
+            registered.insert("iat".to_string(), iat.into());
+        }
+        registered.insert("sv".to_string(), self.sv.into());
+        if let Some(nonce) = &self.nonce {
+            registered.insert("nonce".to_string(), nonce.as_str().into());
+        }
+        if let Some(fingerprint) = &self.fingerprint {
+            registered.insert("cnf".to_string(), serde_json::json!({ "fp": fingerprint }));
+        }
+        if let Some(scope) = &self.scope {
+            registered.insert("scope".to_string(), scope.as_str().into());
+        }
+
+        let renames = T::rename_registered_claims();
+        for (claim, name) in renames {
+            if let Some(value) = registered.remove(*claim) {
+                registered.insert(name.to_string(), value);
+            }
+        }
+
+        // Not only the claims set here, decoding takes every registered claim
+        // out of the payload, under either name
+        let shadowed: Map<String, Value> = REGISTERED
+            .iter()
+            .chain(renames.iter().map(|(_, name)| name))
+            .chain([&SHADOWED])
+            .filter_map(|claim| payload.remove_entry(*claim))
+            .collect();
+        if !shadowed.is_empty() {
+            payload.insert(SHADOWED.to_string(), shadowed.into());
+        }
+
+        payload.extend(registered);
+        payload.serialize(serializer)
+    }
+}
+
+impl<'de, T: ClaimsSubTrait + DeserializeOwned> Deserialize<'de> for Decoded<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut payload = Map::<String, Value>::deserialize(deserializer)?;
+        restore_registered_names::<T>(&mut payload);
+
+        if T::LENIENT_TIME_FORMAT {
+            for claim in TIMESTAMPS {
+                if let Some(value) = payload.get_mut(claim) {
+                    let timestamp = LenientTimestamp::deserialize(&*value)
+                        .map_err(de::Error::custom)?
+                        .unix_timestamp()
+                        .map_err(de::Error::custom)?;
+                    *value = timestamp.into();
+                }
+            }
+        }
+
+        let registered =
+            Registered::deserialize(&Value::Object(payload.clone())).map_err(de::Error::custom)?;
+        if T::INCLUDE_IAT && registered.iat.is_none() {
+            return Err(de::Error::missing_field("iat"));
+        }
+
+        for claim in REGISTERED {
+            payload.remove(claim);
+        }
+        match payload.remove(SHADOWED) {
+            Some(Value::Object(shadowed)) => payload.extend(shadowed),
+            // Not a shadow object, but a field of a Subject issued elsewhere
+            Some(value) => {
+                payload.insert(SHADOWED.to_string(), value);
+            }
+            None => {}
+        }
+        let sub = T::deserialize(Value::Object(payload)).map_err(de::Error::custom)?;
+
+        Ok(Self {
+            sub,
+            subject_id: registered.subject_id,
+            iss: registered.iss,
+            nbf: registered.nbf,
+            exp: registered.exp,
+            iat: registered.iat,
+            sv: registered.sv,
+            nonce: registered.nonce,
+            aud: match registered.aud {
+                None => Vec::new(),
+                Some(Audience::One(aud)) => vec![aud],
+                Some(Audience::Many(aud)) => aud,
+            },
+            jti: registered.jti,
+            fingerprint: registered.cnf.map(|cnf| cnf.fp),
+            scope: registered.scope,
+            token: None,
+        })
+    }
+}
+
+// The schema of the payload as serialized above, with the fields of the
+// Subject named like a registered claim under `shadowed`
+#[cfg(feature = "schemars")]
+impl<T: ClaimsSubTrait + schemars::JsonSchema> schemars::JsonSchema for Decoded<T> {
+    fn schema_name() -> String {
+        format!("Decoded_{}", T::schema_name())
+    }
+
+    fn json_schema(generator: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        use schemars::schema::{ObjectValidation, Schema, SchemaObject, SubschemaValidation};
+
+        let object = |properties: Vec<(String, Schema)>, required: Vec<String>| -> Schema {
+            SchemaObject {
+                instance_type: Some(schemars::schema::InstanceType::Object.into()),
+                object: Some(Box::new(ObjectValidation {
+                    properties: properties.into_iter().collect(),
+                    required: required.into_iter().collect(),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }
+            .into()
+        };
+
+        let mut schema = T::json_schema(generator).into_object();
+        let fields = schema.object();
+
+        let renames = T::rename_registered_claims();
+        let shadowed: Vec<_> = REGISTERED
+            .iter()
+            .chain(renames.iter().map(|(_, name)| name))
+            .chain([&SHADOWED])
+            .filter_map(|claim| fields.properties.remove_entry(*claim))
+            .collect();
+        let required: Vec<_> = shadowed
+            .iter()
+            .map(|(field, _)| field.clone())
+            .filter(|field| fields.required.remove(field))
+            .collect();
+        if !required.is_empty() {
+            fields.required.insert(SHADOWED.to_string());
+        }
+        if !shadowed.is_empty() {
+            let shadowed = object(shadowed, required);
+            fields.properties.insert(SHADOWED.to_string(), shadowed);
+        }
+
+        let string = generator.subschema_for::<String>();
+        let timestamp = generator.subschema_for::<i64>();
+        let audience = SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![
+                    string.clone(),
+                    generator.subschema_for::<Vec<String>>(),
+                ]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let confirmation = object(
+            vec![("fp".to_string(), string.clone())],
+            vec!["fp".to_string()],
+        );
+
+        let claims = [
+            ("sub", string.clone()),
+            ("iss", string.clone()),
+            ("aud", audience.into()),
+            ("jti", string.clone()),
+            ("nbf", timestamp.clone()),
+            ("exp", timestamp.clone()),
+            ("iat", timestamp),
+            ("sv", generator.subschema_for::<u32>()),
+            ("nonce", string.clone()),
+            ("cnf", confirmation),
+            ("scope", string),
+        ];
+        let name = |claim: &'static str| {
+            renames
+                .iter()
+                .find(|(renamed, _)| *renamed == claim)
+                .map_or(claim, |(_, name)| *name)
+        };
+        for (claim, schema) in claims {
+            fields.properties.insert(name(claim).to_string(), schema);
+        }
+        fields.required.insert(name("exp").to_string());
+        fields.required.insert(name("sv").to_string());
+        if T::INCLUDE_IAT {
+            fields.required.insert(name("iat").to_string());
+        }
+
+        schema.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jwt::{testing::TEST_SECRET, Claims, ClaimsDecoded, SymmetricClaims};
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+    struct CollidingSub {
+        id: String,
+        iat: String,
+    }
+
+    impl ClaimsSubTrait for CollidingSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+    }
+
+    impl SymmetricClaims for CollidingSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+    struct ExpiringSub {
+        sub: String,
+        exp: i64,
+    }
+
+    impl ClaimsSubTrait for ExpiringSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+    }
+
+    impl SymmetricClaims for ExpiringSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+    struct ShadowingSub {
+        id: String,
+        shadowed: Map<String, Value>,
+    }
+
+    impl ClaimsSubTrait for ShadowingSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+    }
+
+    impl SymmetricClaims for ShadowingSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct LenientSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for LenientSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const LENIENT_TIME_FORMAT: bool = true;
+    }
+
+    impl SymmetricClaims for LenientSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    fn sign(payload: &Value) -> String {
+        jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            payload,
+            &jsonwebtoken::EncodingKey::from_secret(TEST_SECRET),
+        )
+        .unwrap()
+    }
+
+    fn colliding_payload<T: ClaimsSubTrait>(sub: T, iat: i64) -> Value {
+        serde_json::to_value(Decoded {
+            sub,
+            subject_id: None,
+            iss: None,
+            nbf: None,
+            exp: iat + 60 * 60,
+            iat: Some(iat),
+            sv: 0,
+            nonce: None,
+            aud: Vec::new(),
+            jti: None,
+            fingerprint: None,
+            scope: None,
+            token: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_colliding_claims() {
+        let sub = CollidingSub {
+            id: "123".to_string(),
+            iat: "first login".to_string(),
+        };
+        let iat = time::OffsetDateTime::now_utc().unix_timestamp();
+
+        // Not issued by this crate, see `test_reserved_claim_collision`
+        let payload = colliding_payload(sub.clone(), iat);
+        assert_eq!(payload["iat"], iat);
+        assert_eq!(payload["shadowed"]["iat"], "first login");
+        let token = sign(&payload);
+
+        let decoded_claims: ClaimsDecoded<CollidingSub> = token.try_into().unwrap();
+        let decoded = decoded_claims.claims();
+        assert_eq!(decoded.iat, Some(iat));
+        assert_eq!(decoded.sub, sub);
+    }
+
+    #[test]
+    fn test_lenient_time_format() {
+        let now = OffsetDateTime::now_utc();
+        let exp = now + time::Duration::hours(1);
+        let rfc3339 = |datetime: OffsetDateTime| datetime.format(&Rfc3339).unwrap();
+
+        let numeric = sign(&serde_json::json!({
+            "id": "123",
+            "iat": now.unix_timestamp(),
+            "exp": exp.unix_timestamp(),
+        }));
+        let decoded_claims = Claims::<LenientSub, _>::from(numeric).decode().unwrap();
+        assert_eq!(decoded_claims.claims().exp, exp.unix_timestamp());
+
+        let string = sign(&serde_json::json!({
+            "id": "123",
+            "iat": rfc3339(now),
+            "exp": rfc3339(exp),
+        }));
+        let decoded_claims = Claims::<LenientSub, _>::from(string.clone())
+            .decode()
+            .unwrap();
+        let decoded = decoded_claims.claims();
+        assert_eq!(decoded.iat, Some(now.unix_timestamp()));
+        assert_eq!(decoded.exp, exp.unix_timestamp());
+
+        // Still expired when the datetime lies in the past
+        let expired = sign(&serde_json::json!({
+            "id": "123",
+            "iat": rfc3339(now - time::Duration::hours(3)),
+            "exp": rfc3339(now - time::Duration::hours(2)),
+        }));
+        assert!(matches!(
+            Claims::<LenientSub, _>::from(expired).decode(),
+            Err(crate::jwt::Error::Expired)
+        ));
+
+        // Only numeric timestamps unless the Subject opts in
+        assert!(Claims::<CollidingSub, _>::from(string).decode().is_err());
+    }
+
+    #[test]
+    fn test_shadowed_registered_claims() {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+
+        // A Subject's own `exp` can neither expire the token early
+        let sub = ExpiringSub {
+            sub: "user".to_string(),
+            exp: now - 60 * 60,
+        };
+        let token = sign(&colliding_payload(sub.clone(), now));
+        let decoded_claims: ClaimsDecoded<ExpiringSub> = token.try_into().unwrap();
+        let decoded = decoded_claims.claims();
+        assert_eq!(decoded.exp, now + 60 * 60);
+        assert_eq!(decoded.subject_id, None);
+        assert_eq!(decoded.sub, sub);
+
+        // nor extend its lifetime
+        let sub = ExpiringSub {
+            sub: "user".to_string(),
+            exp: now + 24 * 60 * 60,
+        };
+        let token = sign(&colliding_payload(sub, now - 2 * 60 * 60));
+        assert!(matches!(
+            ClaimsDecoded::<ExpiringSub>::try_from(token),
+            Err(Error::Expired)
+        ));
+    }
+
+    #[test]
+    fn test_reserved_claim_collision() {
+        let sub = ExpiringSub {
+            sub: "user".to_string(),
+            exp: 0,
+        };
+
+        assert!(matches!(
+            Claims::new(sub),
+            Err(Error::ReservedClaimCollision("exp"))
+        ));
+    }
+
+    #[test]
+    fn test_shadowed_field() {
+        let mut shadowed = Map::new();
+        shadowed.insert("id".to_string(), "456".into());
+        let sub = ShadowingSub {
+            id: "123".to_string(),
+            shadowed,
+        };
+
+        let encoded_claims = Claims::new(sub.clone()).unwrap();
+        let payload = encoded_claims.unverified_payload().unwrap();
+        assert_eq!(payload["shadowed"]["shadowed"]["id"], "456");
+
+        let decoded_claims = encoded_claims.decode().unwrap();
+        assert_eq!(decoded_claims.sub(), sub);
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn test_json_schema() {
+        #[derive(Serialize, Deserialize, schemars::JsonSchema)]
+        struct SchemaSub {
+            id: String,
+            exp: String,
+        }
+
+        impl ClaimsSubTrait for SchemaSub {
+            const DURATION: u64 = 60 * 60; // 1 hour
+        }
+
+        let schema = serde_json::to_value(schemars::schema_for!(Decoded<SchemaSub>)).unwrap();
+        let properties = &schema["properties"];
+        assert_eq!(properties["id"]["type"], "string");
+        assert_eq!(properties["exp"]["type"], "integer");
+        assert_eq!(properties["iat"]["type"], "integer");
+        assert_eq!(
+            properties["shadowed"]["properties"]["exp"]["type"],
+            "string"
+        );
+
+        let required = schema["required"].as_array().unwrap();
+        for field in ["id", "exp", "iat", "sv", "shadowed"] {
+            assert!(required.contains(&field.into()), "{} isn't required", field);
+        }
+    }
+}
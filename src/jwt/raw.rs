@@ -0,0 +1,457 @@
+use std::{borrow::Cow, collections::BTreeMap, fmt, marker::PhantomData, time::Duration};
+
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Validation};
+use serde::{de, de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{value::RawValue, Map, Value};
+
+use super::{
+    payload, AlgorithmFamily, AudienceMatch, AudienceSet, ClaimsDecoded, ClaimsSubTrait, Decoded,
+    DecodingClaims, EncodingClaims,
+};
+
+/// A Subject kept as raw JSON, verified with the keys and validation of `K`.
+///
+/// Useful for passing a Subject through without knowing its structure. The
+/// hooks of `K` run on the raw JSON read as a `K`.
+pub struct RawSubject<K> {
+    raw: Box<RawValue>,
+    parent_jti: Option<String>,
+    _keys: PhantomData<fn() -> K>,
+}
+
+impl<K: ClaimsSubTrait + DeserializeOwned> RawSubject<K> {
+    pub fn new(raw: Box<RawValue>) -> Self {
+        let mut subject = Self {
+            raw,
+            parent_jti: None,
+            _keys: PhantomData,
+        };
+        subject.parent_jti = subject
+            .subject()
+            .ok()
+            .and_then(|sub| sub.parent_jti().map(String::from));
+
+        subject
+    }
+
+    /// The raw JSON read as a `K`
+    fn subject(&self) -> serde_json::Result<K> {
+        serde_json::from_str(self.raw.get())
+    }
+}
+
+impl<K> RawSubject<K> {
+    pub fn get(&self) -> &RawValue {
+        &self.raw
+    }
+
+    pub fn into_inner(self) -> Box<RawValue> {
+        self.raw
+    }
+}
+
+impl<K: ClaimsSubTrait + DeserializeOwned> ClaimsDecoded<RawSubject<K>> {
+    /// Add the claim `key` to the Subject, e.g. permissions computed by a
+    /// gateway before it signs the claims again with
+    /// [`Claims::reencode_preserving`]. An existing claim `key` is replaced,
+    /// one named like a registered claim is refused with
+    /// [`Error::ReservedClaimCollision`].
+    ///
+    /// Only for a `RawSubject`, other Subjects have no room for claims their
+    /// type doesn't know of.
+    ///
+    /// [`Claims::reencode_preserving`]: super::Claims::reencode_preserving
+    /// [`Error::ReservedClaimCollision`]: super::Error::ReservedClaimCollision
+    pub fn with_extra(mut self, key: &str, value: Value) -> super::Result<Self> {
+        if let Some(claim) = payload::registered_claim::<K>(key) {
+            return Err(super::Error::ReservedClaimCollision(claim));
+        }
+
+        let mut object: Map<String, Value> = serde_json::from_str(self.claims.sub.raw.get())?;
+        object.insert(key.to_string(), value);
+        self.claims.sub = RawSubject::new(serde_json::value::to_raw_value(&object)?);
+
+        Ok(self)
+    }
+}
+
+impl<K> fmt::Debug for RawSubject<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RawSubject").field(&self.raw).finish()
+    }
+}
+
+// `RawValue` can't be (de)serialized through `#[serde(flatten)]`, so go through
+// a JSON object instead.
+impl<K> Serialize for RawSubject<K> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let object: Map<String, Value> =
+            serde_json::from_str(self.raw.get()).map_err(serde::ser::Error::custom)?;
+        object.serialize(serializer)
+    }
+}
+
+impl<'de, K: ClaimsSubTrait + DeserializeOwned> Deserialize<'de> for RawSubject<K> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let object = Map::<String, Value>::deserialize(deserializer)?;
+        let raw = serde_json::value::to_raw_value(&object).map_err(de::Error::custom)?;
+        Ok(Self::new(raw))
+    }
+}
+
+impl<K: ClaimsSubTrait + DeserializeOwned> ClaimsSubTrait for RawSubject<K> {
+    const DURATION: u64 = K::DURATION;
+    const ALGORITHM: Algorithm = K::ALGORITHM;
+    const COMPRESS: bool = K::COMPRESS;
+    const INCLUDE_IAT: bool = K::INCLUDE_IAT;
+    const EPOCH_OFFSET: i64 = K::EPOCH_OFFSET;
+    const LENIENT_TIME_FORMAT: bool = K::LENIENT_TIME_FORMAT;
+    const TOLERANT_QUOTES: bool = K::TOLERANT_QUOTES;
+    const ALLOW_EXPIRED: bool = K::ALLOW_EXPIRED;
+    const AUDIENCE_MATCH: AudienceMatch = K::AUDIENCE_MATCH;
+    const NBF_LEEWAY: Option<u64> = K::NBF_LEEWAY;
+    const EXPECTED_TYP: Option<&'static str> = K::EXPECTED_TYP;
+    const CONTENT_TYPE: Option<&'static str> = K::CONTENT_TYPE;
+    const MAX_AGE: Option<u64> = K::MAX_AGE;
+    const SECRET_VERSION: u32 = K::SECRET_VERSION;
+    const MIN_SECRET_VERSION: u32 = K::MIN_SECRET_VERSION;
+    const ROTATION_GRACE: u64 = K::ROTATION_GRACE;
+    const MAX_TOKEN_LEN: usize = K::MAX_TOKEN_LEN;
+
+    fn duration() -> Duration {
+        K::duration()
+    }
+
+    fn secret_rotation_window() -> Duration {
+        K::secret_rotation_window()
+    }
+
+    fn subject_id(&self) -> Option<String> {
+        self.subject().ok()?.subject_id()
+    }
+
+    fn parent_jti(&self) -> Option<&str> {
+        self.parent_jti.as_deref()
+    }
+
+    fn generate_nonce() -> Option<String> {
+        K::generate_nonce()
+    }
+
+    fn fingerprint() -> Option<String> {
+        K::fingerprint()
+    }
+
+    fn issuer() -> Option<String> {
+        K::issuer()
+    }
+
+    fn additional_headers() -> BTreeMap<String, Value> {
+        K::additional_headers()
+    }
+
+    fn certificate_chain() -> Vec<Vec<u8>> {
+        K::certificate_chain()
+    }
+
+    fn rename_registered_claims() -> &'static [(&'static str, &'static str)] {
+        K::rename_registered_claims()
+    }
+
+    fn audiences() -> AudienceSet {
+        K::audiences()
+    }
+
+    fn audiences_runtime() -> Vec<String> {
+        K::audiences_runtime()
+    }
+
+    fn accepted_algorithms() -> Vec<Algorithm> {
+        K::accepted_algorithms()
+    }
+
+    fn algorithm_family() -> AlgorithmFamily {
+        K::algorithm_family()
+    }
+
+    fn validation() -> Validation {
+        K::validation()
+    }
+
+    fn validate_token_format(token: &str) -> super::Result<()> {
+        K::validate_token_format(token)
+    }
+
+    fn validate_sub(&self) -> Result<(), String> {
+        self.subject()
+            .map_err(|err| err.to_string())?
+            .validate_sub()
+    }
+
+    fn pre_sign_validate(&self) -> Result<(), String> {
+        self.subject()
+            .map_err(|err| err.to_string())?
+            .pre_sign_validate()
+    }
+
+    // Only the fields of `K` are canonicalized, others are kept as they are.
+    fn canonicalize(self) -> Self {
+        let canonical = match self.subject().map(K::canonicalize) {
+            Ok(canonical) => canonical,
+            Err(_) => return self,
+        };
+        let (mut object, fields) = match (
+            serde_json::from_str::<Map<String, Value>>(self.raw.get()),
+            serde_json::to_value(canonical),
+        ) {
+            (Ok(object), Ok(Value::Object(fields))) => (object, fields),
+            _ => return self,
+        };
+        object.extend(fields);
+
+        match serde_json::value::to_raw_value(&object) {
+            Ok(raw) => Self::new(raw),
+            Err(_) => self,
+        }
+    }
+
+    fn post_encode(token: String) -> String {
+        K::post_encode(token)
+    }
+
+    fn pre_decode(token: &str) -> Cow<'_, str> {
+        K::pre_decode(token)
+    }
+
+    fn on_decode(decoded: &Decoded<Self>) {
+        let sub = match decoded.sub.subject() {
+            Ok(sub) => sub,
+            Err(_) => return,
+        };
+
+        K::on_decode(&Decoded {
+            sub,
+            subject_id: decoded.subject_id.clone(),
+            iss: decoded.iss.clone(),
+            nbf: decoded.nbf,
+            exp: decoded.exp,
+            iat: decoded.iat,
+            sv: decoded.sv,
+            nonce: decoded.nonce.clone(),
+            aud: decoded.aud.clone(),
+            jti: decoded.jti.clone(),
+            fingerprint: decoded.fingerprint.clone(),
+            scope: decoded.scope.clone(),
+            token: None,
+        })
+    }
+
+    fn on_expired() {
+        K::on_expired()
+    }
+}
+
+impl<K: EncodingClaims + DeserializeOwned> EncodingClaims for RawSubject<K> {
+    fn encoding_key() -> EncodingKey {
+        K::encoding_key()
+    }
+
+    fn validate_encoding_key() -> super::Result<()> {
+        K::validate_encoding_key()
+    }
+}
+
+impl<K: DecodingClaims + DeserializeOwned> DecodingClaims for RawSubject<K> {
+    fn decoding_key() -> DecodingKey {
+        K::decoding_key()
+    }
+
+    fn previous_decoding_keys() -> Vec<(u32, DecodingKey)> {
+        K::previous_decoding_keys()
+    }
+
+    fn validate_decoding_key() -> super::Result<()> {
+        K::validate_decoding_key()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::jwt::{testing::TEST_SECRET, Claims, ClaimsDecoded, Error, SymmetricClaims};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct RawTestSub {
+        id: String,
+        name: String,
+    }
+
+    impl ClaimsSubTrait for RawTestSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+    }
+
+    impl SymmetricClaims for RawTestSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    static HOOKED_DECODES: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct HookedRawSub {
+        id: String,
+        name: String,
+        parent: String,
+    }
+
+    impl ClaimsSubTrait for HookedRawSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const MAX_TOKEN_LEN: usize = 512;
+
+        fn subject_id(&self) -> Option<String> {
+            Some(format!("user|{}", self.id))
+        }
+
+        fn parent_jti(&self) -> Option<&str> {
+            Some(&self.parent)
+        }
+
+        fn validate_sub(&self) -> std::result::Result<(), String> {
+            match self.name.as_str() {
+                "blocked" => Err("blocked".to_string()),
+                _ => Ok(()),
+            }
+        }
+
+        fn pre_sign_validate(&self) -> std::result::Result<(), String> {
+            match self.id.is_empty() {
+                true => Err("empty id".to_string()),
+                false => Ok(()),
+            }
+        }
+
+        fn canonicalize(self) -> Self {
+            Self {
+                name: self.name.to_lowercase(),
+                ..self
+            }
+        }
+
+        fn on_decode(_decoded: &Decoded<Self>) {
+            HOOKED_DECODES.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    impl SymmetricClaims for HookedRawSub {
+        fn secret<'a>() -> &'a [u8] {
+            TEST_SECRET
+        }
+    }
+
+    fn hooked(value: Value) -> RawSubject<HookedRawSub> {
+        RawSubject::new(serde_json::value::to_raw_value(&value).unwrap())
+    }
+
+    #[test]
+    fn test_raw_subject_hooks() {
+        let result = Claims::new(hooked(
+            serde_json::json!({ "id": "", "name": "John", "parent": "p" }),
+        ));
+        assert!(matches!(result, Err(Error::SubjectRejected(_))));
+
+        let token = Claims::new(hooked(
+            serde_json::json!({ "id": "123", "name": "JOHN", "parent": "p", "perm": "read" }),
+        ))
+        .unwrap()
+        .token();
+
+        let decodes = HOOKED_DECODES.load(Ordering::SeqCst);
+        let decoded_claims: ClaimsDecoded<RawSubject<HookedRawSub>> = token.try_into().unwrap();
+        assert_eq!(HOOKED_DECODES.load(Ordering::SeqCst), decodes + 1);
+        assert_eq!(decoded_claims.parent_jti(), Some("p"));
+        assert_eq!(decoded_claims.claims.subject_id(), Some("user|123"));
+
+        // Only the fields of `K` are canonicalized
+        let value: Value = serde_json::from_str(decoded_claims.sub().get().get()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({ "id": "123", "name": "john", "parent": "p", "perm": "read" })
+        );
+
+        let blocked = Claims::new(hooked(
+            serde_json::json!({ "id": "123", "name": "blocked", "parent": "p" }),
+        ))
+        .unwrap()
+        .token();
+        let result: crate::jwt::Result<ClaimsDecoded<RawSubject<HookedRawSub>>> =
+            blocked.try_into();
+        assert!(matches!(result, Err(Error::SubjectRejected(_))));
+
+        let result: crate::jwt::Result<ClaimsDecoded<RawSubject<HookedRawSub>>> =
+            "a".repeat(513).try_into();
+        assert!(matches!(result, Err(Error::TokenTooLong { max: 512, .. })));
+    }
+
+    #[test]
+    fn test_raw_subject() {
+        let token = Claims::new(RawTestSub {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        })
+        .unwrap()
+        .token();
+
+        let tampered = format!("{}x", token);
+
+        let decoded_claims: ClaimsDecoded<RawSubject<RawTestSub>> = token.try_into().unwrap();
+        let raw = decoded_claims.sub().into_inner();
+
+        let value: Value = serde_json::from_str(raw.get()).unwrap();
+        assert_eq!(value, serde_json::json!({ "id": "123", "name": "John" }));
+
+        // The signature is still verified
+        let result: crate::jwt::Result<ClaimsDecoded<RawSubject<RawTestSub>>> = tampered.try_into();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_with_extra() {
+        let token = Claims::new(RawTestSub {
+            id: "123".to_string(),
+            name: "John".to_string(),
+        })
+        .unwrap()
+        .token();
+
+        let decoded_claims: ClaimsDecoded<RawSubject<RawTestSub>> = token.try_into().unwrap();
+        let token = decoded_claims
+            .with_extra("perm", serde_json::json!(["read", "write"]))
+            .unwrap()
+            .with_extra("name", serde_json::json!("Jane"))
+            .unwrap()
+            .reencode_preserving()
+            .unwrap()
+            .token();
+
+        let decoded_claims: ClaimsDecoded<RawSubject<RawTestSub>> =
+            token.clone().try_into().unwrap();
+        let value: Value = serde_json::from_str(decoded_claims.sub().get().get()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({ "id": "123", "name": "Jane", "perm": ["read", "write"] })
+        );
+
+        // Registered claims can't be added to the Subject
+        for claim in ["exp", "iat", "aud", "sv"] {
+            let decoded_claims: ClaimsDecoded<RawSubject<RawTestSub>> =
+                token.clone().try_into().unwrap();
+            assert!(matches!(
+                decoded_claims.with_extra(claim, serde_json::json!(0)),
+                Err(Error::ReservedClaimCollision(found)) if found == claim
+            ));
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::sync::{Mutex, MutexGuard};
+
+use ::redis::{Commands, ConnectionLike, RedisResult};
+
+use super::TokenStore;
+
+/// Keeps the revoked `jti`s in Redis, so they're revoked for every instance
+/// sharing it and survive restarts.
+///
+/// Those revoked with [`TokenStore::revoke_until`] expire along with their
+/// token, which needs Redis 6.2 or later. As the trait can't report errors, a
+/// `jti` is taken for revoked when Redis can't be reached, and a failed
+/// revocation is lost, see [`RedisTokenStore::try_revoke`] to handle them.
+pub struct RedisTokenStore<C> {
+    connection: Mutex<C>,
+    prefix: String,
+}
+
+impl<C: ConnectionLike> RedisTokenStore<C> {
+    /// Store the revoked `jti`s with `connection`, e.g. of
+    /// `redis::Client::get_connection`, under the keys `revoked:<jti>`
+    pub fn new(connection: C) -> Self {
+        Self {
+            connection: Mutex::new(connection),
+            prefix: "revoked:".to_string(),
+        }
+    }
+
+    /// Store them under the keys `<prefix><jti>` instead
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Revoke `jti` until the unix timestamp `exp`, for good without one
+    pub fn try_revoke(&self, jti: &str, exp: Option<i64>) -> RedisResult<()> {
+        let key = self.key(jti);
+        let mut connection = self.connection();
+
+        match exp {
+            Some(exp) => ::redis::cmd("SET")
+                .arg(key)
+                .arg(1)
+                .arg("EXAT")
+                .arg(exp)
+                .exec(&mut *connection),
+            None => connection.set(key, 1),
+        }
+    }
+
+    /// Whether `jti` was revoked
+    pub fn try_is_revoked(&self, jti: &str) -> RedisResult<bool> {
+        let key = self.key(jti);
+        self.connection().exists(key)
+    }
+
+    fn key(&self, jti: &str) -> String {
+        format!("{}{}", self.prefix, jti)
+    }
+
+    fn connection(&self) -> MutexGuard<'_, C> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<C: ConnectionLike> TokenStore for RedisTokenStore<C> {
+    fn revoke(&self, jti: &str) {
+        let _ = self.try_revoke(jti, None);
+    }
+
+    fn revoke_until(&self, jti: &str, exp: i64) {
+        let _ = self.try_revoke(jti, Some(exp));
+    }
+
+    fn is_revoked(&self, jti: &str) -> bool {
+        self.try_is_revoked(jti).unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::redis::{cmd, ErrorKind, RedisError};
+    use redis_test::{MockCmd, MockRedisConnection};
+
+    use super::*;
+    use crate::jwt::{testing::TestSub, Claims, Error};
+
+    #[test]
+    fn test_redis_token_store() {
+        let encoded_claims = Claims::builder(TestSub::new("123"))
+            .jti("token-1")
+            .build()
+            .unwrap();
+        let exp = encoded_claims.clone().decode().unwrap().claims().exp;
+        let token = encoded_claims.token();
+
+        let connection = MockRedisConnection::new([
+            MockCmd::new(cmd("EXISTS").arg("revoked:token-1"), Ok(0)),
+            MockCmd::new(
+                cmd("SET")
+                    .arg("revoked:token-1")
+                    .arg(1)
+                    .arg("EXAT")
+                    .arg(exp),
+                Ok("OK"),
+            ),
+            MockCmd::new(cmd("EXISTS").arg("revoked:token-1"), Ok(1)),
+            MockCmd::new(
+                cmd("EXISTS").arg("revoked:token-1"),
+                Err::<i64, _>(RedisError::from((ErrorKind::Io, "connection refused"))),
+            ),
+        ])
+        .assert_all_commands_consumed();
+        let store = RedisTokenStore::new(connection);
+
+        assert!(store.verify::<TestSub>(&token).is_ok());
+        store.revoke_until("token-1", exp);
+        assert!(matches!(
+            store.verify::<TestSub>(&token),
+            Err(Error::Revoked(jti)) if jti == "token-1"
+        ));
+
+        // Not taken for valid while Redis can't be reached
+        assert!(store.verify::<TestSub>(&token).is_err());
+    }
+}
@@ -0,0 +1,83 @@
+//! (De)serialize durations as whole seconds, for use as
+//! `#[serde(with = "typed_string_encoding::jwt::serde_seconds")]` on the
+//! fields of a Subject.
+//!
+//! Works for both [`std::time::Duration`] and [`time::Duration`], any fraction
+//! of a second is dropped.
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+/// A duration that can be (de)serialized as whole seconds
+pub trait Seconds: Sized {
+    fn serialize_seconds<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+
+    fn deserialize_seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>;
+}
+
+impl Seconds for std::time::Duration {
+    fn serialize_seconds<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.as_secs())
+    }
+
+    fn deserialize_seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(Self::from_secs)
+    }
+}
+
+impl Seconds for time::Duration {
+    fn serialize_seconds<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(self.whole_seconds())
+    }
+
+    fn deserialize_seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        i64::deserialize(deserializer).map(Self::seconds)
+    }
+}
+
+pub fn serialize<T: Seconds, S: Serializer>(
+    duration: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    duration.serialize_seconds(serializer)
+}
+
+pub fn deserialize<'de, T: Seconds, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+    T::deserialize_seconds(deserializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Durations {
+        #[serde(with = "super")]
+        std: std::time::Duration,
+        #[serde(with = "super")]
+        time: time::Duration,
+    }
+
+    #[test]
+    fn test_serde_seconds() {
+        let durations = Durations {
+            std: std::time::Duration::from_secs(90),
+            time: time::Duration::seconds(-30),
+        };
+
+        let json = serde_json::to_string(&durations).unwrap();
+        assert_eq!(json, r#"{"std":90,"time":-30}"#);
+
+        let deserialized: Durations = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, durations);
+
+        // Fractions of a second are dropped
+        let durations = Durations {
+            std: std::time::Duration::from_millis(1500),
+            time: time::Duration::milliseconds(2500),
+        };
+        let json = serde_json::to_string(&durations).unwrap();
+        assert_eq!(json, r#"{"std":1,"time":2}"#);
+    }
+}
@@ -0,0 +1,116 @@
+use std::{borrow::Cow, collections::HashSet, sync::Mutex};
+
+use jsonwebtoken::errors::ErrorKind;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Deserialize;
+
+use super::{Claims, ClaimsDecoded, Decoded, DecodingClaims, EncodingClaims, Error, Result};
+
+/// Issues tokens with a `jti` claim, so they can be revoked before they expire.
+pub trait TokenStore {
+    /// Record `jti` as revoked
+    fn revoke(&self, jti: &str);
+
+    /// Record `jti` as revoked until the unix timestamp `exp`, when its token
+    /// expires anyway, e.g. so the store can drop it then. Just
+    /// [`TokenStore::revoke`] unless overridden.
+    fn revoke_until(&self, jti: &str, _exp: i64) {
+        self.revoke(jti)
+    }
+
+    fn is_revoked(&self, jti: &str) -> bool;
+
+    /// A new, unique `jti`: 16 random bytes as hex
+    fn generate_jti(&self) -> Result<String> {
+        let mut jti = [0; 16];
+        SystemRandom::new()
+            .fill(&mut jti)
+            .map_err(|_| Error::Jsonwebtoken(ErrorKind::Crypto(ring::error::Unspecified).into()))?;
+
+        Ok(jti.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    /// Issue a token for `sub` with a new `jti`
+    fn issue<T: EncodingClaims>(&self, sub: T) -> Result<String> {
+        let jti = self.generate_jti()?;
+
+        Ok(Claims::builder(sub).jti(jti).build()?.token())
+    }
+
+    /// Decode `token`, rejecting it when it has no `jti` or it was revoked
+    fn verify<T>(&self, token: &str) -> Result<ClaimsDecoded<T>>
+    where
+        T: DecodingClaims,
+        Decoded<T>: for<'de> Deserialize<'de>,
+    {
+        let decoded_claims = ClaimsDecoded::<T>::try_from(Cow::Borrowed(token))?;
+
+        match decoded_claims.claims.jti() {
+            None => {
+                let kind = ErrorKind::MissingRequiredClaim("jti".to_string());
+                Err(Error::Jsonwebtoken(kind.into()))
+            }
+            Some(jti) if self.is_revoked(jti) => Err(Error::Revoked(jti.to_string())),
+            Some(_) => Ok(decoded_claims),
+        }
+    }
+}
+
+/// Keeps the revoked `jti`s in memory, they're lost on restart.
+#[derive(Debug, Default)]
+pub struct MemoryTokenStore {
+    revoked: Mutex<HashSet<String>>,
+}
+
+impl MemoryTokenStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn revoke(&self, jti: &str) {
+        let mut revoked = self.revoked.lock().unwrap_or_else(|err| err.into_inner());
+        revoked.insert(jti.to_string());
+    }
+
+    fn is_revoked(&self, jti: &str) -> bool {
+        let revoked = self.revoked.lock().unwrap_or_else(|err| err.into_inner());
+        revoked.contains(jti)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jwt::testing::TestSub;
+
+    #[test]
+    fn test_memory_token_store() {
+        let store = MemoryTokenStore::new();
+        let sub = TestSub::new("123");
+
+        let token = store.issue(sub.clone()).unwrap();
+        let decoded = store.verify::<TestSub>(&token).unwrap().claims();
+        assert_eq!(decoded.sub, sub);
+        let jti = decoded.jti().unwrap().to_string();
+
+        // Every token has its own `jti`
+        let other = store.issue(sub.clone()).unwrap();
+        assert_ne!(
+            store.verify::<TestSub>(&other).unwrap().claims().jti(),
+            Some(jti.as_str())
+        );
+
+        store.revoke(&jti);
+        assert!(matches!(
+            store.verify::<TestSub>(&token),
+            Err(Error::Revoked(revoked)) if revoked == jti
+        ));
+        assert!(store.verify::<TestSub>(&other).is_ok());
+
+        // Tokens without a `jti` can't be revoked, so aren't accepted
+        let token = Claims::new(sub).unwrap().token();
+        assert!(store.verify::<TestSub>(&token).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+//! Helpers for testing code built on top of the claims, without re-declaring
+//! Subjects and backdating tokens by hand.
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    clock, Claims, ClaimsEncoded, ClaimsSubTrait, EncodingClaims, Result, SymmetricClaims,
+};
+
+/// A Subject with a fixed secret, valid for an hour
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MockSecret {
+    pub id: String,
+}
+
+impl MockSecret {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self { id: id.into() }
+    }
+}
+
+impl ClaimsSubTrait for MockSecret {
+    const DURATION: u64 = 60 * 60; // 1 hour
+}
+
+impl SymmetricClaims for MockSecret {
+    fn secret<'a>() -> &'a [u8] {
+        b"mock_secret_of_at_least_32_bytes"
+    }
+}
+
+/// The secret the Subjects of this crate's own tests are signed with
+#[cfg(test)]
+pub(crate) const TEST_SECRET: &[u8] = b"test_secret_of_at_least_32_bytes";
+
+/// The Subject of this crate's own tests, valid for an hour
+#[cfg(test)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct TestSub {
+    pub(crate) id: String,
+}
+
+#[cfg(test)]
+impl TestSub {
+    pub(crate) fn new(id: impl Into<String>) -> Self {
+        Self { id: id.into() }
+    }
+}
+
+#[cfg(test)]
+impl ClaimsSubTrait for TestSub {
+    const DURATION: u64 = 60 * 60; // 1 hour
+}
+
+#[cfg(test)]
+impl SymmetricClaims for TestSub {
+    fn secret<'a>() -> &'a [u8] {
+        TEST_SECRET
+    }
+}
+
+/// Issues tokens as if the current time were fixed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedClock {
+    now: i64,
+}
+
+impl FixedClock {
+    /// The fixed unix timestamp
+    pub fn now(&self) -> i64 {
+        self.now
+    }
+
+    /// Issue a token for `sub` at the fixed time
+    pub fn issue<T: EncodingClaims>(&self, sub: T) -> Result<ClaimsEncoded<T>> {
+        Claims::new_at(sub, self.now)
+    }
+}
+
+/// A clock stopped at the unix timestamp `now`
+pub fn fixed_clock(now: i64) -> FixedClock {
+    FixedClock { now }
+}
+
+/// A correctly signed token for `sub` that expired a second ago, beyond the
+/// leeway of `T`.
+pub fn expired_token<T: EncodingClaims>(sub: T) -> Result<ClaimsEncoded<T>> {
+    let leeway = T::validation().leeway;
+    let ttl = T::duration().as_secs().saturating_add(leeway) as i64;
+    let now = clock::now();
+
+    fixed_clock(now.saturating_sub(ttl).saturating_sub(1)).issue(sub)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jwt::Error;
+    use time::OffsetDateTime;
+
+    #[test]
+    fn test_instantly_expired_token() {
+        let encoded_claims = expired_token(MockSecret::new("123")).unwrap();
+
+        assert!(matches!(encoded_claims.decode(), Err(Error::Expired)));
+    }
+
+    #[test]
+    fn test_fixed_clock() {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let clock = fixed_clock(now - 30);
+
+        let decoded_claims = clock
+            .issue(MockSecret::new("123"))
+            .unwrap()
+            .decode()
+            .unwrap();
+        assert_eq!(decoded_claims.claims().iat, Some(clock.now()));
+    }
+}
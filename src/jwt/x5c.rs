@@ -0,0 +1,144 @@
+//! Verify tokens with the key of the certificate in their `x5c` header.
+//!
+//! The chain itself isn't validated here, anyone can put a certificate in the
+//! header of a token they sign. Only the caller knows which certificates to
+//! trust, so it has to check the chain before the key of its leaf is used.
+
+use jsonwebtoken::DecodingKey;
+use serde::de::DeserializeOwned;
+use simple_asn1::{from_der, ASN1Block};
+
+use super::{ClaimsDecoded, ClaimsEncoded, ClaimsSubTrait, Decoded, Error, Result};
+
+const RSA_ENCRYPTION: &[u64] = &[1, 2, 840, 113549, 1, 1, 1];
+const EC_PUBLIC_KEY: &[u64] = &[1, 2, 840, 10045, 2, 1];
+const ED25519: &[u64] = &[1, 3, 101, 112];
+
+/// The decoding key of the public key of the DER encoded certificate `der`,
+/// for RSA, EC and Ed25519 keys.
+pub fn decoding_key(der: &[u8]) -> Result<DecodingKey> {
+    let blocks = from_der(der).map_err(|_| Error::Certificate("not DER encoded"))?;
+
+    // Certificate ::= SEQUENCE { tbsCertificate, signatureAlgorithm, signature }
+    let tbs_certificate = match blocks.first() {
+        Some(ASN1Block::Sequence(_, certificate)) => match certificate.first() {
+            Some(ASN1Block::Sequence(_, tbs_certificate)) => tbs_certificate,
+            _ => return Err(Error::Certificate("missing `tbsCertificate`")),
+        },
+        _ => return Err(Error::Certificate("not a certificate")),
+    };
+
+    // The first field shaped like SubjectPublicKeyInfo ::= SEQUENCE {
+    // algorithm AlgorithmIdentifier, subjectPublicKey BIT STRING }, the
+    // fields before it are all shaped differently
+    let (algorithm, public_key) = tbs_certificate
+        .iter()
+        .find_map(|block| match block {
+            ASN1Block::Sequence(_, info) => match info.as_slice() {
+                [ASN1Block::Sequence(_, algorithm), ASN1Block::BitString(_, _, key)] => {
+                    match algorithm.first() {
+                        Some(ASN1Block::ObjectIdentifier(_, oid)) => Some((oid, key)),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .ok_or(Error::Certificate("missing `subjectPublicKeyInfo`"))?;
+
+    let algorithm = algorithm
+        .as_vec::<u64>()
+        .map_err(|_| Error::Certificate("invalid key algorithm"))?;
+
+    match algorithm.as_slice() {
+        RSA_ENCRYPTION => Ok(DecodingKey::from_rsa_der(public_key)),
+        EC_PUBLIC_KEY => Ok(DecodingKey::from_ec_der(public_key)),
+        ED25519 => Ok(DecodingKey::from_ed_der(public_key)),
+        _ => Err(Error::Certificate("unsupported key algorithm")),
+    }
+}
+
+impl<T: ClaimsSubTrait> ClaimsEncoded<T> {
+    /// Decode the token with the key of the leaf certificate of its `x5c`
+    /// header, once `trusted` accepted the DER encoded chain.
+    pub fn decode_with_x5c(
+        self,
+        trusted: impl FnOnce(&[Vec<u8>]) -> bool,
+    ) -> Result<ClaimsDecoded<T>>
+    where
+        Decoded<T>: DeserializeOwned,
+    {
+        T::validate_token_format(&self.claims.0)?;
+
+        let chain = jsonwebtoken::decode_header(&self.claims.0)?
+            .x5c
+            .ok_or(Error::Certificate("missing `x5c` header"))?
+            .iter()
+            .map(base64::decode)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|_| Error::Certificate("not base64 encoded"))?;
+
+        let leaf = chain.first().ok_or(Error::Certificate("empty chain"))?;
+        let key = decoding_key(leaf)?;
+        if !trusted(&chain) {
+            return Err(Error::Certificate("untrusted chain"));
+        }
+
+        Self::decode_token(&self.claims.0, &key).map(|(_, claims)| claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jwt::{Claims, EncodingClaims};
+    use jsonwebtoken::{Algorithm, EncodingKey};
+    use serde::{Deserialize, Serialize};
+
+    const CERTIFICATE: &[u8] = include_bytes!("../../tests/fixtures/rsa_cert.der");
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+    struct X5cSub {
+        id: String,
+    }
+
+    impl ClaimsSubTrait for X5cSub {
+        const DURATION: u64 = 60 * 60; // 1 hour
+        const ALGORITHM: Algorithm = Algorithm::RS256;
+
+        fn certificate_chain() -> Vec<Vec<u8>> {
+            vec![CERTIFICATE.to_vec()]
+        }
+    }
+
+    // Only holds the private key, tokens are verified with the certificate
+    impl EncodingClaims for X5cSub {
+        fn encoding_key() -> EncodingKey {
+            EncodingKey::from_rsa_pem(include_bytes!("../../tests/fixtures/rsa_private.pem"))
+                .unwrap()
+        }
+    }
+
+    #[test]
+    fn test_decode_with_x5c() {
+        let encoded_claims = Claims::new(X5cSub {
+            id: "123".to_string(),
+        })
+        .unwrap();
+
+        let decoded_claims = encoded_claims
+            .clone()
+            .decode_with_x5c(|chain| chain == [CERTIFICATE])
+            .unwrap();
+        assert_eq!(decoded_claims.sub().id, "123");
+
+        let result = encoded_claims.decode_with_x5c(|_| false);
+        assert!(matches!(result, Err(Error::Certificate("untrusted chain"))));
+
+        assert!(matches!(
+            decoding_key(b"not a certificate"),
+            Err(Error::Certificate(_))
+        ));
+    }
+}
@@ -1,4 +1,6 @@
 pub mod v1;
+#[cfg(feature = "v2")]
 pub mod v2;
 
+#[cfg(feature = "jwt")]
 pub mod jwt;
@@ -2,27 +2,35 @@ use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 use typed_string_encoding::{
-    jwt::{Claims, ClaimsDecoded, ClaimsSubTrait},
+    jwt::{Claims, ClaimsDecoded, ClaimsSubTrait, SymmetricClaims},
     v1, v2,
 };
 
 #[derive(Debug, Deserialize, Serialize)]
 struct Subject1 {
-    sub: String,
+    id: String,
     name: String,
 }
 
 impl Subject1 {
-    fn new(sub: String, name: String) -> Self {
-        Self { sub, name }
+    fn new(id: String, name: String) -> Self {
+        Self { id, name }
     }
 }
 
 impl ClaimsSubTrait for Subject1 {
-    const DURATION: u64 = Duration::from_secs(24 * 60 * 60).as_secs();
+    fn duration() -> Duration {
+        Duration::from_secs(24 * 60 * 60)
+    }
+
+    fn subject_id(&self) -> Option<String> {
+        Some(self.id.clone())
+    }
+}
 
+impl SymmetricClaims for Subject1 {
     fn secret<'a>() -> &'a [u8] {
-        "secret".as_bytes()
+        "a demo secret of at least 32 bytes".as_bytes()
     }
 }
 
@@ -1,7 +1,27 @@
-use std::marker::PhantomData;
+use std::{fmt::Display, marker::PhantomData, str::FromStr};
+
+use thiserror::Error as ErrorTrait;
+
+#[derive(Debug, ErrorTrait)]
+pub(crate) enum Error {
+    #[error(transparent)]
+    Base64(#[from] base64::DecodeError),
+    #[error(transparent)]
+    Utf8(#[from] std::string::FromUtf8Error),
+    #[error("failed to parse secret from `{0}`")]
+    Parse(String),
+}
+pub(crate) type Result<T> = std::result::Result<T, Error>;
 
 struct Secret1(String);
 struct Secret2(usize);
+#[derive(Debug, PartialEq)]
+struct Port(u16);
+
+/// A secret encoded with `Display` and decoded with `FromStr`, a newtype so
+/// it doesn't overlap with hand written `Secret` impls
+#[derive(Debug, PartialEq)]
+struct Displayable<T>(T);
 
 struct EncodedSecret<T: Secret> {
     content: String,
@@ -14,7 +34,19 @@ where
 {
     fn encode(self) -> EncodedSecret<Self>;
 
-    fn decode(secret: EncodedSecret<Self>) -> Self;
+    fn decode(secret: EncodedSecret<Self>) -> Result<Self>;
+
+    /// `encode` with the content base64url encoded
+    fn encode_base64(self) -> EncodedSecret<Self> {
+        let content = self.encode().content;
+        EncodedSecret::new(base64::encode_config(content, base64::URL_SAFE_NO_PAD))
+    }
+
+    /// Inverse of `encode_base64`
+    fn decode_base64(secret: EncodedSecret<Self>) -> Result<Self> {
+        let content = base64::decode_config(secret.content, base64::URL_SAFE_NO_PAD)?;
+        Self::decode(EncodedSecret::new(String::from_utf8(content)?))
+    }
 }
 
 impl<T: Secret> EncodedSecret<T> {
@@ -25,9 +57,20 @@ impl<T: Secret> EncodedSecret<T> {
         }
     }
 
-    fn decode(self) -> T {
+    fn decode(self) -> Result<T> {
         T::decode(self)
     }
+
+    fn decode_base64(self) -> Result<T> {
+        T::decode_base64(self)
+    }
+
+    /// The same content as an encoded `U`, e.g. while migrating from `T` to `U`.
+    ///
+    /// Unchecked, the content must be valid for `U` to decode.
+    fn reinterpret<U: Secret>(self) -> EncodedSecret<U> {
+        EncodedSecret::new(self.content)
+    }
 }
 
 impl Secret for Secret1 {
@@ -35,9 +78,9 @@ impl Secret for Secret1 {
         EncodedSecret::new(self.0)
     }
 
-    fn decode(secret: EncodedSecret<Self>) -> Self {
+    fn decode(secret: EncodedSecret<Self>) -> Result<Self> {
         eprintln!("Decoding to Secret1");
-        Secret1(secret.content)
+        Ok(Secret1(secret.content))
     }
 }
 
@@ -47,12 +90,55 @@ impl Secret for Secret2 {
         EncodedSecret::new(content)
     }
 
-    fn decode(secret: EncodedSecret<Self>) -> Self {
+    fn decode(secret: EncodedSecret<Self>) -> Result<Self> {
         eprintln!("Decoding to Secret2");
-        Secret2(secret.content.parse().unwrap())
+        let content = secret.content;
+        content
+            .parse()
+            .map(Secret2)
+            .map_err(|_| Error::Parse(content))
     }
 }
 
+impl<T: FromStr + Display> Secret for Displayable<T> {
+    fn encode(self) -> EncodedSecret<Self> {
+        EncodedSecret::new(self.0.to_string())
+    }
+
+    fn decode(secret: EncodedSecret<Self>) -> Result<Self> {
+        let content = secret.content;
+        content
+            .parse()
+            .map(Displayable)
+            .map_err(|_| Error::Parse(content))
+    }
+}
+
+/// Implement `Secret` for single field tuple structs through the `Display`
+/// and `FromStr` of their field, like [`Displayable`] but for a named newtype.
+///
+/// Stands in for a `Secret` derive: the trait is private to this module, so
+/// neither a proc-macro crate nor trybuild tests outside of it could name it.
+macro_rules! impl_secret {
+    ($($newtype:ident),+ $(,)?) => {$(
+        impl Secret for $newtype {
+            fn encode(self) -> EncodedSecret<Self> {
+                EncodedSecret::new(self.0.to_string())
+            }
+
+            fn decode(secret: EncodedSecret<Self>) -> Result<Self> {
+                let content = secret.content;
+                content
+                    .parse()
+                    .map($newtype)
+                    .map_err(|_| Error::Parse(content))
+            }
+        }
+    )+};
+}
+
+impl_secret!(Port);
+
 pub fn main() {
     let secret1 = Secret1("toor123".into());
     let secret2 = Secret2(42);
@@ -62,10 +148,34 @@ pub fn main() {
     let encoded_secret1 = secret1.encode();
     let encoded_secret2 = secret2.encode();
 
-    let decoded_secret1 = encoded_secret1.decode();
-    let decoded_secret2 = encoded_secret2.decode();
+    let decoded_secret1 = encoded_secret1.decode().unwrap();
+    let decoded_secret2 = encoded_secret2.decode().unwrap();
+
+    println!("{:?}, {:?}", decoded_secret1.0, decoded_secret2.0);
+
+    let encoded_secret1 = decoded_secret1.encode_base64();
+    let encoded_secret2 = decoded_secret2.encode_base64();
+
+    println!(
+        "{:?}, {:?}",
+        encoded_secret1.content, encoded_secret2.content
+    );
+
+    let decoded_secret1 = encoded_secret1.decode_base64().unwrap();
+    let decoded_secret2 = encoded_secret2.decode_base64().unwrap();
 
     println!("{:?}, {:?}", decoded_secret1.0, decoded_secret2.0);
+
+    let secret3 = Displayable(-1.5);
+    let decoded_secret3 = secret3.encode_base64().decode_base64().unwrap();
+
+    println!("{:?}", decoded_secret3.0);
+
+    let port = Port(8080).encode_base64().decode_base64().unwrap();
+    println!("{:?}", port);
+
+    let migrated_secret = Secret1("42".into()).encode().reinterpret::<Secret2>();
+    println!("{:?}", migrated_secret.decode().unwrap().0);
 }
 
 #[cfg(test)]
@@ -76,7 +186,7 @@ mod tests {
     fn test_secret1_encode_decode() {
         let secret = Secret1(String::from("My secret message"));
         let encoded_secret = secret.encode();
-        let decoded_secret = encoded_secret.decode();
+        let decoded_secret = encoded_secret.decode().unwrap();
         assert_eq!(decoded_secret.0, "My secret message");
     }
 
@@ -84,7 +194,7 @@ mod tests {
     fn test_secret2_encode_decode() {
         let secret = Secret2(42);
         let encoded_secret = secret.encode();
-        let decoded_secret = encoded_secret.decode();
+        let decoded_secret = encoded_secret.decode().unwrap();
         assert_eq!(decoded_secret.0, 42);
     }
 
@@ -99,4 +209,105 @@ mod tests {
         let encoded_secret = EncodedSecret::<Secret2>::new(String::from("42"));
         assert_eq!(encoded_secret.content, "42");
     }
+
+    #[test]
+    fn test_secret1_encode_decode_base64() {
+        let secret = Secret1(String::from("My secret message?"));
+        let encoded_secret = secret.encode_base64();
+        assert_eq!(encoded_secret.content, "TXkgc2VjcmV0IG1lc3NhZ2U_");
+        let decoded_secret = encoded_secret.decode_base64().unwrap();
+        assert_eq!(decoded_secret.0, "My secret message?");
+    }
+
+    #[test]
+    fn test_secret2_encode_decode_base64() {
+        let secret = Secret2(42);
+        let encoded_secret = secret.encode_base64();
+        assert_eq!(encoded_secret.content, "NDI");
+        let decoded_secret = encoded_secret.decode_base64().unwrap();
+        assert_eq!(decoded_secret.0, 42);
+    }
+
+    #[test]
+    fn test_secret2_decode_invalid() {
+        let encoded_secret = EncodedSecret::<Secret2>::new(String::from("not a number"));
+        assert!(matches!(encoded_secret.decode(), Err(Error::Parse(_))));
+
+        let encoded_secret = EncodedSecret::<Secret2>::new(String::from("not base64!"));
+        assert!(matches!(
+            encoded_secret.decode_base64(),
+            Err(Error::Base64(_))
+        ));
+
+        // Valid base64 of invalid UTF-8
+        let encoded_secret = EncodedSecret::<Secret1>::new(String::from("_w"));
+        assert!(matches!(
+            encoded_secret.decode_base64(),
+            Err(Error::Utf8(_))
+        ));
+    }
+
+    #[test]
+    fn test_displayable_encode_decode() {
+        let encoded_secret = Displayable(-42_i64).encode();
+        assert_eq!(encoded_secret.content, "-42");
+        assert_eq!(encoded_secret.decode().unwrap(), Displayable(-42));
+
+        let encoded_secret = Displayable(1.5_f64).encode_base64();
+        assert_eq!(encoded_secret.decode_base64().unwrap(), Displayable(1.5));
+
+        #[derive(Debug, PartialEq)]
+        struct Version(u8, u8);
+
+        impl Display for Version {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}.{}", self.0, self.1)
+            }
+        }
+
+        impl FromStr for Version {
+            type Err = ();
+
+            fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+                let (major, minor) = s.split_once('.').ok_or(())?;
+                Ok(Version(
+                    major.parse().map_err(|_| ())?,
+                    minor.parse().map_err(|_| ())?,
+                ))
+            }
+        }
+
+        let encoded_secret = Displayable(Version(1, 2)).encode();
+        assert_eq!(encoded_secret.content, "1.2");
+        assert_eq!(encoded_secret.decode().unwrap(), Displayable(Version(1, 2)));
+
+        let encoded_secret = EncodedSecret::<Displayable<Version>>::new("1".to_string());
+        assert!(matches!(encoded_secret.decode(), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_reinterpret() {
+        let encoded_secret = Secret1(String::from("42")).encode();
+        let encoded_secret: EncodedSecret<Secret2> = encoded_secret.reinterpret();
+        assert_eq!(encoded_secret.decode().unwrap().0, 42);
+
+        let encoded_secret = Secret1(String::from("forty two")).encode();
+        assert!(matches!(
+            encoded_secret.reinterpret::<Secret2>().decode(),
+            Err(Error::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_impl_secret() {
+        let encoded_secret = Port(8080).encode();
+        assert_eq!(encoded_secret.content, "8080");
+        assert_eq!(encoded_secret.decode().unwrap(), Port(8080));
+
+        let decoded_secret = Port(443).encode_base64().decode_base64().unwrap();
+        assert_eq!(decoded_secret, Port(443));
+
+        let result = EncodedSecret::<Port>::new(String::from("-1")).decode();
+        assert!(matches!(result, Err(Error::Parse(content)) if content == "-1"));
+    }
 }
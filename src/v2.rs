@@ -1,27 +1,212 @@
-use std::{fmt::Debug, marker::PhantomData};
+use std::{fmt::Debug, fs, io, marker::PhantomData, path::Path};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use thiserror::Error as ErrorTrait;
+
+#[derive(Debug, ErrorTrait)]
+pub(crate) enum Error {
+    #[error("failed to deserialize secret from `{0}`")]
+    Deserialize(String),
+    #[cfg(feature = "postcard")]
+    #[error("failed to serialize secret: {0}")]
+    Serialize(String),
+    #[error("failed to encrypt or decrypt secret")]
+    Crypto,
+    #[error("failed to read secret: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid secret frame: {0}")]
+    Frame(&'static str),
+    #[error("unsupported secret format version {0}")]
+    UnsupportedFormat(u8),
+}
+pub(crate) type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
 struct Encoded(String);
+/// Encoded as raw bytes, for binary codecs and binary-safe storage
+#[derive(Debug)]
+struct EncodedBytes(Vec<u8>);
 #[derive(Debug)]
 pub(crate) struct Decoded<T: Debug>(T);
 
 #[derive(Debug)]
 pub(crate) struct Secret<T: SecretTrait, State = Decoded<T>> {
     content: State,
-    _type: PhantomData<T>,
+    // Not `PhantomData<T>`, an encoded secret is `Send + Sync` whatever `T` is
+    _type: PhantomData<fn() -> T>,
 }
 
+// Secrets can be shared across threads, checked for any `T`
+#[allow(dead_code)]
+const _: () = {
+    fn assert_send_sync<S: Send + Sync>() {}
+
+    fn assert_all<T: SecretTrait, U: SecretTrait + Send + Sync>() {
+        assert_send_sync::<Secret<T, Encoded>>();
+        assert_send_sync::<Secret<T, EncodedBytes>>();
+        assert_send_sync::<Secret<U>>();
+        assert_send_sync::<Error>();
+    }
+};
+
 /// Placeholder for `serde::Serialize`
 pub(crate) trait Serialize {
     fn serialize(self) -> String;
 }
 
 /// Placeholder for `serde::DeserializeOwned`
-pub(crate) trait Deserialize {
-    fn deserialize(value: String) -> Self;
+pub(crate) trait Deserialize: Sized {
+    fn deserialize(value: String) -> Result<Self>;
+}
+
+/// Length of the AES-GCM nonce prefixed to an encrypted secret
+const NONCE_LEN: usize = 12;
+
+/// The format version `encrypt` tags its output with
+const FORMAT_VERSION: u8 = 1;
+
+/// The format version of the encrypted secret `encoded`.
+///
+/// Since version 1 it's the leading digit, followed by padded base64url.
+/// Version 0 secrets are untagged and unpadded, which makes their length
+/// never one more than a multiple of 4 like a tagged secret's.
+fn format_version(encoded: &str) -> Result<u8> {
+    if encoded.len() % 4 != 1 {
+        return Ok(0);
+    }
+
+    match encoded.as_bytes()[0] {
+        tag @ b'1'..=b'9' if tag - b'0' <= FORMAT_VERSION => Ok(tag - b'0'),
+        tag @ b'1'..=b'9' => Err(Error::UnsupportedFormat(tag - b'0')),
+        _ => Err(Error::Crypto),
+    }
+}
+
+/// AES-256-GCM encrypt `plaintext` authenticating `aad` along with it,
+/// base64url encoded with the nonce prefixed and tagged with the
+/// [`FORMAT_VERSION`]
+fn encrypt(key: &[u8; 32], plaintext: &[u8], aad: &[u8]) -> Result<String> {
+    let cipher = Aes256Gcm::new(key.into());
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| Error::Crypto)?;
+
+    let mut bytes = nonce.to_vec();
+    bytes.extend(ciphertext);
+    Ok(format!(
+        "{}{}",
+        FORMAT_VERSION,
+        base64::encode_config(bytes, base64::URL_SAFE)
+    ))
+}
+
+/// Inverse of `encrypt`, also for secrets of an earlier format version
+fn decrypt(key: &[u8; 32], encrypted: &str, aad: &[u8]) -> Result<String> {
+    let bytes = match format_version(encrypted)? {
+        0 => base64::decode_config(encrypted, base64::URL_SAFE_NO_PAD),
+        _ => base64::decode_config(&encrypted[1..], base64::URL_SAFE),
+    }
+    .map_err(|_| Error::Crypto)?;
+    if bytes.len() < NONCE_LEN {
+        return Err(Error::Crypto);
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+
+    let cipher = Aes256Gcm::new(key.into());
+    let plaintext = cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| Error::Crypto)?;
+    String::from_utf8(plaintext).map_err(|_| Error::Crypto)
+}
+
+pub(crate) trait SecretTrait: Serialize + Deserialize + Debug {}
+
+/// Turns a secret into the content of an encoded `Secret` and back
+pub(crate) trait Codec<T> {
+    fn encode(secret: T) -> Result<String>;
+
+    fn decode(value: String) -> Result<T>;
+}
+
+/// Turns a secret into the content of a byte encoded `Secret` and back
+pub(crate) trait BytesCodec<T> {
+    fn encode_bytes(secret: T) -> Result<Vec<u8>>;
+
+    fn decode_bytes(bytes: Vec<u8>) -> Result<T>;
+}
+
+/// The `Serialize` and `Deserialize` of the secret itself
+pub(crate) struct PlainCodec;
+
+impl<T: Serialize + Deserialize> Codec<T> for PlainCodec {
+    fn encode(secret: T) -> Result<String> {
+        Ok(secret.serialize())
+    }
+
+    fn decode(value: String) -> Result<T> {
+        T::deserialize(value)
+    }
+}
+
+impl<T: Serialize + Deserialize> BytesCodec<T> for PlainCodec {
+    fn encode_bytes(secret: T) -> Result<Vec<u8>> {
+        Ok(secret.serialize().into_bytes())
+    }
+
+    fn decode_bytes(bytes: Vec<u8>) -> Result<T> {
+        let value = String::from_utf8(bytes)
+            .map_err(|err| Error::Deserialize(String::from_utf8_lossy(err.as_bytes()).into()))?;
+        T::deserialize(value)
+    }
+}
+
+/// Compact binary encoding with `postcard`, base64url encoded
+#[cfg(feature = "postcard")]
+pub(crate) struct PostcardCodec;
+
+#[cfg(feature = "postcard")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> Codec<T> for PostcardCodec {
+    fn encode(secret: T) -> Result<String> {
+        let bytes =
+            postcard::to_allocvec(&secret).map_err(|err| Error::Serialize(err.to_string()))?;
+        Ok(base64::encode_config(bytes, base64::URL_SAFE_NO_PAD))
+    }
+
+    fn decode(value: String) -> Result<T> {
+        base64::decode_config(&value, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .and_then(|bytes| postcard::from_bytes(&bytes).ok())
+            .ok_or(Error::Deserialize(value))
+    }
 }
 
-pub(crate) trait SecretTrait: Serialize + Deserialize + Debug + Clone {}
+/// The `postcard` encoding as is, without the base64url of [`PostcardCodec`]
+#[cfg(feature = "postcard")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> BytesCodec<T> for PostcardCodec {
+    fn encode_bytes(secret: T) -> Result<Vec<u8>> {
+        postcard::to_allocvec(&secret).map_err(|err| Error::Serialize(err.to_string()))
+    }
+
+    fn decode_bytes(bytes: Vec<u8>) -> Result<T> {
+        postcard::from_bytes(&bytes).map_err(|err| Error::Deserialize(err.to_string()))
+    }
+}
 
 impl<T: SecretTrait> Secret<T> {
     fn new(secret: T) -> Secret<T, Decoded<T>> {
@@ -33,20 +218,154 @@ impl<T: SecretTrait> Secret<T> {
 }
 
 impl<T: SecretTrait> Secret<T, Encoded> {
-    fn decode(self) -> Secret<T, Decoded<T>> {
+    fn decode(self) -> Result<Secret<T, Decoded<T>>> {
+        self.decode_as()
+    }
+
+    /// Decode the encoded secret as a different `SecretTrait`
+    fn decode_as<U: SecretTrait>(self) -> Result<Secret<U, Decoded<U>>> {
         let string = self.content.0;
 
         // Decrypt/verify string/jwt token/secret
 
+        Ok(Secret {
+            content: Decoded(U::deserialize(string)?),
+            _type: PhantomData,
+        })
+    }
+
+    /// Decrypt and decode a secret encrypted with `key`
+    fn decrypt(self, key: &[u8; 32]) -> Result<Secret<T, Decoded<T>>> {
+        self.decrypt_with_aad(key, &[])
+    }
+
+    /// Decrypt and decode a secret encrypted with `key` for the context `aad`,
+    /// failing for any other context
+    fn decrypt_with_aad(self, key: &[u8; 32], aad: &[u8]) -> Result<Secret<T, Decoded<T>>> {
+        let decrypted: Secret<T, Encoded> = Secret {
+            content: Encoded(decrypt(key, &self.content.0, aad)?),
+            _type: PhantomData,
+        };
+        decrypted.decode()
+    }
+
+    /// Encrypt a secret encrypted with `key` under `new_key` instead.
+    ///
+    /// The current `key` has to be passed in as the secret doesn't hold it,
+    /// the content is decrypted with it before being encrypted again.
+    fn rotate(self, key: &[u8; 32], new_key: &[u8; 32]) -> Result<Secret<T, Encoded>> {
+        self.decrypt(key)?.encrypt(new_key)
+    }
+
+    /// Encrypt a secret encrypted with `key` in the current format, if it's
+    /// of an earlier format version
+    fn upgrade(self, key: &[u8; 32]) -> Result<Secret<T, Encoded>> {
+        if format_version(&self.content.0)? == FORMAT_VERSION {
+            return Ok(self);
+        }
+
+        self.decrypt(key)?.encrypt(key)
+    }
+
+    /// Decode the encoded secret with `C`, the inverse of `encode_with`
+    fn decode_with<C: Codec<T>>(self) -> Result<Secret<T, Decoded<T>>> {
+        Ok(Secret {
+            content: Decoded(C::decode(self.content.0)?),
+            _type: PhantomData,
+        })
+    }
+
+    /// Store the encoded secret at `path`
+    fn write_to(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, &self.content.0)
+    }
+
+    /// Load an encoded secret stored with `write_to`
+    fn read_from(path: &Path) -> Result<Secret<T, Encoded>> {
+        Ok(Secret {
+            content: Encoded(fs::read_to_string(path)?),
+            _type: PhantomData,
+        })
+    }
+
+    /// The encoded secret prefixed with its length as a big endian `u32`, so
+    /// several can be concatenated, failing for content of 4 GiB or more
+    fn frame(&self) -> Result<Vec<u8>> {
+        let content = self.content.0.as_bytes();
+        let len = u32::try_from(content.len()).map_err(|_| Error::Frame("content too long"))?;
+
+        let mut frame = len.to_be_bytes().to_vec();
+        frame.extend_from_slice(content);
+        Ok(frame)
+    }
+
+    /// Parse the first frame of `input`, returning its secret and the rest of `input`
+    fn unframe(input: &[u8]) -> Result<(Secret<T, Encoded>, &[u8])> {
+        if input.len() < 4 {
+            return Err(Error::Frame("missing length prefix"));
+        }
+        let (len, rest) = input.split_at(4);
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+
+        if rest.len() < len {
+            return Err(Error::Frame("truncated content"));
+        }
+        let (content, rest) = rest.split_at(len);
+        let content =
+            String::from_utf8(content.to_vec()).map_err(|_| Error::Frame("content isn't UTF-8"))?;
+
+        let secret = Secret {
+            content: Encoded(content),
+            _type: PhantomData,
+        };
+        Ok((secret, rest))
+    }
+
+    fn encoded_secret(&self) -> &str {
+        &self.content.0
+    }
+}
+
+impl<T: SecretTrait> Secret<T, EncodedBytes> {
+    /// Decode the byte encoded secret with `C`, the inverse of `encode_bytes_with`
+    fn decode_bytes_with<C: BytesCodec<T>>(self) -> Result<Secret<T, Decoded<T>>> {
+        Ok(Secret {
+            content: Decoded(C::decode_bytes(self.content.0)?),
+            _type: PhantomData,
+        })
+    }
+
+    /// A byte encoded secret, e.g. read back from binary storage
+    fn from_bytes(bytes: Vec<u8>) -> Secret<T, EncodedBytes> {
         Secret {
-            content: Decoded(T::deserialize(string)),
+            content: EncodedBytes(bytes),
             _type: PhantomData,
         }
     }
 
-    fn encoded_secret(&self) -> String {
-        // Clone for demonstration
-        self.content.0.clone()
+    fn encoded_bytes(&self) -> &[u8] {
+        &self.content.0
+    }
+}
+
+/// Serialized as the bare encoded secret, e.g. to keep it in a config file
+impl<T: SecretTrait> serde::Serialize for Secret<T, Encoded> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.content.0)
+    }
+}
+
+impl<'de, T: SecretTrait> serde::Deserialize<'de> for Secret<T, Encoded> {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        Ok(Secret {
+            content: Encoded(<String as serde::Deserialize>::deserialize(deserializer)?),
+            _type: PhantomData,
+        })
     }
 }
 
@@ -62,15 +381,47 @@ impl<T: SecretTrait> Secret<T, Decoded<T>> {
         }
     }
 
-    fn secret(&self) -> T {
-        // Clone for demonstration
-        self.content.0.clone()
+    /// Encode the secret, AES-256-GCM encrypted with `key`
+    fn encrypt(self, key: &[u8; 32]) -> Result<Secret<T, Encoded>> {
+        self.encrypt_with_aad(key, &[])
+    }
+
+    /// `encrypt`, bound to the context `aad` it's authenticated with, e.g. the
+    /// id of the user it belongs to, so it can't be used in another one
+    fn encrypt_with_aad(self, key: &[u8; 32], aad: &[u8]) -> Result<Secret<T, Encoded>> {
+        let encoded = self.encode();
+
+        Ok(Secret {
+            content: Encoded(encrypt(key, encoded.content.0.as_bytes(), aad)?),
+            _type: PhantomData,
+        })
+    }
+
+    /// Encode the secret with `C` instead of its own `Serialize`
+    fn encode_with<C: Codec<T>>(self) -> Result<Secret<T, Encoded>> {
+        Ok(Secret {
+            content: Encoded(C::encode(self.content.0)?),
+            _type: PhantomData,
+        })
+    }
+
+    /// Encode the secret to raw bytes with `C`, e.g. for binary storage that
+    /// doesn't need the base64 of a string encoded secret
+    fn encode_bytes_with<C: BytesCodec<T>>(self) -> Result<Secret<T, EncodedBytes>> {
+        Ok(Secret {
+            content: EncodedBytes(C::encode_bytes(self.content.0)?),
+            _type: PhantomData,
+        })
+    }
+
+    fn secret(&self) -> &T {
+        &self.content.0
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct Secret1(String);
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub(crate) struct Secret2(usize);
 
 impl SecretTrait for Secret1 {}
@@ -78,8 +429,8 @@ impl SecretTrait for Secret2 {}
 impl SecretTrait for usize {}
 
 impl Deserialize for Secret1 {
-    fn deserialize(value: String) -> Self {
-        Self(value)
+    fn deserialize(value: String) -> Result<Self> {
+        Ok(Self(value))
     }
 }
 impl Serialize for Secret1 {
@@ -89,8 +440,8 @@ impl Serialize for Secret1 {
 }
 
 impl Deserialize for Secret2 {
-    fn deserialize(value: String) -> Self {
-        Self(value.parse().unwrap())
+    fn deserialize(value: String) -> Result<Self> {
+        usize::deserialize(value).map(Self)
     }
 }
 impl Serialize for Secret2 {
@@ -100,8 +451,8 @@ impl Serialize for Secret2 {
 }
 
 impl Deserialize for usize {
-    fn deserialize(value: String) -> Self {
-        value.parse().unwrap()
+    fn deserialize(value: String) -> Result<Self> {
+        value.parse().map_err(|_| Error::Deserialize(value))
     }
 }
 impl Serialize for usize {
@@ -141,9 +492,14 @@ pub fn main() {
     //secret_2.encode(); // no method named `encode`
     //secret_3.encode(); // no method named `encode`
 
-    let secret_1 = secret_1.decode();
-    let secret_2 = secret_2.decode();
-    let secret_3 = secret_3.decode();
+    let mut blob = secret_1.frame().unwrap();
+    blob.extend(secret_3.frame().unwrap());
+    let (secret_1, rest) = Secret::<Secret1, Encoded>::unframe(&blob).unwrap();
+    let (secret_3, _) = Secret::<usize, Encoded>::unframe(rest).unwrap();
+
+    let secret_1 = secret_1.decode().unwrap();
+    let secret_2 = secret_2.decode().unwrap();
+    let secret_3 = secret_3.decode().unwrap();
 
     println!(
         "{:?}, {:?}, {:?}",
@@ -151,6 +507,44 @@ pub fn main() {
         secret_2.secret(),
         secret_3.secret(),
     );
+
+    let secret_2 = secret_2.encode_with::<PlainCodec>().unwrap();
+    println!("{:?}", secret_2.encoded_secret());
+    let secret_2 = secret_2.decode_with::<PlainCodec>().unwrap();
+
+    #[cfg(feature = "postcard")]
+    let secret_2 = {
+        let secret_2 = secret_2.encode_with::<PostcardCodec>().unwrap();
+        println!("{:?}", secret_2.encoded_secret());
+        secret_2.decode_with::<PostcardCodec>().unwrap()
+    };
+
+    let secret_2 = secret_2.encode_bytes_with::<PlainCodec>().unwrap();
+    println!("{:?}", secret_2.encoded_bytes());
+    let secret_2 = Secret::<Secret2, EncodedBytes>::from_bytes(secret_2.encoded_bytes().to_vec());
+    let secret_2 = secret_2.decode_bytes_with::<PlainCodec>().unwrap();
+
+    let key = [1; 32];
+    let secret_2 = secret_2.encrypt(&key).unwrap();
+    println!("{:?}", secret_2.encoded_secret());
+    let new_key = [2; 32];
+    let secret_2 = secret_2.rotate(&key, &new_key).unwrap();
+    let secret_2 = secret_2.upgrade(&new_key).unwrap();
+    println!(
+        "format version {}",
+        format_version(secret_2.encoded_secret()).unwrap()
+    );
+
+    let path = std::env::temp_dir().join("typed-string-encoding-secret_2");
+    secret_2.write_to(&path).unwrap();
+    let secret_2 = Secret::<Secret2, Encoded>::read_from(&path).unwrap();
+    let _ = fs::remove_file(&path);
+    let secret_2 = secret_2.decrypt(&new_key).unwrap();
+
+    let secret_2 = secret_2.encrypt_with_aad(&key, b"user-1").unwrap();
+    let secret_2 = secret_2.decrypt_with_aad(&key, b"user-1").unwrap();
+
+    println!("{:?}", secret_2.secret());
 }
 
 #[cfg(test)]
@@ -167,8 +561,8 @@ mod tests {
         let encoded_secret_2 = secret_2.encode();
 
         // Decode the secrets
-        let decoded_secret_1 = encoded_secret_1.decode();
-        let decoded_secret_2 = encoded_secret_2.decode();
+        let decoded_secret_1 = encoded_secret_1.decode().unwrap();
+        let decoded_secret_2 = encoded_secret_2.decode().unwrap();
 
         // Ensure the secrets match
         let secret_1 = Secret::new(Secret1("secret_1".into()));
@@ -177,4 +571,237 @@ mod tests {
         assert_eq!(decoded_secret_1.secret(), secret_1.secret());
         assert_eq!(decoded_secret_2.secret(), secret_2.secret());
     }
+
+    #[test]
+    fn test_secret_decode_as() {
+        let encoded_secret = Secret::new(Secret2(42)).encode();
+        let decoded_secret = encoded_secret.decode_as::<usize>().unwrap();
+        assert_eq!(decoded_secret.secret(), &42);
+
+        let encoded_secret = Secret::new(Secret1("secret_1".into())).encode();
+        assert!(encoded_secret.decode_as::<usize>().is_err());
+    }
+
+    #[cfg(feature = "postcard")]
+    #[test]
+    fn test_postcard_codec() {
+        let secret = Secret::new(Secret2(usize::MAX));
+
+        let json = serde_json::to_string(&secret.secret()).unwrap();
+        let encoded_secret = secret.encode_with::<PostcardCodec>().unwrap();
+        assert!(encoded_secret.encoded_secret().len() < json.len());
+
+        let decoded_secret = encoded_secret.decode_with::<PostcardCodec>().unwrap();
+        assert_eq!(decoded_secret.secret(), &Secret2(usize::MAX));
+
+        let encoded_secret: Secret<Secret2, Encoded> = Secret {
+            content: Encoded("not postcard!".into()),
+            _type: PhantomData,
+        };
+        assert!(encoded_secret.decode_with::<PostcardCodec>().is_err());
+    }
+
+    #[test]
+    fn test_secret_encrypt_rotate() {
+        let key = [1; 32];
+        let new_key = [2; 32];
+
+        let encrypted_secret = Secret::new(Secret1("secret_1".into()))
+            .encrypt(&key)
+            .unwrap();
+        assert!(!encrypted_secret.encoded_secret().contains("secret_1"));
+
+        let rotated_secret = encrypted_secret.rotate(&key, &new_key).unwrap();
+        let encoded = rotated_secret.encoded_secret().to_string();
+
+        let with_key: Secret<Secret1, Encoded> = Secret {
+            content: Encoded(encoded.clone()),
+            _type: PhantomData,
+        };
+        assert!(matches!(with_key.decrypt(&key), Err(Error::Crypto)));
+
+        let decrypted_secret = rotated_secret.decrypt(&new_key).unwrap();
+        assert_eq!(decrypted_secret.secret(), &Secret1("secret_1".into()));
+    }
+
+    #[test]
+    fn test_secret_write_read() {
+        let path =
+            std::env::temp_dir().join(format!("typed-string-encoding-test-{}", std::process::id()));
+
+        let key = [1; 32];
+        let encrypted_secret = Secret::new(Secret2(42)).encrypt(&key).unwrap();
+        encrypted_secret.write_to(&path).unwrap();
+
+        let read_secret = Secret::<Secret2, Encoded>::read_from(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            read_secret.encoded_secret(),
+            encrypted_secret.encoded_secret()
+        );
+
+        let decrypted_secret = read_secret.decrypt(&key).unwrap();
+        assert_eq!(decrypted_secret.secret(), &Secret2(42));
+
+        assert!(matches!(
+            Secret::<Secret2, Encoded>::read_from(&path),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_secret_frame_unframe() {
+        let mut blob = Secret::new(Secret1("secret_1".into()))
+            .encode()
+            .frame()
+            .unwrap();
+        blob.extend(Secret::new(Secret2(42)).encode().frame().unwrap());
+
+        let (secret_1, rest) = Secret::<Secret1, Encoded>::unframe(&blob).unwrap();
+        let (secret_2, rest) = Secret::<Secret2, Encoded>::unframe(rest).unwrap();
+        assert!(rest.is_empty());
+
+        assert_eq!(
+            secret_1.decode().unwrap().secret(),
+            &Secret1("secret_1".into())
+        );
+        assert_eq!(secret_2.decode().unwrap().secret(), &Secret2(42));
+
+        assert!(matches!(
+            Secret::<Secret1, Encoded>::unframe(&blob[..2]),
+            Err(Error::Frame(_))
+        ));
+        assert!(matches!(
+            Secret::<Secret1, Encoded>::unframe(&blob[..6]),
+            Err(Error::Frame(_))
+        ));
+    }
+
+    #[test]
+    fn test_secret_serde() {
+        #[derive(Debug, serde::Serialize, serde::Deserialize)]
+        struct Config {
+            secret: Secret<Secret2, Encoded>,
+        }
+
+        let config = Config {
+            secret: Secret::new(Secret2(42)).encode(),
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(json, r#"{"secret":"42"}"#);
+
+        let config: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(config.secret.decode().unwrap().secret(), &Secret2(42));
+    }
+
+    #[test]
+    fn test_secret_format_upgrade() {
+        let key = [1; 32];
+
+        let encrypted_secret = Secret::new(Secret2(42)).encrypt(&key).unwrap();
+        let encoded = encrypted_secret.encoded_secret().to_string();
+        assert!(encoded.starts_with('1'));
+        assert_eq!(format_version(&encoded).unwrap(), FORMAT_VERSION);
+
+        // Untagged and unpadded, as encrypted before format versions existed
+        let cipher = Aes256Gcm::new((&key).into());
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut bytes = nonce.to_vec();
+        bytes.extend(cipher.encrypt(&nonce, b"42".as_ref()).unwrap());
+        let legacy: Secret<Secret2, Encoded> = Secret {
+            content: Encoded(base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)),
+            _type: PhantomData,
+        };
+        assert_eq!(format_version(legacy.encoded_secret()).unwrap(), 0);
+
+        let upgraded = legacy.upgrade(&key).unwrap();
+        assert_eq!(format_version(upgraded.encoded_secret()).unwrap(), 1);
+        assert_eq!(upgraded.decrypt(&key).unwrap().secret(), &Secret2(42));
+
+        // Already current, left as is
+        let upgraded = encrypted_secret.upgrade(&key).unwrap();
+        assert_eq!(upgraded.encoded_secret(), encoded);
+
+        let future = format!("9{}", &encoded[1..]);
+        assert!(matches!(
+            format_version(&future),
+            Err(Error::UnsupportedFormat(9))
+        ));
+    }
+
+    #[test]
+    fn test_secret_aad() {
+        let key = [1; 32];
+
+        let encrypted_secret = Secret::new(Secret2(42))
+            .encrypt_with_aad(&key, b"user-1")
+            .unwrap();
+        let encoded = encrypted_secret.encoded_secret().to_string();
+
+        let decrypted_secret = encrypted_secret.decrypt_with_aad(&key, b"user-1").unwrap();
+        assert_eq!(decrypted_secret.secret(), &Secret2(42));
+
+        for aad in [b"user-2".as_ref(), b""] {
+            let encrypted_secret: Secret<Secret2, Encoded> = Secret {
+                content: Encoded(encoded.clone()),
+                _type: PhantomData,
+            };
+            assert!(matches!(
+                encrypted_secret.decrypt_with_aad(&key, aad),
+                Err(Error::Crypto)
+            ));
+        }
+    }
+
+    // Not `Clone`, the secret is only ever borrowed
+    #[derive(Debug, PartialEq)]
+    struct ApiKey(String);
+
+    impl SecretTrait for ApiKey {}
+
+    impl Deserialize for ApiKey {
+        fn deserialize(value: String) -> Result<Self> {
+            Ok(Self(value))
+        }
+    }
+    impl Serialize for ApiKey {
+        fn serialize(self) -> String {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_secret_without_clone() {
+        let encoded_secret = Secret::new(ApiKey("key_1".into())).encode();
+        assert_eq!(encoded_secret.encoded_secret(), "key_1");
+
+        let decoded_secret = encoded_secret.decode().unwrap();
+        assert_eq!(decoded_secret.secret(), &ApiKey("key_1".into()));
+    }
+
+    #[test]
+    fn test_secret_encoded_bytes() {
+        let encoded_secret = Secret::new(Secret2(42))
+            .encode_bytes_with::<PlainCodec>()
+            .unwrap();
+        assert_eq!(encoded_secret.encoded_bytes(), b"42");
+
+        let decoded_secret = encoded_secret.decode_bytes_with::<PlainCodec>().unwrap();
+        assert_eq!(decoded_secret.secret(), &Secret2(42));
+
+        let encoded_secret = Secret::<Secret2, EncodedBytes>::from_bytes(vec![0xff]);
+        assert!(encoded_secret.decode_bytes_with::<PlainCodec>().is_err());
+
+        #[cfg(feature = "postcard")]
+        {
+            let raw = postcard::to_allocvec(&Secret2(usize::MAX)).unwrap();
+            let encoded_secret = Secret::new(Secret2(usize::MAX))
+                .encode_bytes_with::<PostcardCodec>()
+                .unwrap();
+            assert_eq!(encoded_secret.encoded_bytes(), raw);
+
+            let decoded_secret = encoded_secret.decode_bytes_with::<PostcardCodec>().unwrap();
+            assert_eq!(decoded_secret.secret(), &Secret2(usize::MAX));
+        }
+    }
 }